@@ -0,0 +1,420 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use actix_web::{
+    HttpResponse, Responder, ResponseError, delete, get, http::header::ContentType, post, web,
+};
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    DATE_FORMAT, GenResult, cache, cluster,
+    collection::{PdfTimetableCollection, ShiftData, file_id, shift_name},
+    config::{ClusterRole, Config},
+    digest::{DigestChanges, compare_digests, compute_digests, load_stored_digests, store_digests},
+    error::HermesError,
+    get_timetable_files, parse_date, parse_trip_sheet_in_memory,
+    parsing::shift_parsing::load_text_document,
+    refresh, return_error, tenant,
+};
+
+#[derive(Serialize)]
+pub struct IndexStatus {
+    pub reindex_needed: bool,
+    pub changes: DigestChanges,
+    // Unix timestamp of the last successful index
+    pub last_indexed: Option<i64>,
+}
+
+pub fn get_index_status() -> GenResult<IndexStatus> {
+    let current_digests = compute_digests(&get_timetable_files()?)?;
+    let (reindex_needed, changes) = match load_stored_digests() {
+        Some(previous_digests) => {
+            let changes = compare_digests(&previous_digests, &current_digests);
+            (!changes.is_empty(), changes)
+        }
+        // Without stored digests everything is new
+        None => (
+            true,
+            DigestChanges {
+                added: current_digests.into_keys().collect(),
+                ..Default::default()
+            },
+        ),
+    };
+    Ok(IndexStatus {
+        reindex_needed,
+        changes,
+        last_indexed: cluster::last_index_time().map(|time| time.unix_timestamp()),
+    })
+}
+
+#[get("/status")]
+async fn index_status() -> impl Responder {
+    // Hashing all files can take a while, so don't do it on the worker thread
+    let tenant = tenant::current();
+    let status =
+        web::block(|| tenant::with_tenant(tenant, get_index_status).map_err(|err| err.to_string()))
+            .await;
+    match status {
+        Ok(Ok(status)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&status).unwrap()),
+        Ok(Err(err)) => return_error(err),
+        Err(err) => return_error(err.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RefreshQuery {
+    dry_run: Option<bool>,
+    // Index even when none of the source PDFs changed
+    force: Option<bool>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FileError {
+    pub file: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct DryRunReport {
+    pub changes: DigestChanges,
+    pub new_collections: Vec<String>,
+    pub new_shifts: Vec<String>,
+    pub removed_shifts: Vec<String>,
+    pub errored_shifts: Vec<String>,
+    pub file_errors: Vec<FileError>,
+}
+
+fn dated_shift_name(key: &str, shift_data: &ShiftData, valid_from: time::Date) -> String {
+    format!(
+        "{} ({})",
+        shift_name(key, shift_data),
+        valid_from.format(DATE_FORMAT).unwrap_or_default()
+    )
+}
+
+// Parse the added and modified files in memory and compare them with the loaded collections
+fn dry_run_refresh() -> GenResult<DryRunReport> {
+    let files = get_timetable_files()?;
+    let current_digests = compute_digests(&files)?;
+    let changes = match load_stored_digests() {
+        Some(previous_digests) => compare_digests(&previous_digests, &current_digests),
+        None => DigestChanges {
+            added: current_digests.keys().cloned().collect(),
+            ..Default::default()
+        },
+    };
+    let collections = PdfTimetableCollection::get_timetables()?;
+
+    // Shifts that come from files which are modified or removed, these could disappear
+    let replaced_files: Vec<&String> = changes.modified.iter().chain(&changes.removed).collect();
+    let mut old_shifts = BTreeSet::new();
+    let mut all_shifts = BTreeSet::new();
+    for collection in &collections {
+        for (key, shift_data) in &collection.pages {
+            let key = dated_shift_name(key, shift_data, collection.valid_from);
+            let from_replaced_file = collection
+                .files
+                .get(&shift_data.file_id)
+                .is_some_and(|path| replaced_files.contains(&path));
+            if from_replaced_file {
+                old_shifts.insert(key.clone());
+            }
+            all_shifts.insert(key);
+        }
+    }
+
+    let mut new_shifts = BTreeSet::new();
+    let mut new_collections = BTreeSet::new();
+    let mut errored_shifts = vec![];
+    let mut file_errors = vec![];
+    for file in &files {
+        let path = file.to_string_lossy().to_string();
+        if !changes.added.contains(&path) && !changes.modified.contains(&path) {
+            continue;
+        }
+        match parse_trip_sheet_in_memory(file, file_id(file)) {
+            Ok(parsed) => {
+                if !collections
+                    .iter()
+                    .any(|collection| collection.valid_from == parsed.valid_from)
+                {
+                    new_collections.insert(parsed.valid_from.format(DATE_FORMAT)?);
+                }
+                for (key, shift_data) in &parsed.shift_data_map {
+                    new_shifts.insert(dated_shift_name(key, shift_data, parsed.valid_from));
+                }
+                errored_shifts.extend(
+                    parsed
+                        .shifts
+                        .iter()
+                        .filter(|shift| shift.parse_error.is_some())
+                        .map(|shift| shift.shift_nr.clone()),
+                );
+            }
+            Err(err) => file_errors.push(FileError {
+                file: path,
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    Ok(DryRunReport {
+        new_collections: new_collections.into_iter().collect(),
+        removed_shifts: old_shifts.difference(&new_shifts).cloned().collect(),
+        new_shifts: new_shifts.difference(&all_shifts).cloned().collect(),
+        errored_shifts,
+        file_errors,
+        changes,
+    })
+}
+
+#[post("/refresh")]
+async fn admin_refresh(query: web::Query<RefreshQuery>) -> impl Responder {
+    let tenant = tenant::current();
+    if !query.dry_run.unwrap_or(false) {
+        if Config::get().role == ClusterRole::Replica {
+            return HermesError::ReadOnlyReplica.error_response();
+        }
+        // Indexing can take minutes, follow it with /admin/refresh/status
+        let mut response = if refresh::start_refresh(query.force.unwrap_or(false)) {
+            HttpResponse::Accepted()
+        } else {
+            HttpResponse::Conflict()
+        };
+        return response
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&refresh::current_progress()).unwrap());
+    }
+    let report =
+        web::block(|| tenant::with_tenant(tenant, dry_run_refresh).map_err(|err| err.to_string()))
+            .await;
+    match report {
+        Ok(Ok(report)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&report).unwrap()),
+        Ok(Err(err)) => return_error(err),
+        Err(err) => return_error(err.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RetireQuery {
+    // Also delete the source PDFs, otherwise a full refresh brings the timetable back
+    delete_sources: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct RetiredTimetable {
+    pub valid_from: String,
+    pub shifts: usize,
+    pub deleted_sources: Vec<String>,
+}
+
+// Remove the collection of a timetable and its extracted shifts, returns None if it doesn't exist
+fn retire_timetable(valid_from: Date, delete_sources: bool) -> GenResult<Option<RetiredTimetable>> {
+    let valid_from_string = valid_from.format(DATE_FORMAT)?;
+    let collection_file =
+        Path::new(&tenant::collection_path()).join(format!("{valid_from_string}.json"));
+    let collection: PdfTimetableCollection = match fs::read(&collection_file) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Box::new(err)),
+    };
+    fs::remove_file(&collection_file)?;
+    match fs::remove_dir_all(collection_file.with_extension("")) {
+        Ok(_) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => return Err(Box::new(err)),
+    }
+    let mut deleted_sources = vec![];
+    if delete_sources {
+        for source in collection.files.values() {
+            match fs::remove_file(source) {
+                Ok(_) => deleted_sources.push(source.clone()),
+                Err(err) => warn!("Failed to delete source PDF {source}: {err}"),
+            }
+        }
+        // Forget the deleted files, so the next refresh doesn't report them as removed
+        if let Some(mut digests) = load_stored_digests() {
+            digests.retain(|path, _| !deleted_sources.contains(path));
+            store_digests(&digests)?;
+        }
+    }
+    info!("Retired timetable {valid_from_string}");
+    PdfTimetableCollection::load_timetables_from_disk()?;
+    cluster::write_index_marker()?;
+    cache::collection_changed();
+    Ok(Some(RetiredTimetable {
+        valid_from: valid_from_string,
+        shifts: collection.pages.len(),
+        deleted_sources,
+    }))
+}
+
+#[delete("/timetable/{valid_from}")]
+async fn delete_timetable(
+    path: web::Path<String>,
+    query: web::Query<RetireQuery>,
+) -> impl Responder {
+    if Config::get().role == ClusterRole::Replica {
+        return HermesError::ReadOnlyReplica.error_response();
+    }
+    // A running refresh would write the collection again
+    if refresh::current_progress().running {
+        return HermesError::RefreshRunning.error_response();
+    }
+    let valid_from_string = path.into_inner();
+    let Some(valid_from) = parse_date(&valid_from_string) else {
+        return HermesError::DateParse(valid_from_string).error_response();
+    };
+    let delete_sources = query.delete_sources.unwrap_or(false);
+    let tenant = tenant::current();
+    let retired = web::block(move || {
+        tenant::with_tenant(tenant, || retire_timetable(valid_from, delete_sources))
+            .map_err(|err| err.to_string())
+    })
+    .await;
+    match retired {
+        Ok(Ok(Some(retired))) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&retired).unwrap()),
+        Ok(Ok(None)) => HermesError::TimetableNotFound(valid_from_string).error_response(),
+        Ok(Err(err)) => return_error(err),
+        Err(err) => return_error(err.to_string()),
+    }
+}
+
+// A reference of a collection that would fail when the shift is requested
+#[derive(Serialize)]
+pub struct BrokenReference {
+    pub timetable: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shift: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub problem: String,
+}
+
+#[derive(Serialize)]
+pub struct VerifyReport {
+    pub timetables: usize,
+    pub files: usize,
+    pub shifts: usize,
+    pub broken: Vec<BrokenReference>,
+}
+
+// Check that the source PDFs of the loaded collections exist and have the pages of the shifts,
+// and that every shift has been parsed
+fn verify_collections() -> GenResult<VerifyReport> {
+    let collections = PdfTimetableCollection::get_timetables()?;
+    let mut report = VerifyReport {
+        timetables: collections.len(),
+        files: 0,
+        shifts: 0,
+        broken: vec![],
+    };
+    for collection in &collections {
+        let timetable = collection.valid_from.format(DATE_FORMAT)?;
+        let broken =
+            |shift: Option<String>, file: Option<&String>, problem: String| BrokenReference {
+                timetable: timetable.clone(),
+                shift,
+                file: file.cloned(),
+                problem,
+            };
+        // The page numbers of every source PDF that could be read
+        let mut file_pages: HashMap<usize, BTreeSet<u32>> = HashMap::new();
+        for (file_id, path) in &collection.files {
+            report.files += 1;
+            if !Path::new(path).is_file() {
+                report.broken.push(broken(
+                    None,
+                    Some(path),
+                    "source PDF does not exist".to_string(),
+                ));
+                continue;
+            }
+            match load_text_document(&PathBuf::from(path)) {
+                Ok(document) => {
+                    file_pages.insert(*file_id, document.get_pages().into_keys().collect());
+                }
+                Err(err) => report.broken.push(broken(
+                    None,
+                    Some(path),
+                    format!("source PDF can't be read: {err}"),
+                )),
+            }
+        }
+        let entries = collection.shift_entries();
+        for (key, shift_data) in &entries {
+            report.shifts += 1;
+            let shift = Some(dated_shift_name(key, shift_data, collection.valid_from));
+            let file = collection.files.get(&shift_data.file_id);
+            if !collection.shifts.contains_key(key) {
+                report.broken.push(broken(
+                    shift.clone(),
+                    file,
+                    "parsed shift is missing".to_string(),
+                ));
+            }
+            let Some(file) = file else {
+                report.broken.push(broken(
+                    shift,
+                    None,
+                    format!("refers to unknown file id {}", shift_data.file_id),
+                ));
+                continue;
+            };
+            let Some(pages) = file_pages.get(&shift_data.file_id) else {
+                continue;
+            };
+            for page in &shift_data.pages {
+                if !pages.contains(page) {
+                    report.broken.push(broken(
+                        shift.clone(),
+                        Some(file),
+                        format!(
+                            "page {page} does not exist, the PDF has {} pages",
+                            pages.len()
+                        ),
+                    ));
+                }
+            }
+        }
+        // Parsed shifts that no longer have pages can't be requested
+        for key in collection.shifts.keys() {
+            if !entries.iter().any(|(entry_key, _)| entry_key == key) {
+                report.broken.push(broken(
+                    Some(key.clone()),
+                    None,
+                    "parsed shift has no pages".to_string(),
+                ));
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[get("/verify")]
+async fn verify() -> impl Responder {
+    // Every source PDF is loaded, so don't do it on the worker thread
+    let tenant = tenant::current();
+    let report = web::block(|| {
+        tenant::with_tenant(tenant, verify_collections).map_err(|err| err.to_string())
+    })
+    .await;
+    match report {
+        Ok(Ok(report)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&report).unwrap()),
+        Ok(Err(err)) => return_error(err),
+        Err(err) => return_error(err.to_string()),
+    }
+}
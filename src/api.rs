@@ -0,0 +1,55 @@
+// Version 1 of the JSON API. The bare routes keep working for existing clients, but changes to the
+// JSON format only go into a new version, so the deployed app keeps the responses it was built for
+use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+
+use crate::{
+    ShiftPath, ShiftQuery, index::handle_index_request, parse_optional_date, serve_shift,
+    statistics::handle_stats_request, timetables::timetables_response,
+};
+
+pub fn routes(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("/api/v1")
+            .route("/shift/{shift_number}", web::get().to(shift))
+            .route("/index", web::get().to(index))
+            .route("/statistics", web::get().to(statistics))
+            .route("/timetables", web::get().to(timetables)),
+    );
+}
+
+// The shift as JSON, any extension in the request is ignored
+async fn shift(
+    http_request: HttpRequest,
+    path: web::Path<ShiftPath>,
+    query: web::Query<ShiftQuery>,
+) -> HttpResponse {
+    let custom_date = match parse_optional_date(&query.date) {
+        Ok(date) => date,
+        Err(err) => return err.error_response(),
+    };
+    let request = path.into_inner().shift_number.to_uppercase();
+    let shift = request.split('.').next().unwrap_or_default();
+    serve_shift(&http_request, &format!("{shift}.JSON"), custom_date, &query).await
+}
+
+async fn index(query: web::Query<ShiftQuery>) -> HttpResponse {
+    match parse_optional_date(&query.date) {
+        Ok(date) => handle_index_request(date, &query.index_filters),
+        Err(err) => err.error_response(),
+    }
+}
+
+// Always JSON, the CSV export stays on the bare route
+async fn statistics(query: web::Query<ShiftQuery>) -> HttpResponse {
+    match parse_optional_date(&query.date) {
+        Ok(date) => handle_stats_request(date, None),
+        Err(err) => err.error_response(),
+    }
+}
+
+async fn timetables(query: web::Query<ShiftQuery>) -> HttpResponse {
+    match parse_optional_date(&query.date) {
+        Ok(date) => timetables_response(date).await,
+        Err(err) => err.error_response(),
+    }
+}
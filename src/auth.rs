@@ -0,0 +1,146 @@
+// The admin endpoints need the configured token, reading shifts stays public.
+// Without an admin_token in the config the admin endpoints are open like before.
+// The check is attached to the admin scopes, the path after decoding decides which routes need it
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    Error, ResponseError,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::header::{AUTHORIZATION, HeaderMap},
+};
+
+use crate::{config::Config, error::HermesError};
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+// The token from "Authorization: Bearer ..." or the API key header
+fn request_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|api_key| api_key.to_str().ok())
+        })
+        .map(str::trim)
+}
+
+// Blake3 hashes compare in constant time, so the token can't be guessed from the response time
+fn token_matches(given: &str, expected: &str) -> bool {
+    blake3::hash(given.as_bytes()) == blake3::hash(expected.as_bytes())
+}
+
+// Whether the request may use the admin endpoints, always true without an admin token
+pub fn is_admin(headers: &HeaderMap) -> bool {
+    Config::get()
+        .admin_token
+        .as_ref()
+        .is_none_or(|admin_token| {
+            request_token(headers).is_some_and(|token| token_matches(token, admin_token))
+        })
+}
+
+type AuthorizedResponse = Pin<Box<dyn Future<Output = Result<ServiceResponse<BoxBody>, Error>>>>;
+
+// Middleware for the admin scopes, refusing requests without the admin token
+pub fn require_admin_token<S, B>(request: ServiceRequest, service: &S) -> AuthorizedResponse
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    if !is_admin(request.headers()) {
+        warn!(
+            "Refused unauthenticated {} {}",
+            request.method(),
+            request.path()
+        );
+        return Box::pin(async move {
+            Ok(request.into_response(HermesError::Unauthorized.error_response()))
+        });
+    }
+    let response = service.call(request);
+    Box::pin(async move {
+        response
+            .await
+            .map(|response| response.map_into_boxed_body())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::StatusCode, test};
+
+    use super::*;
+    use crate::tenant;
+
+    const ADMIN_TOKEN: &str = "secret";
+
+    fn configure_admin_token() {
+        Config::set(Config {
+            admin_token: Some(ADMIN_TOKEN.to_string()),
+            tenants: vec![tenant::test_tenant("noord")],
+            ..Config::default()
+        });
+    }
+
+    async fn status(request: test::TestRequest) -> StatusCode {
+        let app = test::init_service(App::new().configure(crate::app_routes)).await;
+        test::call_service(&app, request.to_request())
+            .await
+            .status()
+    }
+
+    #[actix_web::test]
+    async fn encoded_admin_paths_need_the_token() {
+        configure_admin_token();
+        let requests = [
+            test::TestRequest::get().uri("/admin/status"),
+            test::TestRequest::get().uri("/%61dmin/status"),
+            test::TestRequest::get().uri("/%61%64%6D%69%6E/verify"),
+            test::TestRequest::post().uri("/%61dmin/refresh"),
+            test::TestRequest::post().uri("/%61dmin/upload"),
+            test::TestRequest::post().uri("/%61dmin/reload-config"),
+            test::TestRequest::get().uri("/op/noord/%61dmin/errors"),
+            test::TestRequest::get().uri("/shift/REFRESH"),
+            test::TestRequest::get().uri("/shift/%52EFRESH"),
+            test::TestRequest::get().uri("/shift/%72efresh"),
+            test::TestRequest::get().uri("/op/noord/shift/%52EFRESH"),
+        ];
+        for request in requests {
+            assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[actix_web::test]
+    async fn wrong_token_is_refused() {
+        configure_admin_token();
+        let request = test::TestRequest::get()
+            .uri("/%61dmin/refresh/status")
+            .insert_header((AUTHORIZATION, "Bearer wrong"));
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn admin_token_opens_the_admin_scope() {
+        configure_admin_token();
+        let bearer = test::TestRequest::get()
+            .uri("/%61dmin/refresh/status")
+            .insert_header((AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}")));
+        assert_ne!(status(bearer).await, StatusCode::UNAUTHORIZED);
+        let api_key = test::TestRequest::get()
+            .uri("/op/noord/admin/refresh/status")
+            .insert_header((API_KEY_HEADER, ADMIN_TOKEN));
+        assert_ne!(status(api_key).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn public_routes_stay_open() {
+        configure_admin_token();
+        let request = test::TestRequest::get().uri("/healthz");
+        assert_ne!(status(request).await, StatusCode::UNAUTHORIZED);
+    }
+}
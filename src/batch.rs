@@ -0,0 +1,141 @@
+// Several shifts in one request, so a roster app can build a week view without a request per shift
+use std::collections::BTreeMap;
+
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    DATE_FORMAT, GenResult, ambiguous_shift, collection::key_number, error::HermesError,
+    find_shift, holidays, index, load_shift, parse_optional_date, parsing::shift_structs::Shift,
+    tenant,
+};
+
+// More shifts than fit in a week of rosters is most likely a mistake
+const MAX_SHIFTS_PER_REQUEST: usize = 100;
+
+#[derive(Deserialize)]
+struct ShiftsQuery {
+    // Comma separated, like G1234,E2201,4015
+    numbers: String,
+    date: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ShiftResult {
+    Found {
+        timetable: String,
+        canonical_prefix: String,
+        shift: Box<Shift>,
+    },
+    Error {
+        error: String,
+        code: &'static str,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        suggestions: Vec<String>,
+    },
+}
+
+impl From<HermesError> for ShiftResult {
+    fn from(err: HermesError) -> Self {
+        let suggestions = match &err {
+            HermesError::ShiftNotFound { suggestions, .. } => suggestions.clone(),
+            HermesError::AmbiguousShift { candidates, .. } => candidates.clone(),
+            _ => vec![],
+        };
+        Self::Error {
+            error: err.to_string(),
+            code: err.code(),
+            suggestions,
+        }
+    }
+}
+
+// Same lookup as a single shift: the prefix has to match and with a date the variant
+// that runs on that day is served
+fn lookup_shift(request: &str, date: Option<Date>) -> Result<ShiftResult, HermesError> {
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    let lookup_date = date.unwrap_or(OffsetDateTime::now_utc().date());
+    let not_found = || HermesError::ShiftNotFound {
+        shift: request.to_string(),
+        suggestions: index::similar_shifts(request, date),
+    };
+    if let Some(err) = ambiguous_shift(&shift_prefix, &shift_number, lookup_date) {
+        return Err(err);
+    }
+    let (collection, shift_data, found_number) =
+        find_shift(&shift_prefix, &shift_number, lookup_date)?.ok_or_else(not_found)?;
+    let tenant = tenant::current();
+    if !shift_prefix.is_empty()
+        && !tenant.prefixes_equivalent(&shift_prefix, &shift_data.shift_prefix)
+    {
+        return Err(HermesError::IncorrectPrefix {
+            suggestion: format!(
+                "{}{}",
+                tenant.canonical_prefix(&shift_data.shift_prefix),
+                key_number(&found_number)
+            ),
+            prefix: shift_prefix,
+        });
+    }
+    let shift_name = date
+        .and_then(|date| collection.variant_on(&found_number, holidays::service_weekday(date)))
+        .map_or(found_number, |(variant_name, _)| variant_name);
+    Ok(ShiftResult::Found {
+        timetable: collection
+            .valid_from
+            .format(DATE_FORMAT)
+            .unwrap_or_default(),
+        canonical_prefix: tenant.canonical_prefix(&shift_data.shift_prefix),
+        shift: Box::new(load_shift(shift_name, collection.valid_from)?),
+    })
+}
+
+fn lookup_shifts(requests: Vec<String>, date: Option<Date>) -> GenResult<String> {
+    let shifts: BTreeMap<String, ShiftResult> = requests
+        .into_iter()
+        .map(|request| {
+            let result = lookup_shift(&request, date).unwrap_or_else(ShiftResult::from);
+            (request, result)
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&shifts)?)
+}
+
+// A shift that can't be served gets its error in the map, the other shifts are still returned
+#[get("/shifts")]
+async fn get_shifts(query: web::Query<ShiftsQuery>) -> impl Responder {
+    let date = match parse_optional_date(&query.date) {
+        Ok(date) => date,
+        Err(err) => return err.error_response(),
+    };
+    let requests: Vec<String> = query
+        .numbers
+        .split(',')
+        .map(|number| number.trim().to_uppercase())
+        .filter(|number| !number.is_empty())
+        .collect();
+    if requests.is_empty() {
+        return HermesError::BadRequest("No shift numbers given".to_string()).error_response();
+    }
+    if requests.len() > MAX_SHIFTS_PER_REQUEST {
+        return HermesError::BadRequest(format!(
+            "At most {MAX_SHIFTS_PER_REQUEST} shifts can be requested at once"
+        ))
+        .error_response();
+    }
+    let tenant = tenant::current();
+    let shifts_json = web::block(move || {
+        tenant::with_tenant(tenant, || lookup_shifts(requests, date)).map_err(|err| err.to_string())
+    })
+    .await;
+    match shifts_json {
+        Ok(Ok(json)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(json),
+        Ok(Err(err)) => HermesError::Internal(err).error_response(),
+        Err(err) => HermesError::Internal(err.to_string()).error_response(),
+    }
+}
@@ -0,0 +1,151 @@
+// Optional Redis layer, shared between instances for the assembled shift PDFs
+// and for telling other instances that the collection was reindexed.
+// Without the redis feature every function here is a no-op.
+use time::Date;
+
+use crate::config::Config;
+#[cfg(feature = "redis")]
+use crate::{DATE_FORMAT, GenResult, collection::PdfTimetableCollection, tenant};
+#[cfg(feature = "redis")]
+use redis::Commands;
+#[cfg(feature = "redis")]
+use std::sync::{LazyLock, Mutex};
+
+#[cfg(feature = "redis")]
+const PDF_KEY_PREFIX: &str = "hermes:pdf:";
+#[cfg(feature = "redis")]
+const COLLECTION_CHANGED_CHANNEL: &str = "hermes:collection_changed";
+#[cfg(feature = "redis")]
+const PDF_CACHE_TTL_SECONDS: u64 = 24 * 60 * 60;
+
+#[cfg(feature = "redis")]
+static REDIS_CONNECTION: LazyLock<Mutex<Option<redis::Connection>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+#[cfg(feature = "redis")]
+fn pdf_key(valid_from: Date, shift_number: &str) -> String {
+    format!(
+        "{PDF_KEY_PREFIX}{}:{}:{shift_number}",
+        tenant::current().name,
+        valid_from.format(DATE_FORMAT).unwrap_or_default()
+    )
+}
+
+// Run a command on the shared connection, reconnecting if there is no connection yet.
+// If the command fails the connection is dropped so the next call starts fresh
+#[cfg(feature = "redis")]
+fn with_connection<T>(command: impl FnOnce(&mut redis::Connection) -> GenResult<T>) -> Option<T> {
+    let redis_url = Config::get().redis_url.clone()?;
+    let mut connection_guard = REDIS_CONNECTION.lock().ok()?;
+    if connection_guard.is_none() {
+        match redis::Client::open(redis_url).and_then(|client| client.get_connection()) {
+            Ok(connection) => *connection_guard = Some(connection),
+            Err(err) => {
+                warn!("Could not connect to redis: {err}");
+                return None;
+            }
+        }
+    }
+    match command(connection_guard.as_mut()?) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("Redis command failed: {err}");
+            *connection_guard = None;
+            None
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub fn get_pdf(valid_from: Date, shift_number: &str) -> Option<Vec<u8>> {
+    with_connection(|connection| {
+        let bytes: Option<Vec<u8>> = connection.get(pdf_key(valid_from, shift_number))?;
+        Ok(bytes)
+    })
+    .flatten()
+}
+
+#[cfg(not(feature = "redis"))]
+pub fn get_pdf(_valid_from: Date, _shift_number: &str) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(feature = "redis")]
+pub fn store_pdf(valid_from: Date, shift_number: &str, bytes: &[u8]) {
+    with_connection(|connection| {
+        let _: () = connection.set_ex(
+            pdf_key(valid_from, shift_number),
+            bytes,
+            PDF_CACHE_TTL_SECONDS,
+        )?;
+        Ok(())
+    });
+}
+
+#[cfg(not(feature = "redis"))]
+pub fn store_pdf(_valid_from: Date, _shift_number: &str, _bytes: &[u8]) {}
+
+// Remove all cached PDFs and let the other instances know they should reload the collection
+#[cfg(feature = "redis")]
+pub fn collection_changed() {
+    with_connection(|connection| {
+        let keys: Vec<String> = connection
+            .scan_match::<_, String>(format!("{PDF_KEY_PREFIX}*"))?
+            .collect();
+        if !keys.is_empty() {
+            let _: () = connection.del(keys)?;
+        }
+        let _: () = connection.publish(COLLECTION_CHANGED_CHANNEL, std::process::id())?;
+        Ok(())
+    });
+}
+
+#[cfg(not(feature = "redis"))]
+pub fn collection_changed() {}
+
+// Reload the collection from disk whenever another instance reports it reindexed.
+// This blocks, so it is run on its own thread
+#[cfg(feature = "redis")]
+fn listen_for_changes(redis_url: String) -> GenResult<()> {
+    let client = redis::Client::open(redis_url)?;
+    let mut connection = client.get_connection()?;
+    let mut pubsub = connection.as_pubsub();
+    pubsub.subscribe(COLLECTION_CHANGED_CHANNEL)?;
+    loop {
+        let message = pubsub.get_message()?;
+        let sender: u32 = message.get_payload().unwrap_or_default();
+        if sender == std::process::id() {
+            continue;
+        }
+        info!("Collection changed on another instance, reloading");
+        for tenant in tenant::all() {
+            if let Err(err) =
+                tenant::with_tenant(tenant, PdfTimetableCollection::load_timetables_from_disk)
+            {
+                error!("Failed to reload collection: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub fn start_change_listener() {
+    let Some(redis_url) = Config::get().redis_url.clone() else {
+        return;
+    };
+    std::thread::spawn(move || {
+        loop {
+            if let Err(err) = listen_for_changes(redis_url.clone()) {
+                error!("Lost redis subscription: {err}");
+            }
+            std::thread::sleep(std::time::Duration::from_secs(5));
+        }
+    });
+}
+
+#[cfg(not(feature = "redis"))]
+pub fn start_change_listener() {
+    if Config::get().redis_url.is_some() {
+        warn!("redis_url is configured, but this build doesn't have the redis feature");
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration};
+
+use crate::{
+    DATE_FORMAT, GenResult, ambiguous_shift, coverage::date_range, error::HermesError, find_shift,
+    holidays, index, load_shift, parsing::shift_structs::ShiftValid, tenant,
+};
+
+#[derive(Deserialize)]
+struct CalendarPath {
+    shift_number: String,
+}
+
+#[derive(Deserialize)]
+struct CalendarQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ShiftCalendar {
+    shift: String,
+    from: String,
+    to: String,
+    dates: Vec<CalendarDay>,
+}
+
+#[derive(Serialize)]
+struct CalendarDay {
+    date: String,
+    weekday: String,
+    // Holidays run the Sunday variant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    holiday: Option<String>,
+    // Valid from date of the timetable the shift comes from on this date
+    timetable: String,
+    valid_on: ShiftValid,
+}
+
+// The dates in the range on which the shift runs. On every date the timetable is looked up again,
+// so the variant changes with the timetable when the range crosses a changeover.
+// None if the shift isn't in any timetable during the range
+fn shift_calendar(request: &str, from: Date, days: i64) -> GenResult<Option<Vec<CalendarDay>>> {
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    // The variant only has to be read once per timetable
+    let mut variants: HashMap<Date, ShiftValid> = HashMap::new();
+    let mut dates = vec![];
+    let mut found = false;
+    for day in 0..days {
+        let date = from + Duration::days(day);
+        let Some((collection, shift_data, found_number)) =
+            find_shift(&shift_prefix, &shift_number, date)?
+        else {
+            continue;
+        };
+        if !shift_prefix.is_empty()
+            && !tenant::current().prefixes_equivalent(&shift_prefix, &shift_data.shift_prefix)
+        {
+            continue;
+        }
+        found = true;
+        let valid_on = match variants.get(&collection.valid_from) {
+            Some(valid_on) => valid_on.clone(),
+            None => {
+                let valid_on = load_shift(found_number, collection.valid_from)?.valid_on;
+                variants.insert(collection.valid_from, valid_on.clone());
+                valid_on
+            }
+        };
+        if !ShiftValid::for_weekday(holidays::service_weekday(date)).contains(&valid_on) {
+            continue;
+        }
+        dates.push(CalendarDay {
+            date: date.format(DATE_FORMAT)?,
+            weekday: date.weekday().to_string(),
+            holiday: holidays::holiday(date),
+            timetable: collection.valid_from.format(DATE_FORMAT)?,
+            valid_on,
+        });
+    }
+    Ok(found.then_some(dates))
+}
+
+#[get("/shift/{shift_number}/calendar")]
+async fn calendar(
+    path: web::Path<CalendarPath>,
+    query: web::Query<CalendarQuery>,
+) -> impl Responder {
+    let request = path.into_inner().shift_number.to_uppercase();
+    let (from, days) = match date_range(&query.from, &query.to) {
+        Ok(range) => range,
+        Err(err) => return err.error_response(),
+    };
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    if let Some(err) = ambiguous_shift(&shift_prefix, &shift_number, from) {
+        return err.error_response();
+    }
+    let tenant = tenant::current();
+    let shift_request = request.clone();
+    let dates = web::block(move || {
+        tenant::with_tenant(tenant, || shift_calendar(&shift_request, from, days))
+            .map_err(|err| err.to_string())
+    })
+    .await;
+    let dates = match dates {
+        Ok(Ok(Some(dates))) => dates,
+        Ok(Ok(None)) => {
+            return HermesError::ShiftNotFound {
+                suggestions: index::similar_shifts(&request, Some(from)),
+                shift: request,
+            }
+            .error_response();
+        }
+        Ok(Err(err)) => return HermesError::Internal(err).error_response(),
+        Err(err) => return HermesError::Internal(err.to_string()).error_response(),
+    };
+    let calendar = ShiftCalendar {
+        shift: request,
+        from: from.format(DATE_FORMAT).unwrap_or_default(),
+        to: (from + Duration::days(days - 1))
+            .format(DATE_FORMAT)
+            .unwrap_or_default(),
+        dates,
+    };
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string_pretty(&calendar).unwrap())
+}
@@ -0,0 +1,52 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use time::OffsetDateTime;
+
+use crate::{GenResult, collection::PdfTimetableCollection, config::Config, health, tenant};
+
+// Written by the indexer after every successful index, replicas reload when it changes
+const INDEX_MARKER_NAME: &str = "index_generation";
+
+fn index_marker_path() -> PathBuf {
+    PathBuf::from(tenant::collection_path()).join(INDEX_MARKER_NAME)
+}
+
+pub fn write_index_marker() -> GenResult<()> {
+    let generation = OffsetDateTime::now_utc().unix_timestamp_nanos();
+    fs::write(index_marker_path(), generation.to_string())?;
+    Ok(())
+}
+
+fn read_index_marker() -> Option<String> {
+    fs::read_to_string(index_marker_path()).ok()
+}
+
+// When the last successful index finished
+pub fn last_index_time() -> Option<OffsetDateTime> {
+    let generation: i128 = read_index_marker()?.trim().parse().ok()?;
+    OffsetDateTime::from_unix_timestamp_nanos(generation).ok()
+}
+
+// Poll the shared collection directory and reload when the indexer wrote a new generation
+pub async fn watch_collection() {
+    let mut current_generation = read_index_marker();
+    loop {
+        let poll_interval = Duration::from_secs(Config::get().replica_poll_interval_seconds.max(1));
+        actix_web::rt::time::sleep(poll_interval).await;
+        let generation = read_index_marker();
+        if generation.is_none() || generation == current_generation {
+            continue;
+        }
+        info!(
+            "Indexer wrote a new collection for {}, reloading",
+            tenant::current().name
+        );
+        match PdfTimetableCollection::load_timetables_from_disk() {
+            Ok(_) => {
+                current_generation = generation;
+                health::clear_startup_errors();
+            }
+            Err(err) => error!("Failed to reload collection, retrying next poll: {err}"),
+        }
+    }
+}
@@ -1,51 +1,666 @@
 use std::{
     collections::HashMap,
     fs,
-    sync::{LazyLock, RwLock},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, LazyLock, RwLock},
 };
 
 use serde::{Deserialize, Serialize};
-use time::Date;
+use time::{Date, Weekday};
 
-use crate::{COLLECTION_PATH, GenResult};
+use crate::{
+    DATE_FORMAT, GenResult,
+    config::{ClusterRole, Config},
+    error::{HermesError, OptionResult},
+    parsing::shift_structs::{Shift, ShiftTimes, ShiftType, ShiftValid},
+    response_cache, sqlite, tenant,
+};
+
+// Loaded collections per tenant
+static ALL_TIMETABLE_COLLECTIONS: LazyLock<RwLock<HashMap<String, LoadedTimetables>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+struct LoadedTimetables {
+    // Sorted by valid from date, oldest first. Shared, so requests don't copy whole collections
+    collections: Vec<Arc<PdfTimetableCollection>>,
+    // For every shift number, the positions of the collections containing it with any prefix,
+    // oldest first
+    shift_locations: HashMap<String, Vec<usize>>,
+}
 
-static ALL_TIMETABLE_COLLECTIONS: LazyLock<RwLock<Vec<PdfTimetableCollection>>> =
-    LazyLock::new(|| RwLock::new(vec![]));
+impl LoadedTimetables {
+    fn new(collections: Vec<PdfTimetableCollection>) -> Self {
+        let mut shift_locations: HashMap<String, Vec<usize>> = HashMap::new();
+        for (position, collection) in collections.iter().enumerate() {
+            for key in collection.pages.keys() {
+                let positions = shift_locations.entry(key_number(key)).or_default();
+                if positions.last() != Some(&position) {
+                    positions.push(position);
+                }
+            }
+        }
+        Self {
+            collections: collections.into_iter().map(Arc::new).collect(),
+            shift_locations,
+        }
+    }
+}
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ShiftData {
     pub pages: Vec<u32>,
     pub file_id: usize,
     pub shift_prefix: String,
+    // Name of the depot folder the source PDF is in, None for the book folder itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depot: Option<String>,
+    // Copied from the parsed shift, so the index doesn't need to read every shift
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<ShiftTimes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shift_type: Option<ShiftType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_on: Option<ShiftValid>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PdfTimetableCollection {
     pub valid_from: Date,
     pub files: HashMap<usize, String>,
+    // Shifts by their key, see shift_key
     pub pages: HashMap<String, ShiftData>,
+    // Other variants of shift numbers that run on different days, like the Saturday version of a
+    // weekday shift. The JSON of a variant is stored under its variant key
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variants: HashMap<String, Vec<ShiftData>>,
+    // The parsed shifts, by the shift key or the variant key of their pages. Kept in the
+    // collection, so they are written, merged and removed together with the pages
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub shifts: HashMap<String, Arc<Shift>>,
+}
+
+// Shifts are stored under their prefix and number, like "G1234", so depots that use the same
+// numbers don't replace each other. Collections indexed before that use the bare number
+pub fn shift_key(shift_name: &str) -> String {
+    shift_name
+        .chars()
+        .filter(|character| character.is_alphanumeric())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// The number of a shift key or request, without the prefix
+pub fn key_number(key: &str) -> String {
+    key.chars()
+        .filter(|character| character.is_numeric())
+        .collect()
+}
+
+// The shift number with its prefix, the way drivers know it
+pub fn shift_name(key: &str, shift_data: &ShiftData) -> String {
+    format!("{}{}", shift_data.shift_prefix, key_number(key))
+}
+
+// Shift key with the days a variant runs on, like "G1234.saturday"
+pub fn variant_key(shift_number: &str, valid_on: &ShiftValid) -> String {
+    format!("{shift_number}.{}", valid_on.file_tag())
+}
+
+// Source PDFs are identified by a hash of their path, so adding or removing a file doesn't change
+// the ids of the others. Kept below 2^53, so JavaScript clients can read them exactly
+pub fn file_id(path: &Path) -> usize {
+    let canonical: Vec<String> = path
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    let hash = blake3::hash(canonical.join("/").as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash.as_bytes()[..8]);
+    (u64::from_le_bytes(bytes) & ((1 << 53) - 1)) as usize
+}
+
+// Collections indexed before the shifts were kept in the collection stored them in a folder next to
+// the collection file, named after the valid from date
+fn read_loose_shift(valid_from: Date, key: &str) -> GenResult<Shift> {
+    let path = PathBuf::from(tenant::collection_path())
+        .join(valid_from.format(DATE_FORMAT)?)
+        .join(format!("{key}.json"));
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+// Corrupt collection files are moved here, so they don't stop the other collections from loading
+const CORRUPT_FOLDER_NAME: &str = "corrupt";
+
+fn quarantine_collection(path: &Path) -> GenResult<()> {
+    let corrupt_folder = PathBuf::from(tenant::collection_path()).join(CORRUPT_FOLDER_NAME);
+    fs::create_dir_all(&corrupt_folder)?;
+    fs::rename(path, corrupt_folder.join(path.file_name().result()?))?;
+    Ok(())
 }
 
 impl PdfTimetableCollection {
-    pub fn load_timetables_from_disk() -> GenResult<()> {
-        let collections_on_disk = fs::read_dir(COLLECTION_PATH)?;
+    // Returns the names (valid from dates) of the collections which were corrupt and quarantined
+    pub fn load_timetables_from_disk() -> GenResult<Vec<String>> {
+        let collections_on_disk = fs::read_dir(tenant::collection_path())?;
         let mut collections: Vec<Self> = vec![];
+        let mut quarantined = vec![];
         for file_result in collections_on_disk {
             let file = file_result?;
-            if file.file_type()?.is_dir() {
+            // Skip the extracted shift folders and the index marker
+            if file.file_type()?.is_dir()
+                || file
+                    .path()
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    != Some("json")
+            {
                 continue;
             }
-            let collection_file: PdfTimetableCollection =
-                serde_json::from_slice(&fs::read(file.path())?)?;
-            collections.push(collection_file);
+            let collection_result: GenResult<PdfTimetableCollection> = fs::read(file.path())
+                .map_err(|err| err.into())
+                .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?));
+            match collection_result {
+                Ok(mut collection_file) => {
+                    // Replicas leave the shared collection alone, the indexer migrates it
+                    if collection_file.assign_stable_file_ids()
+                        && Config::get().role != ClusterRole::Replica
+                    {
+                        info!("Migrating {:?} to stable file ids", file.path());
+                        let written: GenResult<()> = serde_json::to_string_pretty(&collection_file)
+                            .map_err(|err| err.into())
+                            .and_then(|json| Ok(fs::write(file.path(), json)?));
+                        if let Err(err) = written {
+                            warn!("Failed to save the migrated {:?}: {err}", file.path());
+                        }
+                    }
+                    collection_file.load_loose_shifts();
+                    collections.push(collection_file);
+                }
+                Err(err) => {
+                    let err = HermesError::CollectionCorrupt {
+                        collection: file.path().to_string_lossy().to_string(),
+                        reason: err.to_string(),
+                    };
+                    error!("{err}, moving it to {CORRUPT_FOLDER_NAME}/");
+                    quarantine_collection(&file.path())?;
+                    if let Some(name) = file.path().file_stem() {
+                        quarantined.push(name.to_string_lossy().to_string());
+                    }
+                }
+            }
         }
         collections.sort_by_key(|key| key.valid_from);
-        *ALL_TIMETABLE_COLLECTIONS.try_write()? = collections;
+        sqlite::sync_collections(&collections);
+        ALL_TIMETABLE_COLLECTIONS
+            .write()?
+            .insert(tenant::current().name, LoadedTimetables::new(collections));
+        response_cache::clear();
+        Ok(quarantined)
+    }
+
+    // Paths of the collection files in a collection folder, skipping the shift folders and the index marker
+    fn collection_files(collection_path: &Path) -> GenResult<Vec<PathBuf>> {
+        let mut collection_files = vec![];
+        for file_result in fs::read_dir(collection_path)? {
+            let path = file_result?.path();
+            if path.extension().and_then(|extension| extension.to_str()) == Some("json") {
+                collection_files.push(path);
+            }
+        }
+        Ok(collection_files)
+    }
+
+    // Point the collections at the new location of source PDFs that were renamed or moved
+    pub fn rename_files(
+        collection_path: &Path,
+        renames: &HashMap<String, String>,
+    ) -> GenResult<()> {
+        for path in Self::collection_files(collection_path)? {
+            let mut collection: Self = serde_json::from_slice(&fs::read(&path)?)?;
+            let mut changed = false;
+            for file_path in collection.files.values_mut() {
+                if let Some(new_path) = renames.get(file_path) {
+                    *file_path = new_path.clone();
+                    changed = true;
+                }
+            }
+            if changed {
+                collection.assign_stable_file_ids();
+                fs::write(&path, serde_json::to_string_pretty(&collection)?)?;
+            }
+        }
         Ok(())
     }
 
-    pub fn get_timetables() -> GenResult<Vec<Self>> {
-        let collections = (*ALL_TIMETABLE_COLLECTIONS.read()?).to_vec();
+    // Drop the shifts that came from the source PDFs, and the collections that have no files left
+    pub fn remove_files(collection_path: &Path, removed_files: &[String]) -> GenResult<()> {
+        for path in Self::collection_files(collection_path)? {
+            let mut collection: Self = serde_json::from_slice(&fs::read(&path)?)?;
+            let removed_ids: Vec<usize> = collection
+                .files
+                .iter()
+                .filter(|(_, file)| removed_files.contains(file))
+                .map(|(file_id, _)| *file_id)
+                .collect();
+            if removed_ids.is_empty() {
+                continue;
+            }
+            let shift_folder = path.with_extension("");
+            if removed_ids.len() == collection.files.len() {
+                info!("Removing collection {path:?}, all of its files were removed");
+                fs::remove_file(&path)?;
+                match fs::remove_dir_all(&shift_folder) {
+                    Ok(_) => (),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                    Err(err) => return Err(Box::new(err)),
+                }
+                continue;
+            }
+            collection
+                .files
+                .retain(|file_id, _| !removed_ids.contains(file_id));
+            let mut removed_shifts = vec![];
+            collection.pages.retain(|shift_number, shift_data| {
+                let removed = removed_ids.contains(&shift_data.file_id);
+                if removed {
+                    removed_shifts.push(shift_number.clone());
+                }
+                !removed
+            });
+            for (shift_number, variants) in collection.variants.iter_mut() {
+                variants.retain(|variant| {
+                    let removed = removed_ids.contains(&variant.file_id);
+                    if removed {
+                        let valid_on = variant.valid_on.clone().unwrap_or(ShiftValid::Unknown);
+                        removed_shifts.push(variant_key(shift_number, &valid_on));
+                    }
+                    !removed
+                });
+            }
+            for shift_number in &removed_shifts {
+                collection.shifts.remove(shift_number);
+                // Collections indexed before the shifts were kept in the collection
+                match fs::remove_file(shift_folder.join(format!("{shift_number}.json"))) {
+                    Ok(_) => (),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                    Err(err) => return Err(Box::new(err)),
+                }
+            }
+            // A remaining variant takes the place of a removed shift with the same number
+            for shift_number in removed_shifts {
+                let Some(variants) = collection.variants.get_mut(&shift_number) else {
+                    continue;
+                };
+                if collection.pages.contains_key(&shift_number) || variants.is_empty() {
+                    continue;
+                }
+                let variant = variants.remove(0);
+                let valid_on = variant.valid_on.clone().unwrap_or(ShiftValid::Unknown);
+                let variant_name = variant_key(&shift_number, &valid_on);
+                if let Some(shift) = collection.shifts.remove(&variant_name) {
+                    collection.shifts.insert(shift_number.clone(), shift);
+                }
+                match fs::rename(
+                    shift_folder.join(format!("{variant_name}.json")),
+                    shift_folder.join(format!("{shift_number}.json")),
+                ) {
+                    Ok(_) => (),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                    Err(err) => return Err(Box::new(err)),
+                }
+                collection.pages.insert(shift_number, variant);
+            }
+            collection
+                .variants
+                .retain(|_, variants| !variants.is_empty());
+            fs::write(&path, serde_json::to_string_pretty(&collection)?)?;
+        }
+        Ok(())
+    }
+
+    // Collections indexed before the ids were derived from the paths numbered the files in the
+    // order they were found. Also used after renames. Returns whether any id changed
+    fn assign_stable_file_ids(&mut self) -> bool {
+        let renumbered: HashMap<usize, usize> = self
+            .files
+            .iter()
+            .map(|(old_id, path)| (*old_id, file_id(Path::new(path))))
+            .filter(|(old_id, new_id)| old_id != new_id)
+            .collect();
+        if renumbered.is_empty() {
+            return false;
+        }
+        self.files = self
+            .files
+            .drain()
+            .map(|(id, path)| (renumbered.get(&id).copied().unwrap_or(id), path))
+            .collect();
+        for shift_data in self
+            .pages
+            .values_mut()
+            .chain(self.variants.values_mut().flatten())
+        {
+            if let Some(new_id) = renumbered.get(&shift_data.file_id) {
+                shift_data.file_id = *new_id;
+            }
+        }
+        true
+    }
+
+    // Load collections without reading the collection folder
+    #[cfg(test)]
+    pub fn set_timetables(mut collections: Vec<Self>) {
+        collections.sort_by_key(|key| key.valid_from);
+        ALL_TIMETABLE_COLLECTIONS
+            .write()
+            .unwrap()
+            .insert(tenant::current().name, LoadedTimetables::new(collections));
+    }
+
+    pub fn get_timetables() -> GenResult<Vec<Arc<Self>>> {
+        let collections = ALL_TIMETABLE_COLLECTIONS
+            .read()?
+            .get(&tenant::current().name)
+            .map(|loaded| loaded.collections.clone())
+            .unwrap_or_default();
         Ok(collections)
     }
+
+    // The shifts and their variants, with the keys their parsed shifts are stored under
+    pub fn shift_entries(&self) -> Vec<(String, &ShiftData)> {
+        let variants = self.variants.iter().flat_map(|(key, variants)| {
+            variants.iter().map(move |variant| {
+                let valid_on = variant.valid_on.clone().unwrap_or(ShiftValid::Unknown);
+                (variant_key(key, &valid_on), variant)
+            })
+        });
+        self.pages
+            .iter()
+            .map(|(key, shift_data)| (key.clone(), shift_data))
+            .chain(variants)
+            .collect()
+    }
+
+    fn shift_keys(&self) -> Vec<String> {
+        self.shift_entries()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    // Add the shifts of an older collection that are still in their own files
+    fn load_loose_shifts(&mut self) {
+        for key in self.shift_keys() {
+            if self.shifts.contains_key(&key) {
+                continue;
+            }
+            if let Ok(shift) = read_loose_shift(self.valid_from, &key) {
+                self.shifts.insert(key, Arc::new(shift));
+            }
+        }
+    }
+
+    // The parsed shift of the loaded timetable valid from the date
+    pub fn load_shift(key: &str, valid_from: Date) -> GenResult<Shift> {
+        let all_timetables = ALL_TIMETABLE_COLLECTIONS.read()?;
+        let shift = all_timetables
+            .get(&tenant::current().name)
+            .and_then(|loaded| {
+                loaded
+                    .collections
+                    .iter()
+                    .find(|collection| collection.valid_from == valid_from)
+            })
+            .and_then(|collection| collection.shifts.get(key));
+        match shift {
+            Some(shift) => Ok(Shift::clone(shift)),
+            None => Err(format!(
+                "Shift {key} is not in the timetable valid from {}",
+                valid_from.format(DATE_FORMAT)?
+            )
+            .into()),
+        }
+    }
+
+    pub fn earliest_valid_from() -> GenResult<Option<Date>> {
+        Ok(ALL_TIMETABLE_COLLECTIONS
+            .read()?
+            .get(&tenant::current().name)
+            .and_then(|loaded| loaded.collections.first())
+            .map(|collection| collection.valid_from))
+    }
+
+    // The variant of a shift that runs on the weekday, with the name its JSON is stored under.
+    // None if the shift has no variant for that day
+    pub fn variant_on(&self, shift_number: &str, weekday: Weekday) -> Option<(String, ShiftData)> {
+        let primary = self.pages.get(shift_number)?;
+        let variants = self.variants.get(shift_number).into_iter().flatten();
+        std::iter::once((shift_number.to_string(), primary))
+            .chain(variants.map(|variant| {
+                let valid_on = variant.valid_on.clone().unwrap_or(ShiftValid::Unknown);
+                (variant_key(shift_number, &valid_on), variant)
+            }))
+            .find(|(_, shift_data)| {
+                shift_data
+                    .valid_on
+                    .as_ref()
+                    .is_none_or(|valid_on| valid_on.runs_on(weekday))
+            })
+            .map(|(name, shift_data)| (name, shift_data.clone()))
+    }
+
+    // The key of the shift with the number. When several depots use the number, the one with the
+    // prefix (or an equivalent one) is preferred, otherwise the first key is used
+    pub fn find_key(&self, shift_prefix: &str, shift_number: &str) -> Option<&String> {
+        let mut keys: Vec<&String> = self
+            .pages
+            .keys()
+            .filter(|key| key_number(key) == shift_number)
+            .collect();
+        keys.sort();
+        let tenant = tenant::current();
+        keys.iter()
+            .find(|key| {
+                !shift_prefix.is_empty()
+                    && tenant.prefixes_equivalent(shift_prefix, &self.pages[**key].shift_prefix)
+            })
+            .or(keys.first())
+            .copied()
+    }
+
+    // Whether the collection has the number with the prefix, any prefix matches when none is given
+    fn has_shift(&self, shift_prefix: &str, shift_number: &str) -> bool {
+        self.find_key(shift_prefix, shift_number)
+            .is_some_and(|key| {
+                shift_prefix.is_empty()
+                    || tenant::current()
+                        .prefixes_equivalent(shift_prefix, &self.pages[key].shift_prefix)
+            })
+    }
+
+    // The names of the shifts with the number in the collections valid on the date, once for every
+    // group of equivalent prefixes
+    pub fn shift_candidates(shift_number: &str, date: Date) -> GenResult<Vec<String>> {
+        let all_timetables = ALL_TIMETABLE_COLLECTIONS.read()?;
+        let Some(loaded) = all_timetables.get(&tenant::current().name) else {
+            return Ok(vec![]);
+        };
+        let tenant = tenant::current();
+        let mut candidates: Vec<String> = loaded
+            .shift_locations
+            .get(shift_number)
+            .into_iter()
+            .flatten()
+            .map(|position| &loaded.collections[*position])
+            .filter(|collection| collection.valid_from <= date)
+            .flat_map(|collection| {
+                collection
+                    .pages
+                    .iter()
+                    .filter(|(key, _)| key_number(key) == shift_number)
+                    .map(|(_, shift_data)| {
+                        format!(
+                            "{}{shift_number}",
+                            tenant.canonical_prefix(&shift_data.shift_prefix)
+                        )
+                    })
+            })
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok(candidates)
+    }
+
+    // Find the newest collection valid on the date that contains the shift.
+    // The prefix picks the depot, a collection with the number for another depot is only used when
+    // no collection has it for the requested one.
+    // The alias is asked per collection, because aliases can be limited to a single timetable.
+    // Also returns the key of the shift that was found
+    pub fn find_shift(
+        shift_prefix: &str,
+        shift_number: &str,
+        date: Date,
+        alias: impl Fn(Date) -> Option<String>,
+    ) -> GenResult<Option<(Arc<Self>, ShiftData, String)>> {
+        let all_timetables = ALL_TIMETABLE_COLLECTIONS.read()?;
+        let Some(loaded) = all_timetables.get(&tenant::current().name) else {
+            return Ok(None);
+        };
+        let collections = &loaded.collections;
+        // Newest first
+        let candidates: Vec<usize> = match sqlite::find_shift(shift_number, date) {
+            Some(valid_froms) => valid_froms
+                .iter()
+                .filter_map(|valid_from| {
+                    collections
+                        .iter()
+                        .position(|collection| collection.valid_from == *valid_from)
+                })
+                .collect(),
+            None => loaded
+                .shift_locations
+                .get(shift_number)
+                .into_iter()
+                .flatten()
+                .rev()
+                .filter(|position| collections[**position].valid_from <= date)
+                .copied()
+                .collect(),
+        };
+        let direct = candidates
+            .iter()
+            .find(|position| collections[**position].has_shift(shift_prefix, shift_number))
+            .or(candidates.first())
+            .copied();
+        // A newer collection could still contain the shift under an alias
+        let newer_collections = direct.map_or(0, |position| position + 1)..collections.len();
+        for collection in collections[newer_collections].iter().rev() {
+            if collection.valid_from > date {
+                continue;
+            }
+            if let Some(alias) = alias(collection.valid_from)
+                && let Some(key) = collection.find_key(shift_prefix, &alias)
+            {
+                let shift = collection.pages[key].clone();
+                return Ok(Some((collection.clone(), shift, key.clone())));
+            }
+        }
+        Ok(direct.and_then(|position| {
+            let collection = &collections[position];
+            let key = collection.find_key(shift_prefix, shift_number)?;
+            Some((
+                collection.clone(),
+                collection.pages[key].clone(),
+                key.clone(),
+            ))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+
+    fn collection(valid_from: Date, shifts: &[&str]) -> PdfTimetableCollection {
+        let pages = shifts
+            .iter()
+            .map(|shift| {
+                let shift_data = ShiftData {
+                    pages: vec![1],
+                    file_id: 0,
+                    shift_prefix: shift.chars().filter(|c| c.is_alphabetic()).collect(),
+                    depot: None,
+                    times: None,
+                    shift_type: None,
+                    valid_on: None,
+                };
+                (shift_key(shift), shift_data)
+            })
+            .collect();
+        PdfTimetableCollection {
+            valid_from,
+            files: HashMap::new(),
+            pages,
+            variants: HashMap::new(),
+            shifts: HashMap::new(),
+        }
+    }
+
+    fn found_in(shift_prefix: &str, shift_number: &str, date: Date) -> Option<(Date, String)> {
+        PdfTimetableCollection::find_shift(shift_prefix, shift_number, date, |_| None)
+            .unwrap()
+            .map(|(collection, _, key)| (collection.valid_from, key))
+    }
+
+    #[test]
+    fn find_shift_prefers_the_newest_valid_timetable() {
+        tenant::with_tenant(tenant::test_tenant("find_newest"), || {
+            // Loaded out of order, like the files in the collection folder
+            PdfTimetableCollection::set_timetables(vec![
+                collection(date!(2024 - 06 - 01), &["1234"]),
+                collection(date!(2024 - 01 - 01), &["1234", "5678"]),
+                collection(date!(2025 - 01 - 01), &["1234"]),
+            ]);
+            assert_eq!(
+                found_in("", "1234", date!(2024 - 07 - 01)),
+                Some((date!(2024 - 06 - 01), "1234".to_string()))
+            );
+            assert_eq!(
+                found_in("", "1234", date!(2025 - 02 - 01)),
+                Some((date!(2025 - 01 - 01), "1234".to_string()))
+            );
+            assert_eq!(
+                found_in("", "1234", date!(2024 - 02 - 01)),
+                Some((date!(2024 - 01 - 01), "1234".to_string()))
+            );
+            // Only the older timetable has the shift
+            assert_eq!(
+                found_in("", "5678", date!(2025 - 02 - 01)),
+                Some((date!(2024 - 01 - 01), "5678".to_string()))
+            );
+            assert_eq!(found_in("", "1234", date!(2023 - 12 - 31)), None);
+        });
+    }
+
+    #[test]
+    fn find_shift_prefers_the_requested_prefix() {
+        tenant::with_tenant(tenant::test_tenant("find_prefix"), || {
+            PdfTimetableCollection::set_timetables(vec![
+                collection(date!(2024 - 01 - 01), &["E1234", "G1234"]),
+                collection(date!(2024 - 06 - 01), &["E1234"]),
+            ]);
+            assert_eq!(
+                found_in("E", "1234", date!(2024 - 07 - 01)),
+                Some((date!(2024 - 06 - 01), "E1234".to_string()))
+            );
+            // The newest timetable doesn't have it for the depot, so the older one is used
+            assert_eq!(
+                found_in("G", "1234", date!(2024 - 07 - 01)),
+                Some((date!(2024 - 01 - 01), "G1234".to_string()))
+            );
+        });
+    }
 }
@@ -0,0 +1,93 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse,
+    http::header::{
+        ETAG, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LAST_MODIFIED, TryIntoHeaderValue,
+    },
+};
+
+use crate::{
+    DATE_FORMAT, cluster,
+    collection::{PdfTimetableCollection, ShiftData},
+    digest::load_stored_digests,
+};
+
+// ETag and Last-Modified of a shift response, so clients can skip downloading it again
+pub struct Validators {
+    etag: EntityTag,
+    last_modified: Option<HttpDate>,
+}
+
+impl Validators {
+    // The representation covers everything else that changes the body, like the extension and layout
+    pub fn for_shift(
+        collection: &PdfTimetableCollection,
+        shift_data: &ShiftData,
+        representation: &str,
+    ) -> Self {
+        let source_file = collection
+            .files
+            .get(&shift_data.file_id)
+            .cloned()
+            .unwrap_or_default();
+        // Fall back to the path when the digests are missing, the tag only has to change with the file
+        let source_digest = load_stored_digests()
+            .and_then(|digests| digests.get(&source_file).cloned())
+            .unwrap_or(source_file);
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(
+            collection
+                .valid_from
+                .format(DATE_FORMAT)
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+        hasher.update(source_digest.as_bytes());
+        for page in &shift_data.pages {
+            hasher.update(&page.to_le_bytes());
+        }
+        hasher.update(representation.as_bytes());
+        let tag = hasher.finalize().to_hex().to_string();
+        // The collection is rewritten on every index, the shift can't have changed after that
+        let last_modified = cluster::last_index_time().map(|time| {
+            HttpDate::from(SystemTime::from(time.replace_nanosecond(0).unwrap_or(time)))
+        });
+        Self {
+            etag: EntityTag::new_strong(tag),
+            last_modified,
+        }
+    }
+
+    // If-None-Match takes precedence, If-Modified-Since is only used without it
+    pub fn not_modified(&self, request: &HttpRequest) -> bool {
+        if let Some(if_none_match) = request.get_header::<IfNoneMatch>() {
+            return match if_none_match {
+                IfNoneMatch::Any => true,
+                IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(&self.etag)),
+            };
+        }
+        match (request.get_header::<IfModifiedSince>(), self.last_modified) {
+            (Some(IfModifiedSince(since)), Some(last_modified)) => last_modified <= since,
+            _ => false,
+        }
+    }
+
+    pub fn not_modified_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::NotModified().finish();
+        self.add_to(&mut response);
+        response
+    }
+
+    pub fn add_to(&self, response: &mut HttpResponse) {
+        let headers = response.headers_mut();
+        if let Ok(etag) = self.etag.clone().try_into_value() {
+            headers.insert(ETAG, etag);
+        }
+        if let Some(last_modified) = self.last_modified
+            && let Ok(last_modified) = last_modified.try_into_value()
+        {
+            headers.insert(LAST_MODIFIED, last_modified);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use actix_web::{HttpResponse, Responder, post};
+use serde::{Deserialize, Serialize};
+
+use time::Date;
+
+use crate::{
+    BOOK_PATH, COLLECTION_PATH, CONFIG_PATH, DEFAULT_BIND_ADDRESS, GenResult, parse_date,
+    response_cache, return_error,
+    tenant::{Depot, Tenant},
+};
+
+// Shared, so reading the configuration in a loop doesn't copy it every time
+static CONFIG: LazyLock<RwLock<Arc<Config>>> = LazyLock::new(|| RwLock::new(Arc::default()));
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusterRole {
+    // Indexes and serves on its own
+    #[default]
+    Standalone,
+    // Writes the (shared) collection directory
+    Indexer,
+    // Never indexes, reloads whenever the indexer finished
+    Replica,
+}
+
+// Old shift number that should be looked up as a new one, optionally only in one timetable
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ShiftAlias {
+    pub from: String,
+    pub to: String,
+    // Valid from date (dd-mm-yyyy or yyyy-mm-dd) of the timetable this alias applies to
+    pub timetable: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    // Source PDFs and extracted collection of the default operator
+    pub book_path: String,
+    pub collection_path: String,
+    // Folder for the digests and statistics history
+    pub state_path: String,
+    // Used when not started through systemd socket activation, requires a restart
+    pub bind_address: String,
+    // Log filter like "info" or "hermes_pdf_shift_http=debug", RUST_LOG takes precedence.
+    // Requires a restart
+    pub log_level: Option<String>,
+    // Write the logs as JSON lines with the fields of their spans, requires a restart
+    pub log_json: bool,
+    // Groups of shift prefixes which may be used interchangeably when requesting a shift.
+    // The first prefix of a group is the canonical one, which is returned in responses
+    pub prefix_equivalences: Vec<Vec<String>>,
+    // Shared cache and change notifications between instances, needs the redis feature
+    pub redis_url: Option<String>,
+    // SQLite copy of the collections used for lookups, needs the sqlite feature. Rebuilt on every load
+    pub sqlite_path: Option<String>,
+    // Write a JSON line for every request to stdout
+    pub access_log: bool,
+    // Changing the role requires a restart
+    pub role: ClusterRole,
+    // How often a replica checks if the indexer wrote a new collection
+    pub replica_poll_interval_seconds: u64,
+    // Maximum number of shift PDFs assembled at the same time, requires a restart
+    pub max_concurrent_pdf_operations: usize,
+    // Number of assembled shift PDFs kept in memory, 0 disables the cache
+    pub pdf_cache_entries: usize,
+    // Number of serialized index, statistics and other responses kept in memory. The dates and
+    // filters of the requests are part of the key, 0 disables the cache
+    pub response_cache_entries: usize,
+    // Refresh automatically when PDFs in the source folders change, requires a restart
+    pub watch_book_path: bool,
+    // How long the folder has to be quiet before refreshing, so copying many files only refreshes once
+    pub watch_debounce_seconds: u64,
+    // Rebuild corrupt collections from the source PDFs at startup, this parses every PDF
+    pub repair_corrupt_collections: bool,
+    pub shift_aliases: Vec<ShiftAlias>,
+    // Treat the public holidays of the Netherlands as Sundays when looking up shifts by date
+    pub dutch_holidays: bool,
+    // More dates (dd-mm-yyyy or yyyy-mm-dd) that run the Sunday timetable
+    pub extra_holidays: Vec<String>,
+    // Texts of a shift page at most this many points lower than the first text of a table row are
+    // part of that row, so a slightly different baseline doesn't split a job in two
+    pub row_tolerance: f32,
+    // Spellings of a location mapped to its canonical name, like "HStation" = "Hoofdstation"
+    pub location_aliases: HashMap<String, String>,
+    // Abbreviated words in location names and what they stand for, like "Stat." = "Station"
+    pub location_abbreviations: HashMap<String, String>,
+    // CSV file with the columns code,name,latitude,longitude. Location codes in the PDFs are
+    // replaced by the name and the jobs get the coordinates, which may be left empty
+    pub locations_path: Option<String>,
+    // CSV file with the columns location,latitude,longitude, used for the GeoJSON of a shift
+    pub location_coordinates_path: Option<String>,
+    // Folder with an extracted GTFS feed, driving jobs in the shift JSON are linked to its trips
+    pub gtfs_path: Option<String>,
+    // Required as bearer token or X-API-Key header on the admin endpoints, they are open without one
+    pub admin_token: Option<String>,
+    // URLs that get a POST with the new collection after every refresh
+    pub webhook_urls: Vec<String>,
+    // Extra operators, served under /op/{name}/. Adding one requires a restart
+    pub tenants: Vec<Tenant>,
+    // Refuse shift numbers without a prefix that several depots use with 300 Multiple Choices,
+    // instead of serving the shift of one of them
+    pub strict_shift_lookup: bool,
+    // Source folders of other depots of the default operator, their shifts are told apart from
+    // the ones in the book folder by their prefix
+    pub depots: Vec<Depot>,
+}
+
+// The location of the config file can be changed with HERMES_CONFIG
+fn config_path() -> String {
+    env::var("HERMES_CONFIG").unwrap_or(CONFIG_PATH.to_string())
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            book_path: BOOK_PATH.to_string(),
+            collection_path: COLLECTION_PATH.to_string(),
+            state_path: ".".to_string(),
+            bind_address: DEFAULT_BIND_ADDRESS.to_string(),
+            log_level: None,
+            log_json: false,
+            prefix_equivalences: vec![vec!["G".to_string(), "GM".to_string()]],
+            redis_url: None,
+            sqlite_path: None,
+            access_log: true,
+            role: ClusterRole::Standalone,
+            replica_poll_interval_seconds: 30,
+            max_concurrent_pdf_operations: 4,
+            pdf_cache_entries: 256,
+            response_cache_entries: 512,
+            watch_book_path: true,
+            watch_debounce_seconds: 10,
+            repair_corrupt_collections: false,
+            shift_aliases: vec![],
+            dutch_holidays: true,
+            extra_holidays: vec![],
+            row_tolerance: 1.0,
+            location_aliases: HashMap::new(),
+            location_abbreviations: HashMap::new(),
+            locations_path: None,
+            location_coordinates_path: None,
+            gtfs_path: None,
+            admin_token: None,
+            webhook_urls: vec![],
+            tenants: vec![],
+            strict_shift_lookup: false,
+            depots: vec![],
+        }
+    }
+}
+
+impl Config {
+    // Read the config file, if it doesn't exist the default configuration is used.
+    // Environment variables override the file
+    fn load_from_disk() -> GenResult<Self> {
+        let mut config = match fs::read_to_string(config_path()) {
+            Ok(config_string) => toml::from_str(&config_string)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(Box::new(err)),
+        };
+        config.apply_environment();
+        Ok(config)
+    }
+
+    fn apply_environment(&mut self) {
+        let overrides = [
+            ("HERMES_BOOK_PATH", &mut self.book_path),
+            ("HERMES_COLLECTION_PATH", &mut self.collection_path),
+            ("HERMES_STATE_PATH", &mut self.state_path),
+            ("HERMES_BIND_ADDRESS", &mut self.bind_address),
+        ];
+        for (variable, setting) in overrides {
+            if let Ok(value) = env::var(variable) {
+                *setting = value;
+            }
+        }
+        if let Ok(log_level) = env::var("HERMES_LOG_LEVEL") {
+            self.log_level = Some(log_level);
+        }
+        // Keeps the token out of the config file
+        if let Ok(admin_token) = env::var("HERMES_ADMIN_TOKEN") {
+            self.admin_token = Some(admin_token);
+        }
+    }
+
+    // Most settings are applied immediately. The location names only change on the next index
+    // and the cluster role is only read at startup
+    pub fn reload() -> GenResult<()> {
+        let config = Self::load_from_disk()?;
+        *CONFIG.write()? = Arc::new(config);
+        // Aliases could make previously missing shifts available
+        response_cache::clear();
+        info!("Loaded configuration from {}", config_path());
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn set(config: Config) {
+        *CONFIG.write().unwrap() = Arc::new(config);
+    }
+
+    pub fn get() -> Arc<Self> {
+        CONFIG
+            .read()
+            .map(|config| Arc::clone(&config))
+            .unwrap_or_default()
+    }
+
+    // Only the numeric part of the alias numbers is used, just like shift lookups
+    pub fn shift_alias(&self, shift_number: &str, timetable: Date) -> Option<String> {
+        let numeric =
+            |number: &str| -> String { number.chars().filter(|c| c.is_numeric()).collect() };
+        self.shift_aliases
+            .iter()
+            .filter(|alias| {
+                alias.timetable.as_ref().is_none_or(|alias_timetable| {
+                    parse_date(alias_timetable).is_some_and(|date| date == timetable)
+                })
+            })
+            .find(|alias| numeric(&alias.from) == shift_number)
+            .map(|alias| numeric(&alias.to))
+    }
+}
+
+pub fn prefixes_equivalent(equivalences: &[Vec<String>], requested: &str, actual: &str) -> bool {
+    requested == actual
+        || equivalences.iter().any(|group| {
+            group.iter().any(|prefix| prefix == requested)
+                && group.iter().any(|prefix| prefix == actual)
+        })
+}
+
+pub fn canonical_prefix(equivalences: &[Vec<String>], prefix: &str) -> String {
+    equivalences
+        .iter()
+        .find(|group| group.iter().any(|equivalent| equivalent == prefix))
+        .and_then(|group| group.first())
+        .map_or(prefix, |canonical| canonical.as_str())
+        .to_string()
+}
+
+// Reload the configuration every time the process receives a SIGHUP
+pub async fn reload_on_sighup() {
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(err) => {
+            error!("Could not listen for SIGHUP: {err}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("Got SIGHUP, reloading configuration");
+        if let Err(err) = Config::reload() {
+            error!("Failed to reload configuration: {err}");
+        }
+    }
+}
+
+#[post("/reload-config")]
+async fn reload_config() -> impl Responder {
+    match Config::reload() {
+        Ok(_) => HttpResponse::Ok().body("Configuration reloaded"),
+        Err(err) => return_error(err.to_string()),
+    }
+}
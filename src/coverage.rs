@@ -0,0 +1,83 @@
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::{
+    DATE_FORMAT, collection::PdfTimetableCollection, error::HermesError, holidays, parse_date,
+    parsing::shift_structs::ShiftValid, return_error,
+};
+
+// Longer ranges are refused, to keep the response a reasonable size
+const MAX_COVERAGE_DAYS: i64 = 366;
+const DEFAULT_COVERAGE_DAYS: i64 = 31;
+
+#[derive(Deserialize)]
+struct CoverageQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CoverageDay {
+    date: String,
+    weekday: String,
+    // Holidays run the Sunday variants
+    #[serde(skip_serializing_if = "Option::is_none")]
+    holiday: Option<String>,
+    // Valid from date of the timetable that applies, None if the date is not covered
+    timetable: Option<String>,
+    // Shift variants that run on this weekday
+    variants: Vec<ShiftValid>,
+}
+
+// The from date and number of days of a range, it starts today and covers a month by default
+pub fn date_range(from: &Option<String>, to: &Option<String>) -> Result<(Date, i64), HermesError> {
+    let parse = |date: &Option<String>| match date {
+        Some(date) => parse_date(date)
+            .ok_or(HermesError::DateParse(date.clone()))
+            .map(Some),
+        None => Ok(None),
+    };
+    let from = parse(from)?.unwrap_or(OffsetDateTime::now_utc().date());
+    let to = parse(to)?.unwrap_or(from + Duration::days(DEFAULT_COVERAGE_DAYS - 1));
+    let days = (to - from).whole_days() + 1;
+    if !(1..=MAX_COVERAGE_DAYS).contains(&days) {
+        return Err(HermesError::BadRequest(format!(
+            "The range should run forward and cover at most {MAX_COVERAGE_DAYS} days"
+        )));
+    }
+    Ok((from, days))
+}
+
+#[get("/coverage")]
+async fn coverage(query: web::Query<CoverageQuery>) -> impl Responder {
+    let (from, days) = match date_range(&query.from, &query.to) {
+        Ok(range) => range,
+        Err(err) => return err.error_response(),
+    };
+    let timetables = match PdfTimetableCollection::get_timetables() {
+        Ok(timetables) => timetables,
+        Err(err) => return return_error(err.to_string()),
+    };
+    let coverage: Vec<CoverageDay> = (0..days)
+        .map(|day| {
+            let date = from + Duration::days(day);
+            // Timetables are sorted by date, the newest one that already started applies
+            let timetable = timetables
+                .iter()
+                .rev()
+                .find(|timetable| timetable.valid_from <= date)
+                .and_then(|timetable| timetable.valid_from.format(DATE_FORMAT).ok());
+            CoverageDay {
+                date: date.format(DATE_FORMAT).unwrap_or_default(),
+                weekday: date.weekday().to_string(),
+                holiday: holidays::holiday(date),
+                timetable,
+                variants: ShiftValid::for_weekday(holidays::service_weekday(date)),
+            }
+        })
+        .collect();
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string_pretty(&coverage).unwrap())
+}
@@ -0,0 +1,523 @@
+use std::sync::Arc;
+
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use crate::{
+    DATE_FORMAT, GenResult,
+    collection::{self, PdfTimetableCollection},
+    error::HermesError,
+    find_shift,
+    gtfs::GtfsTripLink,
+    index, load_shift, parse_optional_date,
+    parsing::shift_structs::{Shift, ShiftJob},
+    references::ShiftReference,
+    render::TIME_FORMAT,
+    response_cache, tenant,
+};
+
+// Summary of how a shift differs from the same shift number in the previous timetable
+#[derive(Serialize, Debug, Clone)]
+pub struct ShiftChanges {
+    pub changed: bool,
+    pub previous_valid_from: Option<Date>,
+    pub changes: Vec<String>,
+}
+
+// The shift is either the stored Shift or its English version
+#[derive(Serialize)]
+pub struct ShiftResponse<S: Serialize = Shift> {
+    #[serde(flatten)]
+    pub shift: S,
+    #[serde(flatten)]
+    pub changes: ShiftChanges,
+    // Prefix of the group of equivalent prefixes the shift's prefix belongs to
+    pub canonical_prefix: String,
+    // The requested shift number, when it was an alias of this shift
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aliased_from: Option<String>,
+    // Only filled when a GTFS feed is configured
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gtfs_trips: Vec<GtfsTripLink>,
+    // Shifts mentioned in the messages of the jobs
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<ShiftReference>,
+}
+
+fn format_time(time: Option<Time>) -> String {
+    match time {
+        Some(time) => format!("{:02}:{:02}", time.hour(), time.minute()),
+        None => "-".to_string(),
+    }
+}
+
+// Create a list of human readable differences between two versions of a shift
+pub fn compare_shifts(previous: &Shift, current: &Shift) -> Vec<String> {
+    let mut changes = vec![];
+    if previous.valid_on != current.valid_on {
+        changes.push(format!(
+            "valid on {:?} -> {:?}",
+            previous.valid_on, current.valid_on
+        ));
+    }
+    if previous.location != current.location {
+        changes.push(format!(
+            "location {} -> {}",
+            previous.location, current.location
+        ));
+    }
+    if previous.start_time() != current.start_time() {
+        changes.push(format!(
+            "start {} -> {}",
+            format_time(previous.start_time()),
+            format_time(current.start_time())
+        ));
+    }
+    if previous.end_time() != current.end_time() {
+        changes.push(format!(
+            "end {} -> {}",
+            format_time(previous.end_time()),
+            format_time(current.end_time())
+        ));
+    }
+    if previous.job.len() != current.job.len() {
+        changes.push(format!(
+            "jobs {} -> {}",
+            previous.job.len(),
+            current.job.len()
+        ));
+    } else {
+        let changed_jobs = previous
+            .job
+            .iter()
+            .zip(current.job.iter())
+            .filter(|(previous_job, current_job)| previous_job != current_job)
+            .count();
+        if changed_jobs > 0 {
+            changes.push(format!("{changed_jobs} job(s) changed"));
+        }
+    }
+    changes
+}
+
+// Find the newest collection older than the current one which also contains this shift, and compare both versions
+pub fn changes_since_previous(
+    shift_number: &str,
+    current_collection: &PdfTimetableCollection,
+    current_shift: &Shift,
+) -> ShiftChanges {
+    let timetables = PdfTimetableCollection::get_timetables().unwrap_or_default();
+    let previous_shift = timetables
+        .iter()
+        .rev()
+        .filter(|timetable| timetable.valid_from < current_collection.valid_from)
+        .find(|timetable| timetable.pages.contains_key(shift_number))
+        .and_then(|timetable| {
+            load_shift(shift_number.to_string(), timetable.valid_from)
+                .ok()
+                .map(|shift| (timetable.valid_from, shift))
+        });
+    match previous_shift {
+        Some((previous_valid_from, previous)) => {
+            let changes = compare_shifts(&previous, current_shift);
+            ShiftChanges {
+                changed: !changes.is_empty(),
+                previous_valid_from: Some(previous_valid_from),
+                changes,
+            }
+        }
+        None => ShiftChanges {
+            changed: false,
+            previous_valid_from: None,
+            changes: vec![],
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct TimetableDiffQuery {
+    // The timetables valid on these dates are compared
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ModifiedShift {
+    shift: String,
+    changes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct TimetableDiff {
+    from_valid_from: String,
+    to_valid_from: String,
+    added_count: usize,
+    removed_count: usize,
+    modified_count: usize,
+    unchanged_count: usize,
+    added: Vec<String>,
+    removed: Vec<String>,
+    modified: Vec<ModifiedShift>,
+}
+
+#[derive(Deserialize)]
+struct DiffPath {
+    shift_number: String,
+}
+
+#[derive(Deserialize)]
+struct DiffQuery {
+    // The timetables valid on these dates are compared
+    from_date: Option<String>,
+    to_date: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FieldChange {
+    field: &'static str,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PositionedJob {
+    // Index in the jobs of the shift it comes from
+    position: usize,
+    job: ShiftJob,
+}
+
+// A job that is in both versions, but with other times or locations
+#[derive(Serialize)]
+struct JobChange {
+    from_position: usize,
+    to_position: usize,
+    job: ShiftJob,
+    changes: Vec<FieldChange>,
+}
+
+#[derive(Serialize)]
+struct ShiftDiff {
+    shift: String,
+    from_valid_from: String,
+    to_valid_from: String,
+    changed: bool,
+    changes: Vec<FieldChange>,
+    added_jobs: Vec<PositionedJob>,
+    removed_jobs: Vec<PositionedJob>,
+    changed_jobs: Vec<JobChange>,
+}
+
+fn time_string(time: Option<Time>) -> Option<String> {
+    time.and_then(|time| time.format(TIME_FORMAT).ok())
+}
+
+fn field_change<T: PartialEq>(
+    field: &'static str,
+    from: T,
+    to: T,
+    display: impl Fn(T) -> Option<String>,
+) -> Option<FieldChange> {
+    (from != to).then(|| FieldChange {
+        field,
+        from: display(from),
+        to: display(to),
+    })
+}
+
+fn job_changes(from: &ShiftJob, to: &ShiftJob) -> Vec<FieldChange> {
+    [
+        field_change("start", from.start, to.start, time_string),
+        field_change("end", from.end, to.end, time_string),
+        field_change(
+            "start_location",
+            &from.start_location,
+            &to.start_location,
+            Clone::clone,
+        ),
+        field_change(
+            "end_location",
+            &from.end_location,
+            &to.end_location,
+            Clone::clone,
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+// Jobs are the same job when they do the same thing on the same omloop and rit,
+// so a job that only moved in time shows up as changed instead of removed and added
+fn same_job(from: &ShiftJob, to: &ShiftJob) -> bool {
+    from.job_type == to.job_type && from.omloop == to.omloop && from.rit == to.rit
+}
+
+// Pairs of positions of the jobs in both versions, as the longest common subsequence of same jobs
+fn match_jobs(from: &[ShiftJob], to: &[ShiftJob]) -> Vec<(usize, usize)> {
+    let mut lengths = vec![vec![0usize; to.len() + 1]; from.len() + 1];
+    for from_index in (0..from.len()).rev() {
+        for to_index in (0..to.len()).rev() {
+            lengths[from_index][to_index] = if same_job(&from[from_index], &to[to_index]) {
+                lengths[from_index + 1][to_index + 1] + 1
+            } else {
+                lengths[from_index + 1][to_index].max(lengths[from_index][to_index + 1])
+            };
+        }
+    }
+    let mut pairs = vec![];
+    let (mut from_index, mut to_index) = (0, 0);
+    while from_index < from.len() && to_index < to.len() {
+        if same_job(&from[from_index], &to[to_index]) {
+            pairs.push((from_index, to_index));
+            from_index += 1;
+            to_index += 1;
+        } else if lengths[from_index + 1][to_index] >= lengths[from_index][to_index + 1] {
+            from_index += 1;
+        } else {
+            to_index += 1;
+        }
+    }
+    pairs
+}
+
+fn diff_shifts(shift: String, from: (Date, Shift), to: (Date, Shift)) -> GenResult<ShiftDiff> {
+    let (from_valid_from, from) = from;
+    let (to_valid_from, to) = to;
+    let changes: Vec<FieldChange> = [
+        field_change("valid_on", &from.valid_on, &to.valid_on, |valid_on| {
+            Some(format!("{valid_on:?}"))
+        }),
+        field_change("location", &from.location, &to.location, |location| {
+            Some(location.clone())
+        }),
+        field_change("start", from.start_time(), to.start_time(), time_string),
+        field_change("end", from.end_time(), to.end_time(), time_string),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let from_jobs: Vec<ShiftJob> = from.job.into_iter().filter(|job| !job.empty()).collect();
+    let to_jobs: Vec<ShiftJob> = to.job.into_iter().filter(|job| !job.empty()).collect();
+    let pairs = match_jobs(&from_jobs, &to_jobs);
+    let changed_jobs: Vec<JobChange> = pairs
+        .iter()
+        .filter_map(|&(from_position, to_position)| {
+            let changes = job_changes(&from_jobs[from_position], &to_jobs[to_position]);
+            (!changes.is_empty()).then(|| JobChange {
+                from_position,
+                to_position,
+                job: to_jobs[to_position].clone(),
+                changes,
+            })
+        })
+        .collect();
+    let unmatched = |jobs: Vec<ShiftJob>, matched: Vec<usize>| -> Vec<PositionedJob> {
+        jobs.into_iter()
+            .enumerate()
+            .filter(|(position, _)| !matched.contains(position))
+            .map(|(position, job)| PositionedJob { position, job })
+            .collect()
+    };
+    let removed_jobs = unmatched(from_jobs, pairs.iter().map(|(from, _)| *from).collect());
+    let added_jobs = unmatched(to_jobs, pairs.iter().map(|(_, to)| *to).collect());
+    Ok(ShiftDiff {
+        shift,
+        from_valid_from: from_valid_from.format(DATE_FORMAT)?,
+        to_valid_from: to_valid_from.format(DATE_FORMAT)?,
+        changed: !changes.is_empty()
+            || !added_jobs.is_empty()
+            || !removed_jobs.is_empty()
+            || !changed_jobs.is_empty(),
+        changes,
+        added_jobs,
+        removed_jobs,
+        changed_jobs,
+    })
+}
+
+// The shift from the timetable valid on the date, None when no timetable there contains it
+fn shift_on(
+    shift_prefix: &str,
+    shift_number: &str,
+    date: Date,
+) -> GenResult<Option<(Date, Shift)>> {
+    let Some((collection, _, found_number)) = find_shift(shift_prefix, shift_number, date)? else {
+        return Ok(None);
+    };
+    Ok(Some((
+        collection.valid_from,
+        load_shift(found_number, collection.valid_from)?,
+    )))
+}
+
+// Without a from date the shift is compared with the timetable before the one of the to date
+fn shift_diff(
+    request: String,
+    from_date: Option<Date>,
+    to_date: Date,
+) -> GenResult<Option<ShiftDiff>> {
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    let Some(to) = shift_on(&shift_prefix, &shift_number, to_date)? else {
+        return Ok(None);
+    };
+    let from_date = from_date.unwrap_or(to.0 - Duration::days(1));
+    let Some(from) = shift_on(&shift_prefix, &shift_number, from_date)? else {
+        return Ok(None);
+    };
+    Ok(Some(diff_shifts(request, from, to)?))
+}
+
+// The newest timetable that is valid on the date
+fn timetable_on(
+    timetables: &[Arc<PdfTimetableCollection>],
+    date: Date,
+) -> Option<&Arc<PdfTimetableCollection>> {
+    timetables
+        .iter()
+        .filter(|timetable| timetable.valid_from <= date)
+        .max_by_key(|timetable| timetable.valid_from)
+}
+
+// Shift number with its prefix, the way drivers know it
+fn shift_name(collection: &PdfTimetableCollection, key: &str) -> String {
+    match collection.pages.get(key) {
+        Some(shift_data) => collection::shift_name(key, shift_data),
+        None => key.to_string(),
+    }
+}
+
+// Without a from date the timetable is compared with the one before it.
+// None if no timetable is valid on one of the dates
+fn timetable_diff(from_date: Option<Date>, to_date: Date) -> GenResult<Option<TimetableDiff>> {
+    let timetables = PdfTimetableCollection::get_timetables()?;
+    let Some(to) = timetable_on(&timetables, to_date) else {
+        return Ok(None);
+    };
+    let from_date = from_date.unwrap_or(to.valid_from - Duration::days(1));
+    let Some(from) = timetable_on(&timetables, from_date) else {
+        return Ok(None);
+    };
+    let mut added: Vec<String> = to
+        .pages
+        .keys()
+        .filter(|shift_number| !from.pages.contains_key(*shift_number))
+        .map(|shift_number| shift_name(to, shift_number))
+        .collect();
+    let mut removed: Vec<String> = from
+        .pages
+        .keys()
+        .filter(|shift_number| !to.pages.contains_key(*shift_number))
+        .map(|shift_number| shift_name(from, shift_number))
+        .collect();
+    let mut modified = vec![];
+    let mut unchanged_count = 0;
+    for shift_number in to.pages.keys() {
+        if !from.pages.contains_key(shift_number) {
+            continue;
+        }
+        let changes = compare_shifts(
+            &load_shift(shift_number.clone(), from.valid_from)?,
+            &load_shift(shift_number.clone(), to.valid_from)?,
+        );
+        if changes.is_empty() {
+            unchanged_count += 1;
+        } else {
+            modified.push(ModifiedShift {
+                shift: shift_name(to, shift_number),
+                changes,
+            });
+        }
+    }
+    added.sort();
+    removed.sort();
+    modified.sort_by(|first, second| first.shift.cmp(&second.shift));
+    Ok(Some(TimetableDiff {
+        from_valid_from: from.valid_from.format(DATE_FORMAT)?,
+        to_valid_from: to.valid_from.format(DATE_FORMAT)?,
+        added_count: added.len(),
+        removed_count: removed.len(),
+        modified_count: modified.len(),
+        unchanged_count,
+        added,
+        removed,
+        modified,
+    }))
+}
+
+// Registered before the shift diff, which would otherwise take "timetables" as a shift number
+#[get("/diff/timetables")]
+async fn diff_timetables(query: web::Query<TimetableDiffQuery>) -> impl Responder {
+    let (from_date, to_date) = match (
+        parse_optional_date(&query.from),
+        parse_optional_date(&query.to),
+    ) {
+        (Ok(from_date), Ok(to_date)) => (from_date, to_date),
+        (Err(err), _) | (_, Err(err)) => return err.error_response(),
+    };
+    let to_date = to_date.unwrap_or(OffsetDateTime::now_utc().date());
+    let tenant = tenant::current();
+    // Every shift of both timetables is read, so the report is kept until the next reload
+    let report = web::block(move || {
+        tenant::with_tenant(tenant, || {
+            let filters = from_date.map(|date| date.to_string()).unwrap_or_default();
+            response_cache::cached(
+                response_cache::cache_key("timetable_diff", Some(to_date), &filters),
+                || match timetable_diff(from_date, to_date)? {
+                    Some(report) => Ok(serde_json::to_string_pretty(&report)?),
+                    None => Err(HermesError::NoTimetable {
+                        date: from_date.unwrap_or(to_date).format(DATE_FORMAT)?,
+                        explanation: "There is nothing to compare".to_string(),
+                    }
+                    .into()),
+                },
+            )
+            // A missing timetable keeps its own error, anything else went wrong while comparing
+            .map_err(|err| match err.downcast::<HermesError>() {
+                Ok(err) => *err,
+                Err(err) => HermesError::from(err),
+            })
+        })
+    })
+    .await;
+    match report {
+        Ok(Ok(json)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(json),
+        Ok(Err(err)) => err.error_response(),
+        Err(err) => HermesError::Internal(err.to_string()).error_response(),
+    }
+}
+
+#[get("/diff/{shift_number}")]
+async fn diff(path: web::Path<DiffPath>, query: web::Query<DiffQuery>) -> impl Responder {
+    let request = path.into_inner().shift_number.to_uppercase();
+    let (from_date, to_date) = match (
+        parse_optional_date(&query.from_date),
+        parse_optional_date(&query.to_date),
+    ) {
+        (Ok(from_date), Ok(to_date)) => (from_date, to_date),
+        (Err(err), _) | (_, Err(err)) => return err.error_response(),
+    };
+    let to_date = to_date.unwrap_or(OffsetDateTime::now_utc().date());
+    let tenant = tenant::current();
+    let shift_request = request.clone();
+    let diff = web::block(move || {
+        tenant::with_tenant(tenant, || shift_diff(shift_request, from_date, to_date))
+            .map_err(|err| err.to_string())
+    })
+    .await;
+    match diff {
+        Ok(Ok(Some(diff))) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&diff).unwrap()),
+        Ok(Ok(None)) => HermesError::ShiftNotFound {
+            suggestions: index::similar_shifts(&request, Some(to_date)),
+            shift: request,
+        }
+        .error_response(),
+        Ok(Err(err)) => HermesError::Internal(err).error_response(),
+        Err(err) => HermesError::Internal(err.to_string()).error_response(),
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    path::PathBuf,
+};
+
+use serde::Serialize;
+
+use crate::{DIGEST_PATH, GenResult, tenant};
+
+// Source PDF path -> blake3 digest of its contents
+pub type FileDigests = BTreeMap<String, String>;
+
+pub fn compute_digests(files: &[PathBuf]) -> GenResult<FileDigests> {
+    let mut digests = FileDigests::new();
+    for file in files {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(File::open(file)?)?;
+        digests.insert(
+            file.to_string_lossy().to_string(),
+            hasher.finalize().to_hex().to_string(),
+        );
+    }
+    Ok(digests)
+}
+
+pub fn load_stored_digests() -> Option<FileDigests> {
+    serde_json::from_slice(&fs::read(tenant::state_path(DIGEST_PATH)).ok()?).ok()
+}
+
+pub fn store_digests(digests: &FileDigests) -> GenResult<()> {
+    fs::write(
+        tenant::state_path(DIGEST_PATH),
+        serde_json::to_string_pretty(digests)?,
+    )?;
+    Ok(())
+}
+
+// Digest over the contents of all files, so renaming or moving a file doesn't change it
+pub fn combined_digest(digests: &FileDigests) -> String {
+    let mut file_digests: Vec<&String> = digests.values().collect();
+    file_digests.sort();
+    let mut hasher = blake3::Hasher::new();
+    for digest in file_digests {
+        hasher.update(digest.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+// Find files that only moved: old path -> new path, matched by their digest
+pub fn renamed_files(previous: &FileDigests, current: &FileDigests) -> HashMap<String, String> {
+    let previous_by_digest: HashMap<&String, &String> = previous
+        .iter()
+        .map(|(path, digest)| (digest, path))
+        .collect();
+    current
+        .iter()
+        .filter(|(path, _)| !previous.contains_key(*path))
+        .filter_map(|(path, digest)| {
+            previous_by_digest
+                .get(digest)
+                .map(|old_path| (old_path.to_string(), path.to_string()))
+        })
+        .collect()
+}
+
+#[derive(Serialize, Default)]
+pub struct DigestChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+    pub renamed: HashMap<String, String>,
+}
+
+impl DigestChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.renamed.is_empty()
+    }
+}
+
+pub fn compare_digests(previous: &FileDigests, current: &FileDigests) -> DigestChanges {
+    let renamed = renamed_files(previous, current);
+    let added = current
+        .keys()
+        .filter(|path| !previous.contains_key(*path) && !renamed.values().any(|new| new == *path))
+        .cloned()
+        .collect();
+    let removed = previous
+        .keys()
+        .filter(|path| !current.contains_key(*path) && !renamed.contains_key(*path))
+        .cloned()
+        .collect();
+    let modified = current
+        .iter()
+        .filter(|(path, digest)| previous.get(*path).is_some_and(|old| old != *digest))
+        .map(|(path, _)| path.clone())
+        .collect();
+    DigestChanges {
+        added,
+        removed,
+        modified,
+        renamed,
+    }
+}
@@ -0,0 +1,215 @@
+// English versions of the shift structs for integrators that don't read Dutch. The stored JSON
+// keeps the Dutch names, these are only built when a client asks for English with ?lang=en or
+// its Accept-Language
+use actix_web::{HttpRequest, http::header::ACCEPT_LANGUAGE};
+use serde::Serialize;
+use time::{Date, Time};
+
+use crate::parsing::shift_structs::{
+    Coordinates, JobDrivingType, JobMessageType, JobType, Shift, ShiftJob, ShiftParseError,
+    ShiftTimes, ShiftType, ShiftValid,
+};
+
+#[derive(Serialize)]
+pub enum EnglishShiftType {
+    Early,
+    Middle,
+    Day,
+    Split {
+        start_break: Option<Time>,
+        end_break: Option<Time>,
+    },
+    Late,
+}
+
+#[derive(Serialize)]
+pub enum EnglishDrivingType {
+    Line(u32),
+    // Driving without passengers, between the depot and the start of a line
+    Deadhead,
+}
+
+#[derive(Serialize)]
+pub enum EnglishMessageType {
+    TakeAlong { shift_numbers: Vec<u32> },
+    Passenger { shift_number: u32, block: String },
+    BusOnLine { line: u32 },
+    TakeBus { bus_type: String },
+    Other(String),
+}
+
+#[derive(Serialize)]
+pub enum EnglishJobType {
+    Driving { drive_type: EnglishDrivingType },
+    Break,
+    Interruption,
+    Relief,
+    PrepareVehicle,
+    ParkAndSignOff,
+    Message { message: EnglishMessageType },
+    Walk,
+    Standby,
+    Unknown,
+}
+
+#[derive(Serialize)]
+pub struct EnglishShiftJob {
+    pub job_type: EnglishJobType,
+    pub start: Option<Time>,
+    pub end: Option<Time>,
+    pub start_next_day: bool,
+    pub end_next_day: bool,
+    pub duration_minutes: Option<i64>,
+    pub start_location: Option<String>,
+    pub end_location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_location_raw: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_location_raw: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_coordinates: Option<Coordinates>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_coordinates: Option<Coordinates>,
+    // Omloop, the vehicle schedule the job is part of
+    pub block: Option<usize>,
+    // Rit
+    pub trip: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remarks: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct EnglishShift {
+    pub shift_number: String,
+    pub valid_on: ShiftValid,
+    pub location: String,
+    pub shift_type: Option<EnglishShiftType>,
+    pub jobs: Vec<EnglishShiftJob>,
+    pub starting_date: Date,
+    pub parse_errors: Option<Vec<ShiftParseError>>,
+    pub times: ShiftTimes,
+}
+
+impl From<ShiftType> for EnglishShiftType {
+    fn from(shift_type: ShiftType) -> Self {
+        match shift_type {
+            ShiftType::Vroeg => Self::Early,
+            ShiftType::Tussen => Self::Middle,
+            ShiftType::Dag => Self::Day,
+            ShiftType::Gebroken {
+                start_break,
+                end_break,
+            } => Self::Split {
+                start_break,
+                end_break,
+            },
+            ShiftType::Laat => Self::Late,
+        }
+    }
+}
+
+impl From<JobDrivingType> for EnglishDrivingType {
+    fn from(drive_type: JobDrivingType) -> Self {
+        match drive_type {
+            JobDrivingType::Lijn(line) => Self::Line(line),
+            JobDrivingType::Mat => Self::Deadhead,
+        }
+    }
+}
+
+impl From<JobMessageType> for EnglishMessageType {
+    fn from(message: JobMessageType) -> Self {
+        match message {
+            JobMessageType::Meenemen { dienstnummers } => Self::TakeAlong {
+                shift_numbers: dienstnummers,
+            },
+            JobMessageType::Passagieren {
+                dienstnummer,
+                omloop,
+            } => Self::Passenger {
+                shift_number: dienstnummer,
+                block: omloop,
+            },
+            JobMessageType::BusOp { lijn } => Self::BusOnLine { line: lijn },
+            JobMessageType::NeemBus { bustype } => Self::TakeBus { bus_type: bustype },
+            JobMessageType::Other(message) => Self::Other(message),
+        }
+    }
+}
+
+impl From<JobType> for EnglishJobType {
+    fn from(job_type: JobType) -> Self {
+        match job_type {
+            JobType::Rijden { drive_type } => Self::Driving {
+                drive_type: drive_type.into(),
+            },
+            JobType::Pauze => Self::Break,
+            JobType::Onderbreking => Self::Interruption,
+            JobType::OpAfstap => Self::Relief,
+            JobType::RijklaarMaken => Self::PrepareVehicle,
+            JobType::StallenAfmelden => Self::ParkAndSignOff,
+            JobType::Melding { message } => Self::Message {
+                message: message.into(),
+            },
+            JobType::LoopReis => Self::Walk,
+            JobType::Reserve => Self::Standby,
+            JobType::Unknown => Self::Unknown,
+        }
+    }
+}
+
+impl From<ShiftJob> for EnglishShiftJob {
+    fn from(job: ShiftJob) -> Self {
+        Self {
+            job_type: job.job_type.into(),
+            start: job.start,
+            end: job.end,
+            start_next_day: job.start_next_day,
+            end_next_day: job.end_next_day,
+            duration_minutes: job.duration_minutes,
+            start_location: job.start_location,
+            end_location: job.end_location,
+            start_location_raw: job.start_location_raw,
+            end_location_raw: job.end_location_raw,
+            start_coordinates: job.start_coordinates,
+            end_coordinates: job.end_coordinates,
+            block: job.omloop,
+            trip: job.rit,
+            remarks: job.remarks,
+        }
+    }
+}
+
+impl From<Shift> for EnglishShift {
+    fn from(shift: Shift) -> Self {
+        Self {
+            shift_number: shift.shift_nr,
+            valid_on: shift.valid_on,
+            location: shift.location,
+            shift_type: shift.shift_type.map(Into::into),
+            jobs: shift.job.into_iter().map(Into::into).collect(),
+            starting_date: shift.starting_date,
+            parse_errors: shift.parse_error,
+            times: shift.times,
+        }
+    }
+}
+
+// The lang query parameter wins, otherwise the first language of the Accept-Language header
+pub fn wants_english(request: &HttpRequest, lang: Option<&str>) -> bool {
+    let language = match lang {
+        Some(lang) => lang.to_string(),
+        None => request
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|accept| accept.to_str().ok())
+            .and_then(|accept| accept.split([',', ';']).next())
+            .unwrap_or_default()
+            .to_string(),
+    };
+    language
+        .trim()
+        .split('-')
+        .next()
+        .is_some_and(|language| language.eq_ignore_ascii_case("en"))
+}
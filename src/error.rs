@@ -1,3 +1,17 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    Error as ActixError, HttpResponse, ResponseError,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::{
+        StatusCode,
+        header::{ACCEPT, ContentType, VARY, WWW_AUTHENTICATE},
+    },
+};
+use serde::Serialize;
+use thiserror::Error;
+
 use crate::GenResult;
 
 #[allow(dead_code)]
@@ -20,3 +34,219 @@ impl<T> OptionResult<T> for Option<T> {
         }
     }
 }
+
+// Errors that are shown to clients, each maps to its own status code
+#[derive(Error, Debug)]
+pub enum HermesError {
+    #[error("Sorry, shift {shift} was not found")]
+    ShiftNotFound {
+        shift: String,
+        // Existing shifts with a similar number, for when a digit was mistyped
+        suggestions: Vec<String>,
+    },
+    #[error("Shift {shift} exists for several depots, add the prefix of one of them")]
+    AmbiguousShift {
+        shift: String,
+        // The shift names the client can choose from
+        candidates: Vec<String>,
+    },
+    #[error("Sorry, no timetable is valid on {date}. {explanation}")]
+    NoTimetable { date: String, explanation: String },
+    #[error("There is no timetable valid from {0}")]
+    TimetableNotFound(String),
+    #[error("Timetable {timetable} has no source PDF with id {file_id}")]
+    SourceNotFound { timetable: String, file_id: usize },
+    #[error("\"{0}\" is not a valid date, use dd-mm-yyyy or yyyy-mm-dd")]
+    DateParse(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error(
+        "Incorrect shift type specified, remove \"{prefix}\" or change the request to \"{suggestion}\""
+    )]
+    IncorrectPrefix { prefix: String, suggestion: String },
+    #[error("Unknown operator {0}")]
+    UnknownOperator(String),
+    #[error("This endpoint requires the admin token")]
+    Unauthorized,
+    #[error("This instance is a read-only replica, refresh the indexer instead")]
+    ReadOnlyReplica,
+    #[error("Indexing is already running")]
+    RefreshRunning,
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("Failed to load the shift PDF: {0}")]
+    PdfLoad(String),
+    #[error("Collection {collection} is corrupt: {reason}")]
+    CollectionCorrupt { collection: String, reason: String },
+    #[error("Sorry, something went wrong loading that shift. error: {0}")]
+    Internal(String),
+}
+
+#[derive(Serialize, Clone)]
+struct ErrorBody {
+    error: String,
+    code: &'static str,
+    // The requested shift, when the error happened while looking one up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shift: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    suggestions: Vec<String>,
+}
+
+impl HermesError {
+    // Stable identifier, so clients don't have to match on the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ShiftNotFound { .. } => "shift_not_found",
+            Self::AmbiguousShift { .. } => "ambiguous_shift",
+            Self::NoTimetable { .. } => "no_timetable",
+            Self::TimetableNotFound(_) => "timetable_not_found",
+            Self::SourceNotFound { .. } => "source_not_found",
+            Self::DateParse(_) => "invalid_date",
+            Self::BadRequest(_) => "bad_request",
+            Self::IncorrectPrefix { .. } => "incorrect_prefix",
+            Self::UnknownOperator(_) => "unknown_operator",
+            Self::Unauthorized => "unauthorized",
+            Self::ReadOnlyReplica => "read_only_replica",
+            Self::RefreshRunning => "refresh_running",
+            Self::Unsupported(_) => "unsupported",
+            Self::PdfLoad(_) => "pdf_load_failed",
+            Self::CollectionCorrupt { .. } => "collection_corrupt",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for HermesError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        Self::Internal(err.to_string())
+    }
+}
+
+impl ResponseError for HermesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ShiftNotFound { .. }
+            | Self::NoTimetable { .. }
+            | Self::TimetableNotFound(_)
+            | Self::SourceNotFound { .. }
+            | Self::UnknownOperator(_) => StatusCode::NOT_FOUND,
+            Self::AmbiguousShift { .. } => StatusCode::MULTIPLE_CHOICES,
+            Self::DateParse(_) | Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::IncorrectPrefix { .. } => StatusCode::NOT_ACCEPTABLE,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::ReadOnlyReplica => StatusCode::FORBIDDEN,
+            Self::RefreshRunning => StatusCode::CONFLICT,
+            Self::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            Self::PdfLoad(_) | Self::CollectionCorrupt { .. } | Self::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = ErrorBody {
+            error: self.to_string(),
+            code: self.code(),
+            shift: None,
+            suggestions: match self {
+                Self::ShiftNotFound { suggestions, .. } => suggestions.clone(),
+                Self::AmbiguousShift { candidates, .. } => candidates.clone(),
+                _ => vec![],
+            },
+        };
+        let mut builder = HttpResponse::build(self.status_code());
+        if let Self::Unauthorized = self {
+            builder.insert_header((WWW_AUTHENTICATE, "Bearer"));
+        }
+        let mut response = builder
+            .content_type(ContentType::json())
+            .body(serde_json::to_string(&body).unwrap());
+        // Kept so negotiate_errors can render it again in the format the client asked for
+        response.extensions_mut().insert(body);
+        response
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Browsers get HTML, unless they asked for one of the JSON formats through the extension or the
+// versioned API. The HTML pages always get HTML errors
+fn wants_html(request: &ServiceRequest) -> bool {
+    let path = request.path().to_uppercase();
+    if path.contains("/API/V") {
+        return false;
+    }
+    if path.ends_with(".HTML") {
+        return true;
+    }
+    let json_extension = [".JSON", ".GEOJSON", ".NDJSON"]
+        .iter()
+        .any(|extension| path.ends_with(extension));
+    let accepts_html = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    accepts_html && !json_extension
+}
+
+type NegotiatedResponse = Pin<Box<dyn Future<Output = Result<ServiceResponse, ActixError>>>>;
+
+// Middleware rendering HermesError responses as HTML for browsers and as JSON for everyone else
+pub fn negotiate_errors<S, B>(request: ServiceRequest, service: &S) -> NegotiatedResponse
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let html = wants_html(&request);
+    let response = service.call(request);
+    Box::pin(async move {
+        let response = response.await?.map_into_boxed_body();
+        let Some(mut body) = response.response().extensions().get::<ErrorBody>().cloned() else {
+            return Ok(response);
+        };
+        body.shift = response
+            .request()
+            .match_info()
+            .get("shift_number")
+            .and_then(|shift| shift.split('.').next())
+            .map(|shift| shift.to_uppercase());
+        let mut rendered = HttpResponse::build(response.status());
+        // The body depends on the Accept header, so caches have to keep the versions apart
+        rendered.insert_header((VARY, "Accept"));
+        if let Some(authenticate) = response.headers().get(WWW_AUTHENTICATE) {
+            rendered.insert_header((WWW_AUTHENTICATE, authenticate.clone()));
+        }
+        let rendered: HttpResponse<BoxBody> = if html {
+            let shift = body
+                .shift
+                .as_ref()
+                .map(|shift| format!("<br>Shift: {}", escape_html(shift)))
+                .unwrap_or_default();
+            let suggestions = if body.suggestions.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "<br>Did you mean: {}",
+                    escape_html(&body.suggestions.join(", "))
+                )
+            };
+            rendered.content_type(ContentType::html()).body(format!(
+                "<h1>{}</h1>{shift}{suggestions}",
+                escape_html(&body.error)
+            ))
+        } else {
+            rendered
+                .content_type(ContentType::json())
+                .body(serde_json::to_string(&body).unwrap())
+        };
+        Ok(response.into_response(rendered))
+    })
+}
@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, iter, sync::Arc};
+
+use actix_web::{
+    HttpResponse, Responder, ResponseError, get, http::header::ContentType, web, web::Bytes,
+};
+use futures_util::stream;
+use serde::Deserialize;
+use time::Date;
+use tracing::warn;
+
+use crate::{
+    GenResult, collection::PdfTimetableCollection, get_valid_timetables, gtfs_export,
+    parse_optional_date, parsing::shift_structs::Shift, return_error,
+};
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    date: Option<String>,
+    timetable: Option<String>,
+}
+
+// The shifts to export, either of one named timetable or of all timetables active on the date.
+// When a shift occurs in multiple active timetables, the newest one wins, same as the index
+fn export_shifts(date: Option<Date>, timetable: Option<Date>) -> GenResult<Vec<Arc<Shift>>> {
+    let timetables = match timetable {
+        Some(valid_from) => PdfTimetableCollection::get_timetables()?
+            .into_iter()
+            .filter(|collection| collection.valid_from == valid_from)
+            .collect(),
+        None => get_valid_timetables(date)?.0,
+    };
+    let mut shifts: BTreeMap<String, Arc<Shift>> = BTreeMap::new();
+    for collection in timetables {
+        for shift_number in collection.pages.keys() {
+            if let Some(shift) = collection.shifts.get(shift_number) {
+                shifts.insert(shift_number.clone(), shift.clone());
+            }
+        }
+    }
+    Ok(shifts.into_values().collect())
+}
+
+// A single shift as compact JSON, shifts that can't be serialized are left out of the export
+fn compact_shift(shift: &Shift) -> Option<String> {
+    match serde_json::to_string(shift) {
+        Ok(json) => Some(json),
+        Err(err) => {
+            warn!("Skipping {} in export: {err}", shift.shift_nr);
+            None
+        }
+    }
+}
+
+fn query_shifts(query: &ExportQuery) -> Result<Vec<Arc<Shift>>, HttpResponse> {
+    let (date, timetable) = match (
+        parse_optional_date(&query.date),
+        parse_optional_date(&query.timetable),
+    ) {
+        (Ok(date), Ok(timetable)) => (date, timetable),
+        (Err(err), _) | (_, Err(err)) => return Err(err.error_response()),
+    };
+    export_shifts(date, timetable).map_err(|err| return_error(err.to_string()))
+}
+
+#[get("/export/shifts.ndjson")]
+async fn export_shifts_ndjson(query: web::Query<ExportQuery>) -> impl Responder {
+    let shifts = match query_shifts(&query) {
+        Ok(shifts) => shifts,
+        Err(response) => return response,
+    };
+    // Shifts are only serialized while the body is sent, so the whole export is never held in memory
+    let lines = stream::iter(shifts.into_iter().filter_map(|shift| {
+        compact_shift(&shift).map(|line| Ok::<Bytes, actix_web::Error>(Bytes::from(line + "\n")))
+    }));
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines)
+}
+
+// The same shifts as one JSON array, streamed just like the NDJSON export
+#[get("/export/shifts.json")]
+async fn export_shifts_json(query: web::Query<ExportQuery>) -> impl Responder {
+    let shifts = match query_shifts(&query) {
+        Ok(shifts) => shifts,
+        Err(response) => return response,
+    };
+    // Shifts that can't be serialized are skipped, so only the elements after the first get a comma
+    let mut first = true;
+    let elements = shifts
+        .into_iter()
+        .filter_map(|shift| compact_shift(&shift))
+        .map(move |json| {
+            let separator = if first { "" } else { ",\n" };
+            first = false;
+            Ok::<Bytes, actix_web::Error>(Bytes::from(format!("{separator}{json}")))
+        });
+    let array = iter::once(Ok(Bytes::from_static(b"[\n")))
+        .chain(elements)
+        .chain(iter::once(Ok(Bytes::from_static(b"\n]\n"))));
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .streaming(stream::iter(array))
+}
+
+// The driving jobs of the shifts as a GTFS-like feed, for comparing against the published feed
+#[get("/export/gtfs.zip")]
+async fn export_gtfs(query: web::Query<ExportQuery>) -> impl Responder {
+    let shifts = match query_shifts(&query) {
+        Ok(shifts) => shifts,
+        Err(response) => return response,
+    };
+    let zip =
+        web::block(move || gtfs_export::gtfs_zip(&shifts).map_err(|err| err.to_string())).await;
+    match zip {
+        Ok(Ok(zip)) => HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header(("Content-Disposition", "attachment; filename=\"gtfs.zip\""))
+            .body(zip),
+        Ok(Err(err)) => return_error(format!("Building the GTFS export failed: {err}")),
+        Err(err) => return_error(err.to_string()),
+    }
+}
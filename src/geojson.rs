@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    GenResult, config::Config, error::OptionResult, parsing::shift_structs::Shift, render::job_row,
+};
+
+// A row of the coordinates file, which has the columns location,latitude,longitude
+#[derive(Deserialize)]
+struct LocationCoordinates {
+    location: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+// Read on every request, so edits to the file don't need a restart
+fn load_coordinates() -> GenResult<HashMap<String, [f64; 2]>> {
+    let path = Config::get()
+        .location_coordinates_path
+        .clone()
+        .result_reason("No location_coordinates_path configured")?;
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut coordinates = HashMap::new();
+    for row in reader.deserialize() {
+        let row: LocationCoordinates = row?;
+        // GeoJSON puts the longitude first
+        coordinates.insert(row.location.to_lowercase(), [row.longitude, row.latitude]);
+    }
+    Ok(coordinates)
+}
+
+fn locate<'a>(
+    coordinates: &'a HashMap<String, [f64; 2]>,
+    location: &'a Option<String>,
+) -> Option<(&'a String, &'a [f64; 2])> {
+    let location = location.as_ref()?;
+    Some((location, coordinates.get(&location.to_lowercase())?))
+}
+
+// The movements of a shift as lines between locations, plus a point for every location visited.
+// Locations without known coordinates are left out
+pub fn shift_to_geojson(shift: &Shift) -> GenResult<String> {
+    let coordinates = load_coordinates()?;
+    let mut features: Vec<Value> = vec![];
+    let mut visited: Vec<&String> = vec![];
+    for job in &shift.job {
+        let start = locate(&coordinates, &job.start_location);
+        let end = locate(&coordinates, &job.end_location);
+        for (location, point) in [start, end].into_iter().flatten() {
+            if !visited.contains(&location) {
+                visited.push(location);
+                features.push(json!({
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": point },
+                    "properties": { "location": location },
+                }));
+            }
+        }
+        if let (Some((from, from_point)), Some((to, to_point))) = (start, end)
+            && from != to
+        {
+            let [start_time, end_time, job_name, line, _] = job_row(job);
+            features.push(json!({
+                "type": "Feature",
+                "geometry": { "type": "LineString", "coordinates": [from_point, to_point] },
+                "properties": {
+                    "job": job_name,
+                    "line": line,
+                    "start": start_time,
+                    "end": end_time,
+                    "from": from,
+                    "to": to,
+                },
+            }));
+        }
+    }
+    Ok(serde_json::to_string_pretty(&json!({
+        "type": "FeatureCollection",
+        "properties": { "shift_nr": shift.shift_nr },
+        "features": features,
+    }))?)
+}
@@ -0,0 +1,65 @@
+use lopdf::{
+    Document, Object,
+    content::{Content, Operation},
+};
+
+use crate::GenResult;
+
+// Fills darker than this are kept as they are, so text stays readable
+const DARK_THRESHOLD: f32 = 0.25;
+// How much of the remaining ink lighter fills keep
+const INK_FACTOR: f32 = 0.4;
+
+fn luminance(red: f32, green: f32, blue: f32) -> f32 {
+    0.299 * red + 0.587 * green + 0.114 * blue
+}
+
+// Light fills like colored headers are mostly background, so they are made lighter still
+fn ink_light(gray: f32) -> f32 {
+    if gray < DARK_THRESHOLD {
+        gray
+    } else {
+        1.0 - (1.0 - gray) * INK_FACTOR
+    }
+}
+
+// Replace the RGB and CMYK color operators with gray ones
+fn to_gray(operation: Operation) -> Operation {
+    let components: Vec<f32> = operation
+        .operands
+        .iter()
+        .filter_map(|operand| operand.as_float().ok())
+        .collect();
+    let gray = match (operation.operator.as_str(), components.as_slice()) {
+        ("rg" | "RG", [red, green, blue]) => luminance(*red, *green, *blue),
+        ("k" | "K", [cyan, magenta, yellow, black]) => luminance(
+            (1.0 - cyan) * (1.0 - black),
+            (1.0 - magenta) * (1.0 - black),
+            (1.0 - yellow) * (1.0 - black),
+        ),
+        ("g" | "G", [gray]) => *gray,
+        _ => return operation,
+    };
+    let (operator, gray) = match operation.operator.as_str() {
+        "rg" | "k" | "g" => ("g", ink_light(gray)),
+        _ => ("G", gray),
+    };
+    Operation::new(operator, vec![Object::Real(gray)])
+}
+
+// A grayscale version of the PDF with lighter fills, for monochrome printers.
+// Images are left as they are
+pub fn print_variant(pdf: &[u8]) -> GenResult<Vec<u8>> {
+    let mut doc = Document::load_mem(pdf)?;
+    for page_id in doc.get_pages().into_values() {
+        let content = Content::decode(&doc.get_page_content(page_id)?)?;
+        let gray_content: Content<Vec<Operation>> = Content {
+            operations: content.operations.into_iter().map(to_gray).collect(),
+        };
+        doc.change_page_content(page_id, gray_content.encode()?)?;
+    }
+    doc.compress();
+    let mut gray_pdf = vec![];
+    doc.save_to(&mut gray_pdf)?;
+    Ok(gray_pdf)
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{LazyLock, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GenResult,
+    config::Config,
+    parsing::shift_structs::{JobDrivingType, JobType, Shift},
+};
+
+// The feed of the configured path, only read again when the path changes
+static GTFS_FEED: LazyLock<RwLock<Option<(String, GtfsTrips)>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+#[derive(Deserialize)]
+struct Route {
+    route_id: String,
+    route_short_name: String,
+}
+
+#[derive(Deserialize)]
+struct Trip {
+    route_id: String,
+    trip_id: String,
+}
+
+#[derive(Deserialize)]
+struct StopTime {
+    trip_id: String,
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: u32,
+}
+
+#[derive(Deserialize)]
+struct Stop {
+    stop_id: String,
+    stop_name: String,
+}
+
+struct TripStart {
+    trip_id: String,
+    // Lowercase name of the first stop
+    origin: String,
+}
+
+// Trips by line number and departure time (hh:mm) from their first stop
+type GtfsTrips = HashMap<(String, String), Vec<TripStart>>;
+
+// A driving job of the shift and the GTFS trip it drives
+#[derive(Serialize, Debug, Clone)]
+pub struct GtfsTripLink {
+    pub job: usize,
+    pub trip_id: String,
+}
+
+fn read_rows<T: for<'de> Deserialize<'de>>(feed: &Path, file: &str) -> GenResult<Vec<T>> {
+    let mut reader = csv::Reader::from_path(feed.join(file))?;
+    Ok(reader.deserialize().collect::<Result<Vec<T>, _>>()?)
+}
+
+// GTFS times can go past 24:00 for trips after midnight
+fn normalize_departure(departure_time: &str) -> Option<String> {
+    let mut parts = departure_time.trim().split(':');
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = parts.next()?.parse().ok()?;
+    Some(format!("{:02}:{minute:02}", hour % 24))
+}
+
+fn load_feed(feed: &Path) -> GenResult<GtfsTrips> {
+    let line_numbers: HashMap<String, String> = read_rows::<Route>(feed, "routes.txt")?
+        .into_iter()
+        .map(|route| (route.route_id, route.route_short_name))
+        .collect();
+    let trip_lines: HashMap<String, String> = read_rows::<Trip>(feed, "trips.txt")?
+        .into_iter()
+        .filter_map(|trip| Some((trip.trip_id, line_numbers.get(&trip.route_id)?.clone())))
+        .collect();
+    let stop_names: HashMap<String, String> = read_rows::<Stop>(feed, "stops.txt")?
+        .into_iter()
+        .map(|stop| (stop.stop_id, stop.stop_name.to_lowercase()))
+        .collect();
+    // stop_times.txt is by far the largest file, so only the first stop of every trip is kept
+    let mut first_stops: HashMap<String, StopTime> = HashMap::new();
+    let mut reader = csv::Reader::from_path(feed.join("stop_times.txt"))?;
+    for row in reader.deserialize() {
+        let stop_time: StopTime = row?;
+        match first_stops.get(&stop_time.trip_id) {
+            Some(first) if first.stop_sequence <= stop_time.stop_sequence => {}
+            _ => {
+                first_stops.insert(stop_time.trip_id.clone(), stop_time);
+            }
+        }
+    }
+    let mut trips: GtfsTrips = HashMap::new();
+    for (trip_id, first_stop) in first_stops {
+        let (Some(line), Some(departure)) = (
+            trip_lines.get(&trip_id),
+            normalize_departure(&first_stop.departure_time),
+        ) else {
+            continue;
+        };
+        trips
+            .entry((line.clone(), departure))
+            .or_default()
+            .push(TripStart {
+                trip_id,
+                origin: stop_names
+                    .get(&first_stop.stop_id)
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+    }
+    Ok(trips)
+}
+
+// Match the driving jobs to trips by line, departure time and origin.
+// Returns nothing when no feed is configured
+pub fn link_trips(shift: &Shift) -> GenResult<Vec<GtfsTripLink>> {
+    let Some(feed_path) = Config::get().gtfs_path.clone() else {
+        return Ok(vec![]);
+    };
+    let loaded = GTFS_FEED
+        .read()?
+        .as_ref()
+        .is_some_and(|(path, _)| *path == feed_path);
+    if !loaded {
+        let trips = load_feed(Path::new(&feed_path))?;
+        info!("Loaded GTFS feed from {feed_path}");
+        *GTFS_FEED.write()? = Some((feed_path, trips));
+    }
+    let feed = GTFS_FEED.read()?;
+    let Some((_, trips)) = feed.as_ref() else {
+        return Ok(vec![]);
+    };
+    let mut links = vec![];
+    for (job_index, job) in shift.job.iter().enumerate() {
+        let (
+            JobType::Rijden {
+                drive_type: JobDrivingType::Lijn(line),
+            },
+            Some(start),
+        ) = (&job.job_type, job.start)
+        else {
+            continue;
+        };
+        let departure = format!("{:02}:{:02}", start.hour(), start.minute());
+        let Some(candidates) = trips.get(&(line.to_string(), departure)) else {
+            continue;
+        };
+        // Names in the duty PDFs are often shortened, so either name may contain the other
+        let origin = job
+            .start_location
+            .clone()
+            .unwrap_or_default()
+            .to_lowercase();
+        let matching_origin = candidates.iter().find(|trip| {
+            !origin.is_empty()
+                && !trip.origin.is_empty()
+                && (trip.origin.contains(&origin) || origin.contains(&trip.origin))
+        });
+        // A single trip at that time on that line is a match even if the origin is spelled differently
+        let trip = matching_origin.or(match candidates.as_slice() {
+            [only] => Some(only),
+            _ => None,
+        });
+        if let Some(trip) = trip {
+            links.push(GtfsTripLink {
+                job: job_index,
+                trip_id: trip.trip_id.clone(),
+            });
+        }
+    }
+    Ok(links)
+}
@@ -0,0 +1,185 @@
+// A GTFS-like feed of the driving jobs, so the duty book can be cross-checked against the published feed.
+// Only the line and trip numbers and the times at the first and last location of a job are known,
+// so the stop times are an approximation with two stops per trip
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Write},
+    sync::Arc,
+};
+
+use serde::Serialize;
+use time::Time;
+use tracing::warn;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    GenResult,
+    parsing::shift_structs::{JobDrivingType, JobType, Shift, ShiftJob},
+};
+
+#[derive(Serialize)]
+struct Route {
+    route_id: String,
+    route_short_name: String,
+    // Bus
+    route_type: u8,
+}
+
+#[derive(Serialize)]
+struct Trip {
+    route_id: String,
+    // The days the shift runs on
+    service_id: String,
+    trip_id: String,
+    // The omloop the bus drives
+    block_id: Option<usize>,
+    // Not part of GTFS, the shift that drives the trip
+    shift_id: String,
+}
+
+#[derive(Serialize)]
+struct StopTime {
+    trip_id: String,
+    arrival_time: String,
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: u32,
+}
+
+#[derive(Serialize)]
+struct Stop {
+    stop_id: String,
+    stop_name: String,
+}
+
+#[derive(Default)]
+struct Feed {
+    routes: BTreeMap<u32, Route>,
+    trips: BTreeMap<String, Trip>,
+    stop_times: Vec<StopTime>,
+    stops: BTreeMap<String, Stop>,
+}
+
+fn stop_id(location: &str) -> String {
+    location
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|character| {
+            if character.is_alphanumeric() {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// GTFS counts the hours on from the start of the service day, so a time before the start
+// of the shift is after midnight
+fn gtfs_time(time: Time, shift_start: Option<Time>) -> String {
+    let hours = match shift_start {
+        Some(shift_start) if time < shift_start => time.hour() + 24,
+        _ => time.hour(),
+    };
+    format!("{hours:02}:{:02}:00", time.minute())
+}
+
+impl Feed {
+    fn add_shift(&mut self, shift: &Shift) {
+        let shift_start = shift.times.start_time;
+        for (index, job) in shift.job.iter().enumerate() {
+            let JobType::Rijden {
+                drive_type: JobDrivingType::Lijn(line),
+            } = job.job_type
+            else {
+                continue;
+            };
+            // Without a rit number the trip can only be told apart by the job it comes from
+            let trip_id = match job.rit {
+                Some(rit) => format!("{line}-{rit}"),
+                None => format!("{line}-{}-{index}", shift.shift_nr),
+            };
+            if let Some(trip) = self.trips.get(&trip_id) {
+                warn!(
+                    "Trip {trip_id} of shift {} is already driven by shift {}",
+                    shift.shift_nr, trip.shift_id
+                );
+                continue;
+            }
+            self.routes.entry(line).or_insert_with(|| Route {
+                route_id: line.to_string(),
+                route_short_name: line.to_string(),
+                route_type: 3,
+            });
+            self.trips.insert(
+                trip_id.clone(),
+                Trip {
+                    route_id: line.to_string(),
+                    service_id: shift.valid_on.file_tag().to_string(),
+                    trip_id: trip_id.clone(),
+                    block_id: job.omloop,
+                    shift_id: shift.shift_nr.clone(),
+                },
+            );
+            self.add_stop_times(&trip_id, job, shift_start);
+        }
+    }
+
+    fn add_stop_times(&mut self, trip_id: &str, job: &ShiftJob, shift_start: Option<Time>) {
+        // The end location is left out when the job ends where it started
+        let end_location = job.end_location.as_ref().or(job.start_location.as_ref());
+        let stops = [
+            (job.start_location.as_ref(), job.start),
+            (end_location, job.end),
+        ];
+        let mut stop_sequence = 0;
+        for (location, time) in stops {
+            let (Some(location), Some(time)) = (location, time) else {
+                continue;
+            };
+            stop_sequence += 1;
+            let stop_id = stop_id(location);
+            self.stops.entry(stop_id.clone()).or_insert_with(|| Stop {
+                stop_id: stop_id.clone(),
+                stop_name: location.to_string(),
+            });
+            let time = gtfs_time(time, shift_start);
+            self.stop_times.push(StopTime {
+                trip_id: trip_id.to_string(),
+                arrival_time: time.clone(),
+                departure_time: time,
+                stop_id,
+                stop_sequence,
+            });
+        }
+    }
+}
+
+fn to_csv<T: Serialize>(rows: impl IntoIterator<Item = T>) -> GenResult<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+// The zip with routes.txt, trips.txt, stop_times.txt and stops.txt of the shifts
+pub fn gtfs_zip(shifts: &[Arc<Shift>]) -> GenResult<Vec<u8>> {
+    let mut feed = Feed::default();
+    for shift in shifts {
+        feed.add_shift(shift);
+    }
+    let files = [
+        ("routes.txt", to_csv(feed.routes.into_values())?),
+        ("trips.txt", to_csv(feed.trips.into_values())?),
+        ("stop_times.txt", to_csv(feed.stop_times)?),
+        ("stops.txt", to_csv(feed.stops.into_values())?),
+    ];
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for (name, contents) in files {
+        zip.start_file(name, SimpleFileOptions::default())?;
+        zip.write_all(&contents)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{LazyLock, RwLock},
+};
+
+use actix_web::{HttpResponse, Responder, get, http::header::ContentType};
+use serde::Serialize;
+
+use crate::{collection::PdfTimetableCollection, get_valid_timetables, refresh, tenant};
+
+// What went wrong while starting per tenant, kept until a refresh or reload succeeds
+static STARTUP_ERRORS: LazyLock<RwLock<HashMap<String, Vec<String>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+pub fn report_startup_error(error: String) {
+    match STARTUP_ERRORS.write() {
+        Ok(mut errors) => errors
+            .entry(tenant::current().name)
+            .or_default()
+            .push(error),
+        Err(err) => error!("Failed to record startup error: {err}"),
+    }
+}
+
+pub fn clear_startup_errors() {
+    if let Ok(mut errors) = STARTUP_ERRORS.write() {
+        errors.remove(&tenant::current().name);
+    }
+}
+
+fn startup_errors() -> Vec<String> {
+    STARTUP_ERRORS
+        .read()
+        .ok()
+        .and_then(|errors| errors.get(&tenant::current().name).cloned())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    collections_loaded: bool,
+    active_timetable: bool,
+    book_readable: bool,
+    // Only informational, the current collections are served until the new ones are swapped in
+    refresh_running: bool,
+    // Started despite errors, the collections may be missing or incomplete
+    degraded: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    startup_errors: Vec<String>,
+}
+
+// The process is up and handling requests
+#[get("/healthz")]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().body("ok")
+}
+
+#[get("/readyz")]
+async fn readyz() -> impl Responder {
+    let collections_loaded =
+        PdfTimetableCollection::get_timetables().is_ok_and(|timetables| !timetables.is_empty());
+    let active_timetable =
+        get_valid_timetables(None).is_ok_and(|(timetables, _)| !timetables.is_empty());
+    let book_readable = fs::read_dir(tenant::book_path()).is_ok();
+    let refresh_running = refresh::current_progress().running;
+    let ready = collections_loaded && active_timetable && book_readable;
+    let startup_errors = startup_errors();
+    let readiness = Readiness {
+        ready,
+        collections_loaded,
+        active_timetable,
+        book_readable,
+        refresh_running,
+        degraded: !startup_errors.is_empty(),
+        startup_errors,
+    };
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    response
+        .content_type(ContentType::json())
+        .body(serde_json::to_string_pretty(&readiness).unwrap())
+}
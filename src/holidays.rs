@@ -0,0 +1,85 @@
+// Public holidays run the Sunday timetable, so lookups by date treat them as a Sunday
+use time::{Date, Duration, Month, Weekday};
+
+use crate::{config::Config, parse_date};
+
+// Easter Sunday, using the anonymous Gregorian algorithm
+fn easter_sunday(year: i32) -> Option<Date> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Date::from_calendar_date(year, Month::try_from(month as u8).ok()?, day as u8).ok()
+}
+
+// The national holidays of the Netherlands in a year
+fn dutch_holidays(year: i32) -> Vec<(Date, &'static str)> {
+    let date = |month: Month, day: u8| Date::from_calendar_date(year, month, day).ok();
+    let mut holidays: Vec<(Option<Date>, &'static str)> = vec![
+        (date(Month::January, 1), "Nieuwjaarsdag"),
+        (date(Month::December, 25), "Eerste Kerstdag"),
+        (date(Month::December, 26), "Tweede Kerstdag"),
+    ];
+    if let Some(easter) = easter_sunday(year) {
+        holidays.extend([
+            (Some(easter), "Eerste Paasdag"),
+            (Some(easter + Duration::days(1)), "Tweede Paasdag"),
+            (Some(easter + Duration::days(39)), "Hemelvaartsdag"),
+            (Some(easter + Duration::days(49)), "Eerste Pinksterdag"),
+            (Some(easter + Duration::days(50)), "Tweede Pinksterdag"),
+        ]);
+    }
+    // Koningsdag moves to the Saturday before when the 27th is a Sunday
+    let kings_day = date(Month::April, 27).map(|kings_day| {
+        if kings_day.weekday() == Weekday::Sunday {
+            kings_day - Duration::days(1)
+        } else {
+            kings_day
+        }
+    });
+    holidays.push((kings_day, "Koningsdag"));
+    // Bevrijdingsdag is only a day off once every five years
+    if year % 5 == 0 {
+        holidays.push((date(Month::May, 5), "Bevrijdingsdag"));
+    }
+    holidays
+        .into_iter()
+        .filter_map(|(date, name)| Some((date?, name)))
+        .collect()
+}
+
+// Name of the holiday on the date, from the built-in Dutch holidays or the configured extra ones
+pub fn holiday(date: Date) -> Option<String> {
+    let config = Config::get();
+    if config.dutch_holidays
+        && let Some((_, name)) = dutch_holidays(date.year())
+            .into_iter()
+            .find(|(holiday, _)| *holiday == date)
+    {
+        return Some(name.to_string());
+    }
+    config
+        .extra_holidays
+        .iter()
+        .any(|holiday| parse_date(holiday) == Some(date))
+        .then(|| "Holiday".to_string())
+}
+
+// The weekday whose timetable runs on the date
+pub fn service_weekday(date: Date) -> Weekday {
+    if holiday(date).is_some() {
+        Weekday::Sunday
+    } else {
+        date.weekday()
+    }
+}
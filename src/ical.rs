@@ -0,0 +1,129 @@
+use time::{
+    Date, Duration, OffsetDateTime, Time,
+    format_description::BorrowedFormatItem,
+    macros::{format_description, time},
+};
+
+use crate::{
+    parsing::shift_structs::{Shift, ShiftValid},
+    render::shift_to_text,
+};
+
+const ICAL_DATE_TIME_FORMAT: &[BorrowedFormatItem<'_>] =
+    format_description!["[year][month][day]T[hour][minute][second]"];
+const ICAL_DATE_FORMAT: &[BorrowedFormatItem<'_>] = format_description!["[year][month][day]"];
+
+// Lines longer than this have to be folded
+const MAX_LINE_OCTETS: usize = 75;
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Continuation lines start with a space, never split inside a character
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut line_octets = 0;
+    for character in line.chars() {
+        if line_octets + character.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            line_octets = 1;
+        }
+        folded.push(character);
+        line_octets += character.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn weekdays(valid_on: &ShiftValid) -> Option<&'static str> {
+    match valid_on {
+        ShiftValid::Weekdays => Some("MO,TU,WE,TH,FR"),
+        ShiftValid::Wednesday => Some("WE"),
+        ShiftValid::WeekdaysExceptWednesday => Some("MO,TU,TH,FR"),
+        ShiftValid::Saturday => Some("SA"),
+        ShiftValid::Sunday => Some("SU"),
+        ShiftValid::Unknown => None,
+    }
+}
+
+// The first day on or after the date the shift is driven
+fn first_day(shift: &Shift) -> Date {
+    let mut day = shift.starting_date;
+    for _ in 0..7 {
+        if ShiftValid::for_weekday(day.weekday()).contains(&shift.valid_on) {
+            return day;
+        }
+        day += Duration::days(1);
+    }
+    shift.starting_date
+}
+
+fn format_date_time(date: Date, time: Time) -> String {
+    date.with_time(time)
+        .format(ICAL_DATE_TIME_FORMAT)
+        .unwrap_or_default()
+}
+
+// A recurring event on every day the shift is driven, until the next timetable starts.
+// Times are floating, so calendars show them in the local time of the phone
+pub fn shift_to_ical(shift: &Shift, valid_from: Date, until: Option<Date>) -> String {
+    let now = OffsetDateTime::now_utc();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//hermes_pdf_shift_http//NL".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!(
+            "UID:{}-{}@hermes_pdf_shift_http",
+            shift.shift_nr,
+            valid_from.format(ICAL_DATE_FORMAT).unwrap_or_default()
+        ),
+        format!("DTSTAMP:{}Z", format_date_time(now.date(), now.time())),
+        format!(
+            "SUMMARY:{}",
+            escape_text(&format!("Dienst {}", shift.shift_nr))
+        ),
+        format!("LOCATION:{}", escape_text(&shift.location)),
+        format!("DESCRIPTION:{}", escape_text(&shift_to_text(shift))),
+    ];
+    let day = first_day(shift);
+    let timed = shift.start_time().is_some() && shift.end_time().is_some();
+    match (shift.start_time(), shift.end_time()) {
+        (Some(start), Some(end)) => {
+            // Shifts ending before they start run past midnight
+            let end_day = if end < start {
+                day.next_day().unwrap_or(day)
+            } else {
+                day
+            };
+            lines.push(format!("DTSTART:{}", format_date_time(day, start)));
+            lines.push(format!("DTEND:{}", format_date_time(end_day, end)));
+        }
+        _ => lines.push(format!(
+            "DTSTART;VALUE=DATE:{}",
+            day.format(ICAL_DATE_FORMAT).unwrap_or_default()
+        )),
+    }
+    if let Some(weekdays) = weekdays(&shift.valid_on) {
+        let until = until
+            .and_then(|until| until.previous_day())
+            // UNTIL has to have the same type as DTSTART, the last day is included completely
+            .map(|until| {
+                let until = if timed {
+                    format_date_time(until, time!(23:59:59))
+                } else {
+                    until.format(ICAL_DATE_FORMAT).unwrap_or_default()
+                };
+                format!(";UNTIL={until}")
+            })
+            .unwrap_or_default();
+        lines.push(format!("RRULE:FREQ=WEEKLY;BYDAY={weekdays}{until}"));
+    }
+    lines.push("END:VEVENT".to_string());
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold_line(line)).collect()
+}
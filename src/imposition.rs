@@ -0,0 +1,98 @@
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+
+use crate::{GenResult, error::OptionResult};
+
+// A4 in landscape, in PDF points
+const SHEET_WIDTH: f32 = 842.0;
+const SHEET_HEIGHT: f32 = 595.0;
+
+// Pages can inherit attributes like the resources and media box from their parents
+fn inherited_attribute(doc: &Document, page_id: ObjectId, key: &[u8]) -> Option<Object> {
+    let mut dictionary = doc.get_dictionary(page_id).ok()?;
+    loop {
+        if let Ok(value) = dictionary.get(key) {
+            return doc.dereference(value).ok().map(|(_, value)| value.clone());
+        }
+        let parent = dictionary
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .ok()?;
+        dictionary = doc.get_dictionary(parent).ok()?;
+    }
+}
+
+fn media_box(doc: &Document, page_id: ObjectId) -> GenResult<[f32; 4]> {
+    let media_box =
+        inherited_attribute(doc, page_id, b"MediaBox").result_reason("Page has no media box")?;
+    let corners = media_box
+        .as_array()?
+        .iter()
+        .map(Object::as_float)
+        .collect::<Result<Vec<f32>, _>>()?;
+    corners
+        .try_into()
+        .map_err(|_| "Media box does not have four corners".into())
+}
+
+// Turn a page into a form XObject, so it can be drawn scaled on another page
+fn page_to_form(doc: &mut Document, page_id: ObjectId) -> GenResult<(ObjectId, [f32; 4])> {
+    let media_box = media_box(doc, page_id)?;
+    let resources = inherited_attribute(doc, page_id, b"Resources")
+        .unwrap_or(Object::Dictionary(Dictionary::new()));
+    let content = doc.get_page_content(page_id)?;
+    let form = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => media_box.iter().map(|corner| Object::Real(*corner)).collect::<Vec<Object>>(),
+            "Resources" => resources,
+        },
+        content,
+    );
+    Ok((doc.add_object(form), media_box))
+}
+
+// Put two pages side by side on every A4 sheet, scaled to fit while keeping their proportions
+pub fn two_up(pdf: &[u8]) -> GenResult<Vec<u8>> {
+    let mut doc = Document::load_mem(pdf)?;
+    let pages_id = doc.catalog()?.get(b"Pages")?.as_reference()?;
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let mut forms = vec![];
+    for page_id in page_ids {
+        forms.push(page_to_form(&mut doc, page_id)?);
+    }
+
+    let slot_width = SHEET_WIDTH / 2.0;
+    let mut sheets = vec![];
+    for sheet_forms in forms.chunks(2) {
+        let mut content = String::new();
+        let mut xobjects = Dictionary::new();
+        for (slot, (form_id, [x0, y0, x1, y1])) in sheet_forms.iter().enumerate() {
+            let (width, height) = (x1 - x0, y1 - y0);
+            let scale = (slot_width / width).min(SHEET_HEIGHT / height);
+            let x = slot as f32 * slot_width + (slot_width - width * scale) / 2.0 - x0 * scale;
+            let y = (SHEET_HEIGHT - height * scale) / 2.0 - y0 * scale;
+            let name = format!("Page{slot}");
+            content.push_str(&format!("q {scale} 0 0 {scale} {x} {y} cm /{name} Do Q\n"));
+            xobjects.set(name, *form_id);
+        }
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+        sheets.push(Object::Reference(doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), SHEET_WIDTH.into(), SHEET_HEIGHT.into()],
+            "Resources" => dictionary! { "XObject" => xobjects },
+            "Contents" => content_id,
+        })));
+    }
+
+    let pages = doc.get_dictionary_mut(pages_id)?;
+    pages.set("Count", sheets.len() as i64);
+    pages.set("Kids", sheets);
+    // The original pages are no longer referenced by the page tree
+    doc.prune_objects();
+    doc.compress();
+    let mut imposed = vec![];
+    doc.save_to(&mut imposed)?;
+    Ok(imposed)
+}
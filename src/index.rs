@@ -1,46 +1,246 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
-use actix_web::{HttpResponse, http::header::ContentType};
-use serde::Serialize;
-use time::Date;
+use actix_web::{HttpResponse, ResponseError, http::header::ContentType};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
 
-use crate::{GenResult, get_valid_timetables};
+use crate::{
+    GenResult,
+    collection::{ShiftData, shift_name},
+    error::HermesError,
+    get_valid_timetables,
+    parsing::shift_structs::{ShiftTimes, ShiftType},
+    response_cache, sqlite, tenant,
+};
 
 #[derive(Serialize)]
 pub struct IndexShift {
     shift_number: String,
+    canonical_prefix: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depot: Option<String>,
     valid_from: Date,
+    #[serde(flatten)]
+    times: Option<ShiftTimes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shift_type: Option<ShiftType>,
 }
 
 pub fn get_valid_shifts(date: Option<Date>) -> GenResult<Vec<IndexShift>> {
-    let mut available_shifts: HashMap<String, (Date, String)> = HashMap::new();
-    let valid_timetables = get_valid_timetables(date)?.0;
-    for current_timetable in valid_timetables {
-        for shift in current_timetable.pages {
-            available_shifts.insert(
-                shift.0,
-                (current_timetable.valid_from, shift.1.shift_prefix),
-            );
+    let mut available_shifts: HashMap<String, (Date, ShiftData)> = HashMap::new();
+    let current_date = date.unwrap_or(OffsetDateTime::now_utc().date());
+    if let Some(shifts) = sqlite::valid_shifts(current_date) {
+        for (shift_number, valid_from, shift_data) in shifts {
+            available_shifts.insert(shift_number, (valid_from, shift_data));
+        }
+    } else {
+        let valid_timetables = get_valid_timetables(date)?.0;
+        for current_timetable in valid_timetables {
+            for (key, shift_data) in &current_timetable.pages {
+                available_shifts.insert(
+                    key.clone(),
+                    (current_timetable.valid_from, shift_data.clone()),
+                );
+            }
         }
     }
+    let tenant = tenant::current();
     let mut struct_available_shifts: Vec<IndexShift> = vec![];
-    for available_shift in available_shifts {
+    for (key, (valid_from, shift_data)) in available_shifts {
         struct_available_shifts.push(IndexShift {
-            shift_number: format!("{}{}", available_shift.1.1, available_shift.0),
-            valid_from: available_shift.1.0,
+            shift_number: shift_name(&key, &shift_data),
+            canonical_prefix: tenant.canonical_prefix(&shift_data.shift_prefix),
+            depot: shift_data.depot,
+            valid_from,
+            times: shift_data.times,
+            shift_type: shift_data.shift_type,
         })
     }
     Ok(struct_available_shifts)
 }
 
-pub fn handle_index_request(date: Option<Date>) -> HttpResponse {
-    match get_valid_shifts(date) {
-        Ok(shifts) => HttpResponse::Ok()
+const MAX_SUGGESTIONS: usize = 5;
+// Shift numbers further away than this aren't a typo anymore
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+fn levenshtein(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=second.len()).collect();
+    for (first_index, first_char) in first.chars().enumerate() {
+        let mut row = vec![first_index + 1];
+        for (second_index, second_char) in second.iter().enumerate() {
+            let substitution = previous_row[second_index] + usize::from(first_char != *second_char);
+            row.push(
+                substitution
+                    .min(previous_row[second_index + 1] + 1)
+                    .min(row[second_index] + 1),
+            );
+        }
+        previous_row = row;
+    }
+    previous_row[second.len()]
+}
+
+// The valid shifts closest to a shift that wasn't found, by the edit distance of the number.
+// Shifts with the requested prefix (or an equivalent one) come first when the distance is the same
+pub fn similar_shifts(request: &str, date: Option<Date>) -> Vec<String> {
+    let request = request.to_uppercase();
+    let requested_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let requested_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    if requested_number.is_empty() {
+        return vec![];
+    }
+    let Ok(shifts) = get_valid_shifts(date) else {
+        return vec![];
+    };
+    let tenant = tenant::current();
+    let mut candidates: Vec<(usize, bool, String)> = shifts
+        .into_iter()
+        .filter_map(|shift| {
+            let prefix: String = shift
+                .shift_number
+                .chars()
+                .filter(|c| c.is_alphabetic())
+                .collect();
+            let number: String = shift
+                .shift_number
+                .chars()
+                .filter(|c| c.is_numeric())
+                .collect();
+            let distance = levenshtein(&requested_number, &number);
+            let other_prefix = !requested_prefix.is_empty()
+                && !tenant.prefixes_equivalent(&requested_prefix, &prefix);
+            (distance <= MAX_SUGGESTION_DISTANCE).then_some((
+                distance,
+                other_prefix,
+                shift.shift_number,
+            ))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, shift_number)| shift_number)
+        .collect()
+}
+
+// Filters and pagination of the index, without them every valid shift is listed
+#[derive(Deserialize)]
+pub struct IndexFilters {
+    // Only shifts with this prefix or an equivalent one
+    prefix: Option<String>,
+    // Range of shift numbers, both inclusive
+    from: Option<String>,
+    to: Option<String>,
+    offset: Option<String>,
+    limit: Option<String>,
+}
+
+struct ParsedFilters {
+    prefix: Option<String>,
+    from: Option<u32>,
+    to: Option<u32>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+fn parse_number<T: FromStr>(name: &str, value: &Option<String>) -> Result<Option<T>, HermesError> {
+    value
+        .as_ref()
+        .map(|value| {
+            value.trim().parse().map_err(|_| {
+                HermesError::BadRequest(format!("{name} should be a number, not \"{value}\""))
+            })
+        })
+        .transpose()
+}
+
+impl IndexFilters {
+    fn parse(&self) -> Result<ParsedFilters, HermesError> {
+        Ok(ParsedFilters {
+            prefix: self
+                .prefix
+                .as_ref()
+                .map(|prefix| prefix.trim().to_uppercase())
+                .filter(|prefix| !prefix.is_empty()),
+            from: parse_number("from", &self.from)?,
+            to: parse_number("to", &self.to)?,
+            offset: parse_number("offset", &self.offset)?.unwrap_or_default(),
+            limit: parse_number("limit", &self.limit)?,
+        })
+    }
+}
+
+fn shift_number_value(shift_number: &str) -> u32 {
+    shift_number
+        .chars()
+        .filter(|c| c.is_numeric())
+        .collect::<String>()
+        .parse()
+        .unwrap_or_default()
+}
+
+// The valid shifts sorted by number, filtered and paginated
+fn filtered_shifts(date: Option<Date>, filters: &ParsedFilters) -> GenResult<Vec<IndexShift>> {
+    let tenant = tenant::current();
+    let mut shifts: Vec<IndexShift> = get_valid_shifts(date)?
+        .into_iter()
+        .filter(|shift| {
+            let number = shift_number_value(&shift.shift_number);
+            let prefix: String = shift
+                .shift_number
+                .chars()
+                .filter(|c| c.is_alphabetic())
+                .collect();
+            filters
+                .prefix
+                .as_ref()
+                .is_none_or(|requested| tenant.prefixes_equivalent(requested, &prefix))
+                && filters.from.is_none_or(|from| number >= from)
+                && filters.to.is_none_or(|to| number <= to)
+        })
+        .collect();
+    shifts.sort_by(|first, second| {
+        shift_number_value(&first.shift_number)
+            .cmp(&shift_number_value(&second.shift_number))
+            .then_with(|| first.shift_number.cmp(&second.shift_number))
+            .then_with(|| first.valid_from.cmp(&second.valid_from))
+    });
+    Ok(shifts
+        .into_iter()
+        .skip(filters.offset)
+        .take(filters.limit.unwrap_or(usize::MAX))
+        .collect())
+}
+
+pub fn handle_index_request(date: Option<Date>, filters: &IndexFilters) -> HttpResponse {
+    let filters = match filters.parse() {
+        Ok(filters) => filters,
+        Err(err) => return err.error_response(),
+    };
+    let cache_filters = format!(
+        "{}|{:?}|{:?}|{}|{:?}",
+        filters.prefix.as_deref().unwrap_or_default(),
+        filters.from,
+        filters.to,
+        filters.offset,
+        filters.limit
+    );
+    let index_json = response_cache::cached(
+        response_cache::cache_key("index", date, &cache_filters),
+        || {
+            Ok(serde_json::to_string_pretty(&filtered_shifts(
+                date, &filters,
+            )?)?)
+        },
+    );
+    match index_json {
+        Ok(json) => HttpResponse::Ok()
             .content_type(ContentType::json())
-            .body(serde_json::to_string_pretty(&shifts).unwrap()),
-        Err(err) => HttpResponse::InternalServerError().body(format!(
-            "<h1>sorry, loading shift index failed</h1><br>{}",
-            err.to_string()
-        )),
+            .body(json),
+        Err(err) => {
+            HermesError::Internal(format!("Loading the shift index failed: {err}")).error_response()
+        }
     }
 }
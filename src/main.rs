@@ -1,39 +1,102 @@
-use crate::collection::{PdfTimetableCollection, ShiftData};
-use crate::parsing::{shift_parsing::parse_pdf, shift_structs::Shift};
-use crate::statistics::handle_stats_request;
-use actix_web::http::header::ContentType;
-use actix_web::{App, HttpResponse, HttpServer, Responder, get, web};
+use crate::admin::FileError;
+use crate::collection::{
+    PdfTimetableCollection, ShiftData, file_id, key_number, shift_key, variant_key,
+};
+use crate::conditional::Validators;
+use crate::config::{ClusterRole, Config, reload_config, reload_on_sighup};
+use crate::diff::{ShiftResponse, changes_since_previous};
+use crate::digest::{
+    DigestChanges, FileDigests, combined_digest, compare_digests, compute_digests,
+    load_stored_digests, renamed_files, store_digests,
+};
+use crate::english::EnglishShift;
+use crate::parsing::{
+    locations::LocationCanonicalizer,
+    shift_parsing::{load_text_document, parse_pdf},
+    shift_structs::{Shift, ShiftValid},
+};
+use crate::statistics::{
+    errored_shifts_page, handle_stats_request, record_statistics_snapshot, statistics_history,
+};
+use actix_web::http::header::{ContentType, TryIntoHeaderPair, VARY};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError, get, web};
 use index::handle_index_request;
 use lopdf::Document;
 use qpdf::QPdf;
+use rayon::prelude::*;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fs::{self};
-use std::hash::{DefaultHasher, Hash, Hasher};
-use std::io;
-use std::path::{Component, PathBuf};
-use std::time::SystemTime;
+use std::io::{self, IsTerminal};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
 use time::format_description::BorrowedFormatItem;
 use time::macros::format_description;
 use time::{Date, OffsetDateTime};
+use tokio::sync::Semaphore;
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+use version::get_version;
 use walkdir::WalkDir;
 
-use crate::error::OptionResult;
+use crate::error::{HermesError, OptionResult};
 
-extern crate pretty_env_logger;
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
+mod admin;
+mod api;
+mod auth;
+mod batch;
+mod cache;
+mod calendar;
+mod cluster;
 mod collection;
+mod conditional;
+mod config;
+mod coverage;
+mod diff;
+mod digest;
+mod english;
 mod error;
+mod export;
+mod geojson;
+mod grayscale;
+mod gtfs;
+mod gtfs_export;
+mod health;
+mod holidays;
+mod ical;
+mod imposition;
 mod index;
+mod metrics;
+mod parse_errors;
 mod parsing;
+mod preview;
+mod references;
+mod refresh;
+mod render;
+mod request_log;
+mod response_cache;
+mod roster;
+mod route;
+mod search;
+mod sqlite;
 mod statistics;
+mod systemd;
+mod tenant;
+mod timetables;
+mod upload;
+mod version;
+mod viewer;
+mod watcher;
+mod webhook;
 
-type ValidTimetables = Vec<PdfTimetableCollection>;
+type ValidTimetables = Vec<Arc<PdfTimetableCollection>>;
 type NextTimetableChangeDate = Option<Date>;
 
 //const PDF_PATH: &str = "Dienstboek";
@@ -41,23 +104,62 @@ const COLLECTION_PATH: &str = "pdf_collection";
 
 const BOOK_PATH: &str = "Dienstboek";
 
+// Added to the collection path for the folders used while swapping in a new index
+const BUILDING_COLLECTION_SUFFIX: &str = ".building";
+const PREVIOUS_COLLECTION_SUFFIX: &str = ".previous";
+
+const CONFIG_PATH: &str = "config.toml";
+
+const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0:8080";
+
+const DIGEST_PATH: &str = "pdf_digests.json";
+
 const CHANGE_FOLDER_NAME: &str = "Wijzigingen";
 
+const STATISTICS_HISTORY_PATH: &str = "statistics_history.ndjson";
+
+const STATISTICS_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// How long a shift number that was not found is answered from memory
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(60);
+
 // static CURRENT_TIMETABLE_DATE: LazyLock<RwLock<Date>> = LazyLock::new(|| RwLock::new(vec![]));
 
+// Set on PDF responses when the requested shift number was an alias, contains the requested number
+const SHIFT_ALIAS_HEADER: &str = "X-Shift-Alias-Of";
+const SHIFT_VARIANT_NOTICE_HEADER: &str = "X-Shift-Variant-Notice";
+
 const DATE_FORMAT: &[BorrowedFormatItem<'_>] = format_description!["[day]-[month]-[year]"];
 
+// Most clients send ISO 8601 dates, these are accepted as well
+const ISO_DATE_FORMAT: &[BorrowedFormatItem<'_>] = format_description!["[year]-[month]-[day]"];
+
 pub type GenResult<T> = Result<T, Box<dyn std::error::Error>>;
 
+// Opening the source PDFs takes a lot of memory, so limit how many are open at once
+static PDF_SEMAPHORE: LazyLock<Semaphore> =
+    LazyLock::new(|| Semaphore::new(Config::get().max_concurrent_pdf_operations.max(1)));
+
 #[derive(Deserialize)]
 struct ShiftQuery {
-    date: Option<String>, // Optional date query parameter
+    date: Option<String>,    // Optional date query parameter
+    format: Option<String>,  // Optional output format, like csv for statistics
+    layout: Option<String>,  // Optional print layout for PDFs, like 2up
+    variant: Option<String>, // Optional PDF variant, like print for monochrome printers
+    page: Option<String>,    // Optional page of a PNG preview, the first page by default
+    lang: Option<String>,    // Optional language of the JSON field names, en for English
+    #[serde(flatten)]
+    index_filters: index::IndexFilters, // Optional filters and pagination for the index
 }
 
 fn get_timetable_files() -> GenResult<Vec<PathBuf>> {
     let mut trip_files = Vec::new();
     let mut updated_trip_files = Vec::new();
-    for entry in WalkDir::new(BOOK_PATH).into_iter().filter_map(Result::ok) {
+    let source_entries = tenant::current()
+        .source_paths()
+        .into_iter()
+        .flat_map(|source_path| WalkDir::new(source_path).into_iter().filter_map(Result::ok));
+    for entry in source_entries {
         let path = entry.path();
         if path
             .components()
@@ -86,57 +188,301 @@ fn get_creation_date(path: &PathBuf) -> GenResult<SystemTime> {
     Ok(fs::metadata(path)?.created()?)
 }
 
-fn load_pdf_and_index() -> GenResult<()> {
-    let files = get_timetable_files()?;
-    fs::remove_dir_all(COLLECTION_PATH)?;
-    fs::create_dir(COLLECTION_PATH)?;
-    for file_path in files.iter().enumerate() {
-        parse_trip_sheets(file_path.1.into(), file_path.0)?;
+#[derive(Serialize, Default, Clone)]
+pub struct IndexSummary {
+    pub files_indexed: usize,
+    pub shifts_parsed: usize,
+    pub errored_shifts: Vec<String>,
+    pub file_errors: Vec<FileError>,
+}
+
+fn remove_dir_if_exists(path: &Path) -> GenResult<()> {
+    match fs::remove_dir_all(path) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(Box::new(err)),
     }
+}
+
+// A folder next to the collection folder, so renaming between them stays on the same file system
+fn sibling_collection_path(suffix: &str) -> PathBuf {
+    let collection_path = tenant::collection_path();
+    PathBuf::from(format!("{}{suffix}", collection_path.trim_end_matches('/')))
+}
+
+// Index the source PDFs. Unless a full index is requested, only the files that changed since the
+// last index are parsed again
+fn load_pdf_and_index(full: bool) -> GenResult<IndexSummary> {
+    let files = get_timetable_files()?;
+    let digests = compute_digests(&files)?;
+    // The current collections keep being served until the new ones are complete
+    let building_path = sibling_collection_path(BUILDING_COLLECTION_SUFFIX);
+    remove_dir_if_exists(&building_path)?;
+    let changes = match load_stored_digests() {
+        Some(previous_digests) if !full && Path::new(&tenant::collection_path()).is_dir() => {
+            Some(compare_digests(&previous_digests, &digests))
+        }
+        _ => None,
+    };
+    let build_result = match &changes {
+        Some(changes) => index_changed_files(&files, changes, &building_path),
+        None => fs::create_dir_all(&building_path)
+            .map_err(|err| err.into())
+            .and_then(|_| {
+                let numbered_files: Vec<(usize, &PathBuf)> =
+                    files.iter().map(|file| (file_id(file), file)).collect();
+                build_collections(&numbered_files, &building_path)
+            }),
+    };
+    let summary = match build_result {
+        Ok(summary) => summary,
+        Err(err) => {
+            if let Err(remove_err) = remove_dir_if_exists(&building_path) {
+                warn!("Failed to remove unfinished collections {building_path:?}: {remove_err}");
+            }
+            return Err(err);
+        }
+    };
+    swap_collection(&building_path)?;
     PdfTimetableCollection::load_timetables_from_disk()?;
+    cluster::write_index_marker()?;
+    store_digests(&digests)?;
+    cache::collection_changed();
+    if let Err(err) = record_statistics_snapshot() {
+        warn!("Failed to save statistics snapshot: {err}");
+    }
+    Ok(summary)
+}
+
+// Replace the collection folder with the new one. Both steps are renames,
+// so the folder is only missing for a moment and never half written
+fn swap_collection(new_collection_path: &Path) -> GenResult<()> {
+    let collection_path = PathBuf::from(tenant::collection_path());
+    let previous_path = sibling_collection_path(PREVIOUS_COLLECTION_SUFFIX);
+    remove_dir_if_exists(&previous_path)?;
+    match fs::rename(&collection_path, &previous_path) {
+        Ok(_) => (),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(Box::new(err)),
+    }
+    fs::rename(new_collection_path, &collection_path)?;
+    remove_dir_if_exists(&previous_path)
+}
+
+// Copy a folder with everything in it
+fn copy_dir(source: &Path, destination: &Path) -> GenResult<()> {
+    for entry in WalkDir::new(source) {
+        let entry = entry?;
+        let target = destination.join(entry.path().strip_prefix(source)?);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
     Ok(())
 }
 
-// Load every PDF and group them
-fn parse_trip_sheets(pdf_path: PathBuf, file_id: usize) -> Result<(), Box<dyn Error>> {
-    // Load the PDF document.
-    let shift_data_map = load_shift_data(&pdf_path, file_id)?;
-    let parsed_shifts = parse_pdf(&pdf_path, shift_data_map.clone())?;
-    let valid_from_day = parsed_shifts
+// Start from a copy of the current collections, drop the shifts of the removed and modified
+// files and only parse the files that were added or modified
+fn index_changed_files(
+    files: &[PathBuf],
+    changes: &DigestChanges,
+    building_path: &Path,
+) -> GenResult<IndexSummary> {
+    copy_dir(Path::new(&tenant::collection_path()), building_path)?;
+    PdfTimetableCollection::rename_files(building_path, &changes.renamed)?;
+    let replaced_files: Vec<String> = changes
+        .removed
+        .iter()
+        .chain(&changes.modified)
+        .cloned()
+        .collect();
+    PdfTimetableCollection::remove_files(building_path, &replaced_files)?;
+    let changed_files: Vec<(usize, &PathBuf)> = files
+        .iter()
+        .filter(|file| {
+            let path = file.to_string_lossy().to_string();
+            changes.added.contains(&path) || changes.modified.contains(&path)
+        })
+        .map(|file| (file_id(file), file))
+        .collect();
+    info!(
+        "Indexing {} changed files, removed {} files",
+        changed_files.len(),
+        changes.removed.len()
+    );
+    build_collections(&changed_files, building_path)
+}
+
+// A file that can't be parsed is reported in the summary, the other files are still indexed
+fn build_collections(
+    numbered_files: &[(usize, &PathBuf)],
+    collection_path: &Path,
+) -> GenResult<IndexSummary> {
+    let mut summary = IndexSummary::default();
+    refresh::report_total_files(numbered_files.len());
+    // The rayon threads don't have the operator of the refresh, so it's passed on to them
+    let tenant = tenant::current();
+    // Parse as many files at once as there are threads, so only a few parsed PDFs are in memory
+    for batch in numbered_files.chunks(rayon::current_num_threads()) {
+        let parsed_batch: Vec<Result<ParsedTripSheet, String>> = batch
+            .par_iter()
+            .map(|(file_id, file_path)| {
+                tenant::with_tenant(tenant.clone(), || {
+                    parse_trip_sheet_in_memory(file_path, *file_id)
+                })
+                .map_err(|err| err.to_string())
+            })
+            .collect();
+        // Written one at a time, files with the same valid from date extend the same collection
+        for ((file_id, file_path), parsed) in batch.iter().zip(parsed_batch) {
+            let written = parsed
+                .map_err(|err| err.into())
+                .and_then(|parsed| write_trip_sheet(collection_path, file_path, *file_id, parsed));
+            match written {
+                Ok((shifts_parsed, errored_shifts)) => {
+                    summary.files_indexed += 1;
+                    summary.shifts_parsed += shifts_parsed;
+                    summary.errored_shifts.extend(errored_shifts);
+                }
+                Err(err) => {
+                    error!(file = %file_path.display(), %err, "Failed to index file");
+                    summary.file_errors.push(FileError {
+                        file: file_path.to_string_lossy().to_string(),
+                        error: err.to_string(),
+                    });
+                }
+            }
+        }
+        refresh::report_summary(&summary);
+    }
+    // Serving nothing is worse than serving the previous collections
+    if !numbered_files.is_empty() && summary.files_indexed == 0 {
+        return Err(
+            "None of the timetable files could be indexed, keeping the current collections".into(),
+        );
+    }
+    Ok(summary)
+}
+
+pub struct ParsedTripSheet {
+    pub valid_from: Date,
+    pub shift_data_map: HashMap<String, ShiftData>,
+    pub shifts: Vec<Shift>,
+}
+
+// Find and parse all shifts in a PDF without writing anything
+#[instrument(name = "parse_file", skip_all, fields(file = %pdf_path.display(), file_id = file_id))]
+fn parse_trip_sheet_in_memory(pdf_path: &PathBuf, file_id: usize) -> GenResult<ParsedTripSheet> {
+    // Load the PDF document once, both finding and parsing the shifts use it
+    let doc = load_text_document(pdf_path)?;
+    let shift_data_map = load_shift_data(&doc, file_id, tenant::current().depot_of(pdf_path))?;
+    let mut shifts = parse_pdf(&doc, &shift_data_map)?;
+    let canonicalizer = LocationCanonicalizer::from_config(&Config::get());
+    for shift in &mut shifts {
+        canonicalizer.canonicalize_shift(shift);
+    }
+    let valid_from = shifts
         .first()
         .result_reason("No shifts found")?
         .starting_date;
-    let valid_from_string = valid_from_day.format(DATE_FORMAT).unwrap();
-    let mut output_path = PathBuf::from(format!("{}/{}", COLLECTION_PATH, valid_from_string));
-    save_extracted_shifts(output_path.clone(), parsed_shifts)?;
-    output_path.set_extension("json");
-    let pdf_collection: PdfTimetableCollection = if let Ok(file) = fs::read_to_string(&output_path)
-    {
-        info!("Extending existing collection {:?}", &output_path);
-        let mut pdf_collection: PdfTimetableCollection = serde_json::from_str(&file)?;
-        pdf_collection
-            .files
-            .insert(file_id, pdf_path.to_string_lossy().to_string());
-        pdf_collection.pages.extend(shift_data_map);
-        pdf_collection
-    } else {
-        info!("Writing new collection {:?}", &output_path);
-        PdfTimetableCollection {
-            valid_from: valid_from_day,
-            files: HashMap::from([(file_id, pdf_path.to_string_lossy().to_string())]),
-            pages: shift_data_map,
+    Ok(ParsedTripSheet {
+        valid_from,
+        shift_data_map,
+        shifts,
+    })
+}
+
+// Save the shifts of a parsed PDF and add it to the collection of its date,
+// returns the amount of shifts and the ones with parse errors
+fn write_trip_sheet(
+    collection_path: &Path,
+    pdf_path: &Path,
+    file_id: usize,
+    parsed: ParsedTripSheet,
+) -> Result<(usize, Vec<String>), Box<dyn Error>> {
+    let ParsedTripSheet {
+        valid_from: valid_from_day,
+        mut shift_data_map,
+        shifts: parsed_shifts,
+    } = parsed;
+    for shift in &parsed_shifts {
+        let Some(shift_data) = shift_data_map.get_mut(&shift_key(&shift.shift_nr)) else {
+            continue;
+        };
+        shift_data.valid_on = Some(shift.valid_on.clone());
+        // A shift can span multiple pages, keep the times and type of the page that has them
+        if shift_data.times.is_none() || shift.times.start_time.is_some() {
+            shift_data.times = Some(shift.times.clone());
+            shift_data.shift_type = shift.shift_type.clone();
         }
-    };
+    }
+    let valid_from_string = valid_from_day.format(DATE_FORMAT)?;
+    let output_path = collection_path.join(format!("{valid_from_string}.json"));
+    let shift_count = parsed_shifts.len();
+    let errored_shifts: Vec<String> = parsed_shifts
+        .iter()
+        .filter(|shift| shift.parse_error.is_some())
+        .map(|shift| shift.shift_nr.clone())
+        .collect();
+    let mut pdf_collection: PdfTimetableCollection =
+        if let Ok(file) = fs::read_to_string(&output_path) {
+            info!("Extending existing collection {:?}", &output_path);
+            let mut pdf_collection: PdfTimetableCollection = serde_json::from_str(&file)?;
+            pdf_collection
+                .files
+                .insert(file_id, pdf_path.to_string_lossy().to_string());
+            pdf_collection
+        } else {
+            info!("Writing new collection {:?}", &output_path);
+            PdfTimetableCollection {
+                valid_from: valid_from_day,
+                files: HashMap::from([(file_id, pdf_path.to_string_lossy().to_string())]),
+                pages: HashMap::new(),
+                variants: HashMap::new(),
+                shifts: HashMap::new(),
+            }
+        };
+    // A shift number that is already in the collection for other days, like a Saturday book
+    // next to the weekday book, is kept as a variant instead of replacing the shift
+    let mut variant_names: HashMap<String, String> = HashMap::new();
+    for (shift_number, shift_data) in shift_data_map {
+        let is_variant = pdf_collection
+            .pages
+            .get(&shift_number)
+            .and_then(|existing| existing.valid_on.as_ref())
+            .zip(shift_data.valid_on.as_ref())
+            .is_some_and(|(existing, new)| existing != new);
+        if !is_variant {
+            pdf_collection.pages.insert(shift_number, shift_data);
+            continue;
+        }
+        let valid_on = shift_data.valid_on.clone().unwrap_or(ShiftValid::Unknown);
+        variant_names.insert(shift_number.clone(), variant_key(&shift_number, &valid_on));
+        let variants = pdf_collection.variants.entry(shift_number).or_default();
+        variants.retain(|variant| variant.valid_on != shift_data.valid_on);
+        variants.push(shift_data);
+    }
+    // Shifts stored as a variant are saved under their variant key instead of their number
+    for shift in parsed_shifts {
+        let key = shift_key(&shift.shift_nr);
+        let key = variant_names.remove(&key).unwrap_or(key);
+        pdf_collection.shifts.insert(key, Arc::new(shift));
+    }
 
     // Serialize the index into pretty JSON.
     let index_json = serde_json::to_string_pretty(&pdf_collection)?;
     fs::write(&output_path, index_json)?;
-    Ok(())
+    Ok((shift_count, errored_shifts))
 }
 
-fn load_shift_data(path: &PathBuf, file_id: usize) -> GenResult<HashMap<String, ShiftData>> {
-    let doc = Document::load(&path)?;
-
+// The shifts of a PDF by their key, with the pages they are on
+fn load_shift_data(
+    doc: &Document,
+    file_id: usize,
+    depot: Option<String>,
+) -> GenResult<HashMap<String, ShiftData>> {
     // Define a regex pattern that finds "Dienst" followed by a trip number.
     let re = Regex::new(r"Dienst\s*(\b[A-Z]{1,2} \d{4}\b)")?;
     let mut index: HashMap<String, ShiftData> = HashMap::new();
@@ -161,12 +507,16 @@ fn load_shift_data(path: &PathBuf, file_id: usize) -> GenResult<HashMap<String,
                 .collect();
             if !shift_number.is_empty() {
                 index
-                    .entry(shift_number)
+                    .entry(shift_key(&shift_name))
                     .and_modify(|shift_data| shift_data.pages.push(*page_num))
                     .or_insert(ShiftData {
                         pages: vec![*page_num],
                         file_id,
                         shift_prefix,
+                        depot: depot.clone(),
+                        times: None,
+                        shift_type: None,
+                        valid_on: None,
                     });
             }
         }
@@ -174,25 +524,23 @@ fn load_shift_data(path: &PathBuf, file_id: usize) -> GenResult<HashMap<String,
     Ok(index)
 }
 
-fn save_extracted_shifts(path: PathBuf, shifts: Vec<Shift>) -> GenResult<()> {
-    match std::fs::create_dir(&path) {
-        Ok(_) => (),
-        Err(kind) if kind.kind() == io::ErrorKind::AlreadyExists => (),
-        Err(kind) => return Err(Box::new(kind)),
-    };
-    for shift in shifts {
-        let shift_json = serde_json::to_string_pretty(&shift)?;
-        let shift_number: String = shift
-            .shift_nr
-            .chars()
-            .filter(|character| character.is_numeric())
-            .collect();
-        let mut shift_path = path.clone();
-        shift_path.push(shift_number);
-        shift_path.set_extension("json");
-        fs::write(shift_path, shift_json)?;
-    }
-    Ok(())
+// Parse a date given by a client. dd-mm-yyyy is tried first, then yyyy-mm-dd.
+// The year position differs, so a date can never match both formats
+pub fn parse_date(date_string: &str) -> Option<Date> {
+    Date::parse(date_string, DATE_FORMAT)
+        .or_else(|_| Date::parse(date_string, ISO_DATE_FORMAT))
+        .ok()
+}
+
+// A date query parameter that is missing is fine, one that doesn't parse is refused
+// instead of silently falling back to today
+pub fn parse_optional_date(date_string: &Option<String>) -> Result<Option<Date>, HermesError> {
+    date_string
+        .as_ref()
+        .map(|date_string| {
+            parse_date(date_string).ok_or(HermesError::DateParse(date_string.clone()))
+        })
+        .transpose()
 }
 
 // load all pdf_collection files. And determine which one is current
@@ -206,7 +554,7 @@ fn get_valid_timetables(
         None => OffsetDateTime::now_utc().date(),
     };
     let mut upcoming_timetables: Vec<Date> = vec![];
-    let mut active_timetables: Vec<PdfTimetableCollection> = vec![];
+    let mut active_timetables: ValidTimetables = vec![];
     // Loop over all files in the collection folder
     for timetable_collection in collections {
         //if the current collection date is higher than the last but lower than the system date. Make this the most recent one
@@ -220,118 +568,504 @@ fn get_valid_timetables(
         }
     }
 
-    let next_timetable = upcoming_timetables.first().cloned();
+    let next_timetable = upcoming_timetables.into_iter().min();
+
+    // Oldest first, so the newest timetable is at the end. Don't rely on the order they were loaded in
+    active_timetables.sort_by_key(|timetable| timetable.valid_from);
 
     Ok((active_timetables, next_timetable))
 }
 
-// Recursive function to find a valid shift
+// Find a shift in the newest timetable valid on the date, following the aliases from the configuration.
+// The prefix picks the depot when several use the number, it may be empty
 fn find_shift(
+    shift_prefix: &str,
     shift_number: &str,
-    valid_timetables: &mut Vec<PdfTimetableCollection>,
-) -> Option<(PdfTimetableCollection, ShiftData)> {
-    let current_timetable = match valid_timetables.pop() {
-        Some(timetable) => timetable,
-        None => return None, // If there are no more valid timetables while this check runs, the shift is not available
-    };
-    match current_timetable.clone().pages.get(shift_number) {
-        Some(shift) => Some((current_timetable, shift.clone())),
-        None => find_shift(shift_number, valid_timetables),
+    date: Date,
+) -> GenResult<Option<(Arc<PdfTimetableCollection>, ShiftData, String)>> {
+    let config = Config::get();
+    PdfTimetableCollection::find_shift(shift_prefix, shift_number, date, |timetable| {
+        config.shift_alias(shift_number, timetable)
+    })
+}
+
+// In strict mode a number without a prefix that several depots use is refused, with the shifts
+// the client can choose from
+fn ambiguous_shift(shift_prefix: &str, shift_number: &str, date: Date) -> Option<HermesError> {
+    if !Config::get().strict_shift_lookup || !shift_prefix.is_empty() {
+        return None;
     }
+    let candidates = PdfTimetableCollection::shift_candidates(shift_number, date).ok()?;
+    (candidates.len() > 1).then(|| HermesError::AmbiguousShift {
+        shift: shift_number.to_string(),
+        candidates,
+    })
 }
 
 fn handle_refresh_request() -> HttpResponse {
-    _ = load_pdf_and_index();
+    if Config::get().role == ClusterRole::Replica {
+        return HermesError::ReadOnlyReplica.error_response();
+    }
+    if refresh::start_refresh(true) {
+        HttpResponse::Accepted().body("Indexing started, follow it at /admin/refresh/status")
+    } else {
+        HermesError::RefreshRunning.error_response()
+    }
+}
 
-    return HttpResponse::Accepted().body("Shifts sucessfully indexed");
+#[derive(Deserialize)]
+struct ShiftPath {
+    shift_number: String,
 }
 
 #[get("/shift/{shift_number}")]
-async fn get_shift(request: web::Path<String>, query: web::Query<ShiftQuery>) -> impl Responder {
-    info!("Got request for {}", request);
-    let custom_date_option = query
-        .date
-        .as_ref()
-        .and_then(|date_string| Date::parse(date_string, DATE_FORMAT).ok());
+async fn get_shift(
+    http_request: HttpRequest,
+    path: web::Path<ShiftPath>,
+    query: web::Query<ShiftQuery>,
+) -> impl Responder {
+    let request = path.into_inner().shift_number;
+    let custom_date_option = match parse_optional_date(&query.date) {
+        Ok(date) => date,
+        Err(err) => return err.error_response(),
+    };
 
     let request_uppercase = request.to_uppercase();
 
     // Handle specific request
     if request_uppercase == "REFRESH" {
+        // Refreshing is an admin action, but this route is outside the admin scope
+        if !auth::is_admin(http_request.headers()) {
+            warn!(
+                "Refused unauthenticated refresh through {}",
+                http_request.path()
+            );
+            return HermesError::Unauthorized.error_response();
+        }
         return handle_refresh_request();
     } else if request_uppercase == "INDEX" {
-        return handle_index_request(custom_date_option);
+        return handle_index_request(custom_date_option, &query.index_filters);
     } else if request_uppercase == "STATS" {
-        return handle_stats_request(custom_date_option);
+        return handle_stats_request(custom_date_option, query.format.as_deref());
     }
+    serve_shift(
+        &http_request,
+        &request_uppercase,
+        custom_date_option,
+        &query,
+    )
+    .await
+}
 
-    let mut valid_timetables = match get_valid_timetables(custom_date_option) {
-        Ok(result) => result.0,
-        Err(err) => return return_error(err.to_string()),
-    };
-
+// Serve a shift in the format of the extension of the request, like 1234.JSON. The request is
+// uppercase and the date already parsed
+async fn serve_shift(
+    http_request: &HttpRequest,
+    request_uppercase: &str,
+    custom_date_option: Option<Date>,
+    query: &ShiftQuery,
+) -> HttpResponse {
     let mut shift_split = request_uppercase.split(".");
-    let shift = shift_split.next().unwrap_or(&request_uppercase);
+    let shift = shift_split.next().unwrap_or(request_uppercase);
     let request_extension_option = shift_split.next();
 
     let shift_prefix: String = shift.chars().filter(|c| c.is_alphabetic()).collect();
-    let numeric_shift_number: String = shift.chars().filter(|c| c.is_numeric()).collect();
-
-    let (shift_collection, shift_data) =
-        match find_shift(&numeric_shift_number, &mut valid_timetables) {
-            Some(shift) => shift,
-            None => {
-                return HttpResponse::NotFound()
-                    .body(format!("<h1>Sorry, shift {shift} was not found</h1>"));
+    let requested_shift_number: String = shift.chars().filter(|c| c.is_numeric()).collect();
+
+    let not_found = || {
+        HermesError::ShiftNotFound {
+            shift: shift.to_string(),
+            suggestions: index::similar_shifts(shift, custom_date_option),
+        }
+        .error_response()
+    };
+    let missing_key =
+        response_cache::cache_key("missing", custom_date_option, &requested_shift_number);
+    if response_cache::is_known_missing(&missing_key) {
+        return not_found();
+    }
+
+    let date = custom_date_option.unwrap_or(OffsetDateTime::now_utc().date());
+    let earliest_valid_from = match PdfTimetableCollection::earliest_valid_from() {
+        Ok(earliest_valid_from) => earliest_valid_from,
+        Err(err) => return return_error(err.to_string()),
+    };
+    if earliest_valid_from.is_none_or(|earliest| earliest > date) {
+        return no_timetable_response(date, earliest_valid_from);
+    }
+
+    if let Some(err) = ambiguous_shift(&shift_prefix, &requested_shift_number, date) {
+        return err.error_response();
+    }
+    let (shift_collection, shift_data, found_key) =
+        match find_shift(&shift_prefix, &requested_shift_number, date) {
+            Ok(Some(shift)) => shift,
+            Ok(None) => {
+                response_cache::remember_missing(missing_key);
+                return not_found();
             }
+            Err(err) => return return_error(err.to_string()),
         };
+    let aliased_from = (key_number(&found_key) != requested_shift_number).then(|| {
+        info!("Shift {requested_shift_number} is an alias of {found_key}");
+        requested_shift_number.clone()
+    });
 
-    // Check for correct shift prefix
-    if !shift_prefix.is_empty() && shift_prefix != shift_data.shift_prefix {
-        // Add exceptions for the shift prefix check
-        if !(shift_prefix == "GM" && shift_data.shift_prefix == "G"
-            || shift_prefix == "G" && shift_data.shift_prefix == "GM")
-        {
-            return HttpResponse::NotAcceptable()
-            .body(format!("<h1>Incorrect shift type specified.</h1> <br><h2>Please remove \"{shift_prefix}\" or change request to \"{}{numeric_shift_number}\"</h2>",shift_data.shift_prefix));
+    // Check for correct shift prefix, the configuration can mark prefixes as equivalent
+    if !shift_prefix.is_empty()
+        && !tenant::current().prefixes_equivalent(&shift_prefix, &shift_data.shift_prefix)
+    {
+        return HermesError::IncorrectPrefix {
+            suggestion: format!(
+                "{}{}",
+                tenant::current().canonical_prefix(&shift_data.shift_prefix),
+                key_number(&found_key)
+            ),
+            prefix: shift_prefix,
         }
+        .error_response();
+    }
+
+    // With a date, serve the variant of the shift that runs on that weekday, holidays count as Sunday.
+    // When none of them does, the shift is still served with a notice explaining why
+    let mut variant_notice = None;
+    let (shift_data, found_key) = match custom_date_option {
+        Some(date) => {
+            match shift_collection.variant_on(&found_key, holidays::service_weekday(date)) {
+                Some((variant_name, variant)) => (variant, variant_name),
+                None => {
+                    variant_notice = Some(format!(
+                        "Shift {found_key} does not run on {} {}, this variant runs on {:?}",
+                        holidays::holiday(date).unwrap_or(date.weekday().to_string()),
+                        date.format(DATE_FORMAT).unwrap_or_default(),
+                        shift_data.valid_on.clone().unwrap_or(ShiftValid::Unknown)
+                    ));
+                    (shift_data, found_key)
+                }
+            }
+        }
+        None => (shift_data, found_key),
+    };
+
+    // Checked before the validators, so an invalid request is refused instead of getting a 304
+    let page = match query.page.as_deref().map(str::parse::<u16>) {
+        None => 0,
+        Some(Ok(page)) => page,
+        Some(Err(_)) => {
+            return HermesError::BadRequest("page should be a page number, like 0".to_string())
+                .error_response();
+        }
+    };
+    let two_up = match query.layout.as_deref() {
+        None => false,
+        Some(layout) if layout.eq_ignore_ascii_case("2up") => true,
+        Some(layout) => {
+            return HermesError::BadRequest(format!("Unknown layout \"{layout}\", supported: 2up"))
+                .error_response();
+        }
+    };
+    let print_variant = match query.variant.as_deref() {
+        None => false,
+        Some(variant) if variant.eq_ignore_ascii_case("print") => true,
+        Some(variant) => {
+            return HermesError::BadRequest(format!(
+                "Unknown variant \"{variant}\", supported: print"
+            ))
+            .error_response();
+        }
+    };
+
+    let english = english::wants_english(http_request, query.lang.as_deref());
+    let representation = format!(
+        "{}/{}/{}/{}/{}",
+        request_extension_option.unwrap_or("PDF"),
+        if english { "en" } else { "nl" },
+        query.layout.as_deref().unwrap_or_default(),
+        query.variant.as_deref().unwrap_or_default(),
+        query.page.as_deref().unwrap_or_default()
+    );
+    let validators = Validators::for_shift(&shift_collection, &shift_data, &representation);
+    if validators.not_modified(http_request) {
+        return validators.not_modified_response();
     }
 
-    if let Some(shift_extension) = request_extension_option
+    let mut response = if let Some(shift_extension) = request_extension_option
         && shift_extension == "JSON"
     {
-        info!("Got JSON request for {request_uppercase}");
-        match find_json_shift(numeric_shift_number, shift_collection.valid_from) {
+        match shift_json_with_changes(
+            found_key,
+            &shift_collection,
+            &shift_data.shift_prefix,
+            aliased_from,
+            english,
+        ) {
+            // The field names depend on the Accept-Language header when there is no lang parameter
             Ok(json) => HttpResponse::Ok()
                 .content_type(ContentType::json())
+                .insert_header((VARY, "Accept-Language"))
                 .body(json),
             Err(err) => return_error(err.to_string()),
         }
-    } else {
-        info!("Got PDF request for shift {request_uppercase}");
-        match find_pdf_shift(&shift_collection, shift_data) {
-            Ok(bytes) => HttpResponse::Ok()
-                .content_type("application/pdf")
-                .body(bytes),
+    } else if request_extension_option == Some("TXT") {
+        match load_shift(found_key, shift_collection.valid_from) {
+            Ok(shift) => HttpResponse::Ok()
+                .content_type(ContentType::plaintext())
+                .body(render::shift_to_text(&shift)),
+            Err(err) => return_error(err.to_string()),
+        }
+    } else if request_extension_option == Some("GEOJSON") {
+        match load_shift(found_key, shift_collection.valid_from)
+            .and_then(|shift| geojson::shift_to_geojson(&shift))
+        {
+            Ok(geojson) => HttpResponse::Ok()
+                .content_type("application/geo+json")
+                .body(geojson),
+            Err(err) => return_error(err.to_string()),
+        }
+    } else if request_extension_option == Some("ICS") {
+        // The events repeat until the next timetable takes over
+        let next_timetable = PdfTimetableCollection::get_timetables()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|timetable| timetable.valid_from)
+            .find(|valid_from| *valid_from > shift_collection.valid_from);
+        match load_shift(found_key, shift_collection.valid_from) {
+            Ok(shift) => HttpResponse::Ok()
+                .content_type("text/calendar; charset=utf-8")
+                .body(ical::shift_to_ical(
+                    &shift,
+                    shift_collection.valid_from,
+                    next_timetable,
+                )),
+            Err(err) => return_error(err.to_string()),
+        }
+    } else if request_extension_option == Some("MD") {
+        match load_shift(found_key, shift_collection.valid_from) {
+            Ok(shift) => HttpResponse::Ok()
+                .content_type("text/markdown; charset=utf-8")
+                .body(render::shift_to_markdown(&shift)),
             Err(err) => return_error(err.to_string()),
         }
+    } else if request_extension_option == Some("PNG") {
+        let bytes = match cached_shift_pdf(&shift_collection, shift_data.clone(), &found_key).await
+        {
+            Ok(bytes) => bytes,
+            Err(err) => return HermesError::PdfLoad(err).error_response(),
+        };
+        match web::block(move || preview::render_page(&bytes, page)).await {
+            Ok(Ok(png)) => HttpResponse::Ok().content_type("image/png").body(png),
+            Ok(Err(err)) => err.error_response(),
+            Err(err) => return_error(err.to_string()),
+        }
+    } else if request_extension_option == Some("HTML") {
+        match load_shift(found_key, shift_collection.valid_from).and_then(|parsed| {
+            viewer::shift_to_html(
+                &parsed,
+                shift,
+                query.date.as_deref(),
+                variant_notice.as_deref(),
+            )
+        }) {
+            Ok(html) => HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body(html),
+            Err(err) => return_error(err.to_string()),
+        }
+    } else {
+        let mut response = HttpResponse::Ok();
+        response.content_type("application/pdf");
+        if let Some(aliased_from) = &aliased_from {
+            response.insert_header((SHIFT_ALIAS_HEADER, aliased_from.as_str()));
+        }
+        // The caches only hold the original PDF, layouts and variants are applied on every request
+        let bytes = match cached_shift_pdf(&shift_collection, shift_data, &found_key).await {
+            Ok(bytes) => bytes,
+            Err(err) => return HermesError::PdfLoad(err).error_response(),
+        };
+        if !two_up && !print_variant {
+            response.body(bytes)
+        } else {
+            let processed = web::block(move || {
+                let mut bytes = bytes;
+                if print_variant {
+                    bytes = grayscale::print_variant(&bytes).map_err(|err| err.to_string())?;
+                }
+                if two_up {
+                    bytes = imposition::two_up(&bytes).map_err(|err| err.to_string())?;
+                }
+                Ok::<Vec<u8>, String>(bytes)
+            });
+            match processed.await {
+                Ok(Ok(bytes)) => response.body(bytes),
+                Ok(Err(err)) => return_error(err),
+                Err(err) => return_error(err.to_string()),
+            }
+        }
+    };
+    if response.status().is_success() {
+        validators.add_to(&mut response);
+        if let Some(variant_notice) = variant_notice
+            && let Ok((name, value)) = (SHIFT_VARIANT_NOTICE_HEADER, variant_notice).try_into_pair()
+        {
+            response.headers_mut().insert(name, value);
+        }
     }
+    response
+}
+
+// Explain that the date is not covered, instead of saying the shift doesn't exist
+fn no_timetable_response(date: Date, earliest: Option<Date>) -> HttpResponse {
+    let explanation = match earliest {
+        Some(earliest) => format!(
+            "The earliest timetable is valid from {}",
+            earliest.format(DATE_FORMAT).unwrap_or_default()
+        ),
+        None => "No timetables have been loaded".to_string(),
+    };
+    HermesError::NoTimetable {
+        date: date.format(DATE_FORMAT).unwrap_or_default(),
+        explanation,
+    }
+    .error_response()
+}
+
+#[derive(Serialize)]
+struct ShiftExists {
+    exists: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonical_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timetable: Option<String>,
+}
+
+// Only looks at the loaded collections, so it is cheap enough to call on every key press
+#[get("/shift/{shift_number}/exists")]
+async fn shift_exists(path: web::Path<ShiftPath>, query: web::Query<ShiftQuery>) -> impl Responder {
+    let request = path.into_inner().shift_number.to_uppercase();
+    let date = match parse_optional_date(&query.date) {
+        Ok(date) => date.unwrap_or(OffsetDateTime::now_utc().date()),
+        Err(err) => return err.error_response(),
+    };
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    let found = match find_shift(&shift_prefix, &shift_number, date) {
+        Ok(found) => found.filter(|(_, shift_data, _)| {
+            shift_prefix.is_empty()
+                || tenant::current().prefixes_equivalent(&shift_prefix, &shift_data.shift_prefix)
+        }),
+        Err(err) => return return_error(err.to_string()),
+    };
+    let (mut response, body) = match found {
+        Some((collection, shift_data, _)) => (
+            HttpResponse::Ok(),
+            ShiftExists {
+                exists: true,
+                canonical_prefix: Some(
+                    tenant::current().canonical_prefix(&shift_data.shift_prefix),
+                ),
+                prefix: Some(shift_data.shift_prefix),
+                timetable: collection.valid_from.format(DATE_FORMAT).ok(),
+            },
+        ),
+        None => (
+            HttpResponse::NotFound(),
+            ShiftExists {
+                exists: false,
+                prefix: None,
+                canonical_prefix: None,
+                timetable: None,
+            },
+        ),
+    };
+    response
+        .content_type(ContentType::json())
+        .body(serde_json::to_string(&body).unwrap())
 }
 
 fn return_error(error: String) -> HttpResponse {
-    HttpResponse::InternalServerError().body(format!(
-        "<h1>Sorry, something went wrong loading that shift.</h1><br>error: {}",
-        error.to_string()
-    ))
+    HermesError::Internal(error).error_response()
 }
 
-fn find_json_shift(shift_number: String, shift_timetable_date: Date) -> GenResult<String> {
-    let filepath = format!(
-        "{COLLECTION_PATH}/{date_str}/{shift_number}.json",
-        date_str = shift_timetable_date.format(DATE_FORMAT)?
-    );
-    let file_json = fs::read_to_string(filepath)?;
-    Ok(file_json)
+fn load_shift(shift_number: String, shift_timetable_date: Date) -> GenResult<Shift> {
+    PdfTimetableCollection::load_shift(&shift_number, shift_timetable_date)
+}
+
+// Load the shift JSON and add whether it changed compared to the previous timetable
+fn shift_json_with_changes(
+    shift_number: String,
+    shift_collection: &PdfTimetableCollection,
+    shift_prefix: &str,
+    aliased_from: Option<String>,
+    english: bool,
+) -> GenResult<String> {
+    let shift = load_shift(shift_number.clone(), shift_collection.valid_from)?;
+    let changes = changes_since_previous(&shift_number, shift_collection, &shift);
+    // The shift is still useful without the trips, so a broken feed doesn't fail the request
+    let gtfs_trips = gtfs::link_trips(&shift).unwrap_or_else(|err| {
+        error!("Failed to link shift {shift_number} to GTFS trips: {err}");
+        vec![]
+    });
+    let references = references::link_references(&shift, shift_collection);
+    let canonical_prefix = tenant::current().canonical_prefix(shift_prefix);
+    if english {
+        return Ok(serde_json::to_string_pretty(&ShiftResponse {
+            shift: EnglishShift::from(shift),
+            references,
+            changes,
+            canonical_prefix,
+            aliased_from,
+            gtfs_trips,
+        })?);
+    }
+    let response = ShiftResponse {
+        shift,
+        references,
+        changes,
+        canonical_prefix,
+        aliased_from,
+        gtfs_trips,
+    };
+    Ok(serde_json::to_string_pretty(&response)?)
+}
+
+// The PDF of a shift, memory is tried first, then the cache shared with the other instances
+async fn cached_shift_pdf(
+    shift_collection: &PdfTimetableCollection,
+    shift_data: ShiftData,
+    key: &str,
+) -> Result<Vec<u8>, String> {
+    let valid_from = shift_collection.valid_from;
+    if let Some(bytes) = response_cache::get_pdf(valid_from, key) {
+        return Ok(bytes);
+    }
+    let bytes = match cache::get_pdf(valid_from, key) {
+        Some(bytes) => bytes,
+        None => {
+            let bytes = assemble_pdf_shift(shift_collection.clone(), shift_data).await?;
+            cache::store_pdf(valid_from, key, &bytes);
+            bytes
+        }
+    };
+    response_cache::store_pdf(valid_from, key, &bytes);
+    Ok(bytes)
+}
+
+// Assemble the PDF on the blocking thread pool, waiting for a free slot when too many are in progress
+async fn assemble_pdf_shift(
+    shift_timetable_collection: PdfTimetableCollection,
+    shift_data: ShiftData,
+) -> Result<Vec<u8>, String> {
+    let _permit = PDF_SEMAPHORE
+        .acquire()
+        .await
+        .map_err(|err| err.to_string())?;
+    web::block(move || {
+        find_pdf_shift(&shift_timetable_collection, shift_data).map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())?
 }
 
 fn find_pdf_shift(
@@ -345,56 +1079,281 @@ fn find_pdf_shift(
         .result_reason("No PDF found")?
         .to_owned();
 
-    let shift_pages = shift_data.pages;
     let full_pdf = QPdf::read(shift_pdf_path)?;
     let shift_pdf = QPdf::empty();
-    // Keep only the pages we want
-    for page in shift_pages {
-        let extracted_pages = full_pdf
+    copy_shift_pages(&full_pdf, &shift_pdf, &shift_data.pages)?;
+
+    Ok(shift_pdf.writer().write_to_memory()?)
+}
+
+// Append the pages of a shift to the end of another PDF. The page contents are only copied when
+// the target is written, so the source has to be kept open until then
+fn copy_shift_pages(source: &QPdf, target: &QPdf, pages: &[u32]) -> GenResult<()> {
+    for page in pages {
+        let extracted_page = source
             .get_page(page - 1)
             .result_reason("Shift page not found")?;
-        shift_pdf.add_page(extracted_pages, false)?;
+        target.add_page(extracted_page, false)?;
     }
+    Ok(())
+}
 
-    Ok(shift_pdf.writer().write_to_memory()?)
+// Parse the source PDFs again, only keeping the ones that belong to the corrupt collections
+fn repair_collections(quarantined: &[String]) -> GenResult<()> {
+    let files = get_timetable_files()?;
+    for file_path in &files {
+        let file_id = file_id(file_path);
+        let Ok(parsed) = parse_trip_sheet_in_memory(file_path, file_id) else {
+            continue;
+        };
+        let valid_from = parsed.valid_from.format(DATE_FORMAT)?;
+        if quarantined.contains(&valid_from) {
+            warn!("Rebuilding collection {valid_from} from {file_path:?}");
+            write_trip_sheet(
+                Path::new(&tenant::collection_path()),
+                file_path,
+                file_id,
+                parsed,
+            )?;
+        }
+    }
+    PdfTimetableCollection::load_timetables_from_disk()?;
+    Ok(())
+}
+
+// Compare the stored digests with the current ones to see if the files need to be indexed again
+fn digests_require_index(
+    previous_digests_option: Option<FileDigests>,
+    current_digests: &FileDigests,
+) -> bool {
+    let Some(previous_digests) = previous_digests_option else {
+        error!("Could not find previous hash, reindexing");
+        return true;
+    };
+    if combined_digest(&previous_digests) != combined_digest(current_digests) {
+        warn!("Hash is changed, reindexing files");
+        return true;
+    }
+    let renames = renamed_files(&previous_digests, current_digests);
+    if renames.is_empty() {
+        info!("Hash is the same, so wont reindex");
+        return false;
+    }
+    // Only the file names changed, so the collections just need the new paths
+    info!("Files were renamed, updating collections: {renames:?}");
+    PdfTimetableCollection::rename_files(Path::new(&tenant::collection_path()), &renames).is_err()
+}
+
+fn index_on_startup() {
+    // Hash the contents of all files in the folder. If any content changes, it will reindex
+    let current_digests = match get_timetable_files().and_then(|files| compute_digests(&files)) {
+        Ok(current_digests) => current_digests,
+        Err(err) => {
+            error!("Failed to read the timetable files: {err}");
+            health::report_startup_error(format!("Failed to read the timetable files: {err}"));
+            return;
+        }
+    };
+    // Debug builds always reindex
+    let needs_index =
+        cfg!(debug_assertions) || digests_require_index(load_stored_digests(), &current_digests);
+    if needs_index {
+        if let Err(err) = load_pdf_and_index(cfg!(debug_assertions)) {
+            error!("Failed to index trip sheets: {err}");
+            health::report_startup_error(format!("Failed to index trip sheets: {err}"));
+        }
+    } else if let Err(err) = store_digests(&current_digests) {
+        warn!("Failed to store file digests: {err}");
+    }
+}
+
+// Index (if needed) and load the collection of the current tenant
+fn load_on_startup() {
+    // Replicas never index, they only read the collection written by the indexer
+    if Config::get().role == ClusterRole::Replica {
+        info!("Running as read-only replica, skipping indexing");
+    } else {
+        info!("Indexing trip sheets");
+        index_on_startup();
+    }
+    // Without collections the server still starts, it reports itself as degraded until a refresh works
+    let quarantined = match PdfTimetableCollection::load_timetables_from_disk() {
+        Ok(quarantined) => quarantined,
+        Err(err) => {
+            error!("Failed to load collections, starting without timetables: {err}");
+            health::report_startup_error(format!("Failed to load collections: {err}"));
+            return;
+        }
+    };
+    if quarantined.is_empty() {
+        return;
+    }
+    let repaired = Config::get().repair_corrupt_collections
+        && match repair_collections(&quarantined) {
+            Ok(_) => true,
+            Err(err) => {
+                error!("Failed to repair corrupt collections: {err}");
+                false
+            }
+        };
+    if !repaired {
+        for collection in &quarantined {
+            health::report_startup_error(format!("Collection {collection} is corrupt"));
+        }
+    }
+}
+
+// Routes which are served for every operator
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .configure(api::routes)
+        .service(viewer::landing_page)
+        .service(health::healthz)
+        .service(health::readyz)
+        .service(statistics::statistics_page)
+        .service(statistics_history)
+        .service(statistics::prometheus_metrics)
+        .service(errored_shifts_page)
+        .service(export::export_shifts_ndjson)
+        .service(export::export_shifts_json)
+        .service(export::export_gtfs)
+        .service(coverage::coverage)
+        .service(timetables::timetables)
+        .service(timetables::timetable_source)
+        .service(search::search)
+        .service(roster::roster_pdf)
+        .service(shift_exists)
+        .service(calendar::calendar)
+        .service(route::route)
+        .service(parse_errors::shift_parse_errors)
+        .service(diff::diff_timetables)
+        .service(diff::diff)
+        .service(batch::get_shifts)
+        .service(get_shift);
+}
+
+// Admin routes which are served for every operator, below /admin
+fn admin_routes(config: &mut web::ServiceConfig) {
+    config
+        .service(admin::index_status)
+        .service(admin::admin_refresh)
+        .service(upload::upload)
+        .service(admin::delete_timetable)
+        .service(admin::verify)
+        .service(refresh::refresh_status)
+        .service(parse_errors::admin_parse_errors);
+}
+
+// Every route of the server. The admin token is required by the admin scopes themselves, so
+// the routing decides what is an admin request and not the spelling of the path
+fn app_routes(config: &mut web::ServiceConfig) {
+    config
+        .service(
+            web::scope("/op/{operator}")
+                .wrap_fn(tenant::scope_request)
+                .service(
+                    web::scope("/admin")
+                        .wrap_fn(auth::require_admin_token)
+                        .configure(admin_routes),
+                )
+                .configure(routes),
+        )
+        .service(get_version)
+        .service(
+            web::scope("/admin")
+                .wrap_fn(auth::require_admin_token)
+                .service(reload_config)
+                .configure(admin_routes),
+        )
+        .configure(routes);
+}
+
+const SHUTDOWN_REFRESH_TIMEOUT: Duration = Duration::from_secs(60);
+
+// RUST_LOG takes precedence over the configured log level, without either only errors are logged.
+// Spans are logged with their duration when they close, so slow PDFs show up at the info level.
+// Logs go to stderr, stdout has the access log
+fn init_logging() {
+    let config = Config::get();
+    let filter = env::var("RUST_LOG")
+        .ok()
+        .or(config.log_level.clone())
+        .and_then(|filters| EnvFilter::try_new(filters).ok())
+        .unwrap_or_else(|| EnvFilter::new("error"));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(io::stderr);
+    if config.log_json {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    pretty_env_logger::init();
-    // Load shift data
-    info!("Indexing trip sheets");
-    // Get the hash of all files in the folder. If anything changes, the hash changes and so it will reindex
-    let mut s = DefaultHasher::new();
-    let files = get_timetable_files().expect("Failed to get timetable files");
-    files.hash(&mut s);
-    let current_hash = s.finish();
-    let _previous_hash_option = fs::read("pdf_hash")
-        .ok()
-        .and_then(|bytes| Some(u64::from_le_bytes(bytes.try_into().unwrap())));
-    #[cfg(not(debug_assertions))]
-    {
-        if let Some(previous_hash) = _previous_hash_option {
-            if previous_hash != current_hash {
-                warn!("Hash is changed, reindexing files");
-                load_pdf_and_index();
-            } else {
-                info!("Hash is the same, so wont reindex");
+    // The configuration is loaded first, because it can set the log level
+    let config_result = Config::reload();
+    init_logging();
+    if let Err(err) = config_result {
+        error!("Failed to load configuration, using defaults: {err}");
+    }
+    for tenant in tenant::all() {
+        info!("Loading operator {}", tenant.name);
+        tenant::with_tenant(tenant, load_on_startup);
+    }
+
+    actix_web::rt::spawn(reload_on_sighup());
+    cache::start_change_listener();
+    if Config::get().role == ClusterRole::Replica {
+        for tenant in tenant::all() {
+            actix_web::rt::spawn(tenant::in_tenant(tenant, cluster::watch_collection()));
+        }
+    } else {
+        watcher::watch_book_paths();
+    }
+
+    // Save a snapshot of the statistics every day, the first tick completes immediately so skip it
+    actix_web::rt::spawn(async {
+        let mut interval = actix_web::rt::time::interval(STATISTICS_SNAPSHOT_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            for tenant in tenant::all() {
+                if let Err(err) = tenant::with_tenant(tenant, record_statistics_snapshot) {
+                    warn!("Failed to save daily statistics snapshot: {err}");
+                }
             }
-        } else {
-            error!("Could not find previous hash, reindexing");
-            load_pdf_and_index();
         }
+    });
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap_fn(error::negotiate_errors)
+            .wrap_fn(metrics::record_request)
+            .wrap_fn(request_log::log_request)
+            .configure(app_routes)
+    });
+    // Use the socket from systemd socket activation if available
+    let server = match systemd::activated_listener() {
+        Some(listener) => {
+            info!("Using socket from systemd");
+            server.listen(listener)?
+        }
+        None => server.bind(&Config::get().bind_address)?,
     }
-    #[cfg(debug_assertions)]
-    {
-        load_pdf_and_index().unwrap();
+    .run();
+    if let Err(err) = systemd::notify("READY=1") {
+        warn!("Failed to notify systemd: {err}");
     }
-    let _ = fs::write("pdf_hash", current_hash.to_le_bytes());
-    PdfTimetableCollection::load_timetables_from_disk().unwrap();
-
-    HttpServer::new(move || App::new().service(get_shift))
-        .bind("0.0.0.0:8080")?
-        .run()
-        .await
+    // The server stops on SIGTERM or SIGINT after finishing the requests in flight,
+    // a running refresh gets some time to finish so its collections are swapped in
+    let result = server.await;
+    if let Err(err) = systemd::notify("STOPPING=1") {
+        warn!("Failed to notify systemd: {err}");
+    }
+    refresh::wait_for_running_refreshes(SHUTDOWN_REFRESH_TIMEOUT).await;
+    result
 }
@@ -0,0 +1,149 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{LazyLock, Mutex},
+    time::Instant,
+};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse},
+};
+use serde::{Deserialize, Serialize};
+
+// Only the most recent latencies are kept for the percentiles, so memory use stays constant
+const LATENCY_SAMPLES: usize = 1000;
+
+static ENDPOINT_METRICS: LazyLock<Mutex<HashMap<&'static str, EndpointMetrics>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Default)]
+struct EndpointMetrics {
+    requests: u64,
+    errors: u64,
+    latencies_ms: VecDeque<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EndpointStatistics {
+    pub requests: u64,
+    pub errors: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    sorted[index]
+}
+
+// Group a request path into the endpoints we want to keep track of
+fn classify(path: &str) -> &'static str {
+    let path = path.to_uppercase();
+    if path.ends_with("/SHIFT/REFRESH") || path.ends_with("/ADMIN/REFRESH") {
+        "refresh"
+    } else if path.ends_with("/SHIFT/INDEX") || path.ends_with("/API/V1/INDEX") {
+        "index"
+    } else if path.ends_with("/SHIFT/STATS") || path.contains("/STATISTICS") {
+        "statistics"
+    } else if path.ends_with("/SEARCH") {
+        "search"
+    } else if path.contains("/TIMETABLE/") && path.contains("/SOURCE/") {
+        "timetable_source"
+    } else if path.ends_with("/TIMETABLES") {
+        "timetables"
+    } else if path.ends_with("/SHIFTS") {
+        "shifts"
+    } else if path.ends_with("/DIFF/TIMETABLES") {
+        "timetable_diff"
+    } else if path.contains("/DIFF/") {
+        "shift_diff"
+    } else if path.contains("/API/V1/SHIFT/") {
+        "shift_json"
+    } else if path.contains("/SHIFT/") && path.ends_with("/EXISTS") {
+        "shift_exists"
+    } else if path.contains("/SHIFT/") && path.ends_with("/CALENDAR") {
+        "shift_calendar"
+    } else if path.contains("/SHIFT/") && path.ends_with("/ROUTE") {
+        "shift_route"
+    } else if path.contains("/SHIFT/") && path.ends_with("/ERRORS") {
+        "shift_errors"
+    } else if path.contains("/SHIFT/") && path.ends_with(".JSON") {
+        "shift_json"
+    } else if path.contains("/SHIFT/") && path.ends_with(".PNG") {
+        "shift_png"
+    } else if path.contains("/SHIFT/") && path.ends_with(".HTML") {
+        "shift_html"
+    } else if path.contains("/SHIFT/") {
+        "shift_pdf"
+    } else {
+        "other"
+    }
+}
+
+fn record(endpoint: &'static str, latency_ms: f64, is_error: bool) {
+    let Ok(mut metrics) = ENDPOINT_METRICS.lock() else {
+        return;
+    };
+    let endpoint_metrics = metrics.entry(endpoint).or_default();
+    endpoint_metrics.requests += 1;
+    if is_error {
+        endpoint_metrics.errors += 1;
+    }
+    if endpoint_metrics.latencies_ms.len() >= LATENCY_SAMPLES {
+        endpoint_metrics.latencies_ms.pop_front();
+    }
+    endpoint_metrics.latencies_ms.push_back(latency_ms);
+}
+
+pub fn endpoint_statistics() -> BTreeMap<String, EndpointStatistics> {
+    let Ok(metrics) = ENDPOINT_METRICS.lock() else {
+        return BTreeMap::new();
+    };
+    metrics
+        .iter()
+        .map(|(endpoint, endpoint_metrics)| {
+            let mut latencies: Vec<f64> = endpoint_metrics.latencies_ms.iter().copied().collect();
+            latencies.sort_by(f64::total_cmp);
+            (
+                endpoint.to_string(),
+                EndpointStatistics {
+                    requests: endpoint_metrics.requests,
+                    errors: endpoint_metrics.errors,
+                    p50_ms: percentile(&latencies, 0.5),
+                    p95_ms: percentile(&latencies, 0.95),
+                },
+            )
+        })
+        .collect()
+}
+
+type MetricsResponse<B> = Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, Error>>>>;
+
+// Middleware measuring the count, errors and latency of every request
+pub fn record_request<S, B>(request: ServiceRequest, service: &S) -> MetricsResponse<B>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let endpoint = classify(request.path());
+    let start = Instant::now();
+    let response = service.call(request);
+    Box::pin(async move {
+        let response = response.await;
+        let is_error = match &response {
+            Ok(response) => {
+                response.status().is_client_error() || response.status().is_server_error()
+            }
+            Err(_) => true,
+        };
+        record(endpoint, start.elapsed().as_secs_f64() * 1000.0, is_error);
+        response
+    })
+}
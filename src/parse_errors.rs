@@ -0,0 +1,175 @@
+// What went wrong while parsing shifts, so broken pages can be checked against the source PDF
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    DATE_FORMAT, GenResult, ambiguous_shift,
+    collection::{PdfTimetableCollection, ShiftData, shift_name},
+    error::HermesError,
+    find_shift, holidays, index, load_shift, parse_optional_date,
+    parsing::shift_structs::{ShiftParseError, ShiftValid},
+    return_error, tenant,
+};
+
+#[derive(Deserialize)]
+struct ErrorsPath {
+    shift_number: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorsQuery {
+    date: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ParseErrorReport {
+    pub kind: &'static str,
+    pub page_number: Option<u32>,
+    pub line: Option<String>,
+    pub message: String,
+}
+
+impl From<&ShiftParseError> for ParseErrorReport {
+    fn from(error: &ShiftParseError) -> Self {
+        Self {
+            kind: error.kind(),
+            page_number: error.page_number(),
+            line: error.line().map(str::to_string),
+            message: error.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ShiftErrors {
+    pub shift: String,
+    pub valid_on: Option<ShiftValid>,
+    // The Dienstboek the shift was read from, and its pages in there
+    pub source_file: Option<String>,
+    pub pages: Vec<u32>,
+    pub errors: Vec<ParseErrorReport>,
+}
+
+#[derive(Serialize)]
+pub struct TimetableErrors {
+    pub valid_from: String,
+    pub errored_shifts: usize,
+    pub parse_errors: usize,
+    pub shifts: Vec<ShiftErrors>,
+}
+
+// The errors of the shift stored under the key, empty when it parsed fine
+fn shift_errors(
+    collection: &PdfTimetableCollection,
+    key: &str,
+    shift_data: &ShiftData,
+) -> GenResult<ShiftErrors> {
+    let shift = load_shift(key.to_string(), collection.valid_from)?;
+    Ok(ShiftErrors {
+        shift: shift_name(key, shift_data),
+        valid_on: shift_data.valid_on.clone(),
+        source_file: collection.files.get(&shift_data.file_id).cloned(),
+        pages: shift_data.pages.clone(),
+        errors: shift
+            .parse_error
+            .iter()
+            .flatten()
+            .map(ParseErrorReport::from)
+            .collect(),
+    })
+}
+
+// Every shift with parse errors, including the variants, grouped by timetable oldest first.
+// Timetables without errored shifts are left out
+pub fn timetable_errors() -> GenResult<Vec<TimetableErrors>> {
+    let mut timetables = vec![];
+    for collection in PdfTimetableCollection::get_timetables()? {
+        let mut shifts: Vec<ShiftErrors> = collection
+            .shift_entries()
+            .into_iter()
+            // A shift without its JSON can't be checked
+            .filter_map(|(key, shift_data)| shift_errors(&collection, &key, shift_data).ok())
+            .filter(|shift| !shift.errors.is_empty())
+            .collect();
+        if shifts.is_empty() {
+            continue;
+        }
+        shifts.sort_by(|a, b| a.shift.cmp(&b.shift));
+        timetables.push(TimetableErrors {
+            valid_from: collection.valid_from.format(DATE_FORMAT)?,
+            errored_shifts: shifts.len(),
+            parse_errors: shifts.iter().map(|shift| shift.errors.len()).sum(),
+            shifts,
+        });
+    }
+    Ok(timetables)
+}
+
+#[get("/shift/{shift_number}/errors")]
+async fn shift_parse_errors(
+    path: web::Path<ErrorsPath>,
+    query: web::Query<ErrorsQuery>,
+) -> impl Responder {
+    let request = path.into_inner().shift_number.to_uppercase();
+    let custom_date = match parse_optional_date(&query.date) {
+        Ok(date) => date,
+        Err(err) => return err.error_response(),
+    };
+    let date = custom_date.unwrap_or(OffsetDateTime::now_utc().date());
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    if let Some(err) = ambiguous_shift(&shift_prefix, &shift_number, date) {
+        return err.error_response();
+    }
+    let tenant = tenant::current();
+    let errors = web::block(move || {
+        tenant::with_tenant(tenant, || -> GenResult<Option<ShiftErrors>> {
+            let Some((collection, shift_data, found_key)) =
+                find_shift(&shift_prefix, &shift_number, date)?
+            else {
+                return Ok(None);
+            };
+            if !shift_prefix.is_empty()
+                && !tenant::current().prefixes_equivalent(&shift_prefix, &shift_data.shift_prefix)
+            {
+                return Ok(None);
+            }
+            // With a date, the errors of the variant running on that weekday
+            let (key, shift_data) = custom_date
+                .and_then(|date| collection.variant_on(&found_key, holidays::service_weekday(date)))
+                .unwrap_or((found_key, shift_data));
+            Ok(Some(shift_errors(&collection, &key, &shift_data)?))
+        })
+        .map_err(|err| err.to_string())
+    })
+    .await;
+    match errors {
+        Ok(Ok(Some(errors))) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&errors).unwrap()),
+        Ok(Ok(None)) => HermesError::ShiftNotFound {
+            suggestions: index::similar_shifts(&request, custom_date),
+            shift: request,
+        }
+        .error_response(),
+        Ok(Err(err)) => HermesError::Internal(err).error_response(),
+        Err(err) => HermesError::Internal(err.to_string()).error_response(),
+    }
+}
+
+#[get("/errors")]
+async fn admin_parse_errors() -> impl Responder {
+    // Every shift of every timetable is read, so don't do it on the worker thread
+    let tenant = tenant::current();
+    let errors =
+        web::block(|| tenant::with_tenant(tenant, timetable_errors).map_err(|err| err.to_string()))
+            .await;
+    match errors {
+        Ok(Ok(errors)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&errors).unwrap()),
+        Ok(Err(err)) => return_error(err),
+        Err(err) => return_error(err.to_string()),
+    }
+}
@@ -0,0 +1,319 @@
+// Text of a page with the position it is drawn at, read from the operators of the content stream.
+// The position follows the text matrix and the graphics state, so it doesn't depend on how the
+// generator of the PDF writes its coordinates
+use std::collections::HashMap;
+
+use lopdf::{Document, Encoding, Object, ObjectId, content::Content};
+
+use crate::GenResult;
+
+// Text shown on a page, with the x and y position it starts at
+pub type TextElement = (String, (f32, f32));
+
+// A PDF transformation matrix [a b c d e f]
+#[derive(Clone, Copy)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translation(x: f32, y: f32) -> Self {
+        Matrix([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    // This matrix applied before the other one
+    fn then(&self, other: &Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [oa, ob, oc, od, oe, of] = other.0;
+        Matrix([
+            a * oa + b * oc,
+            a * ob + b * od,
+            c * oa + d * oc,
+            c * ob + d * od,
+            e * oa + f * oc + oe,
+            e * ob + f * od + of,
+        ])
+    }
+
+    fn from_operands(operands: &[Object]) -> Option<Self> {
+        let values: Vec<f32> = operands
+            .iter()
+            .map(|operand| operand.as_float().ok())
+            .collect::<Option<_>>()?;
+        Some(Matrix(values.try_into().ok()?))
+    }
+}
+
+struct TextState {
+    // Current transformation matrix with the ones saved by q
+    ctm: Matrix,
+    saved_ctm: Vec<Matrix>,
+    text_matrix: Matrix,
+    line_matrix: Matrix,
+    leading: f32,
+    font: Vec<u8>,
+}
+
+impl TextState {
+    fn new() -> Self {
+        TextState {
+            ctm: Matrix::IDENTITY,
+            saved_ctm: vec![],
+            text_matrix: Matrix::IDENTITY,
+            line_matrix: Matrix::IDENTITY,
+            leading: 0.0,
+            font: vec![],
+        }
+    }
+
+    fn move_line(&mut self, x: f32, y: f32) {
+        self.line_matrix = Matrix::translation(x, y).then(&self.line_matrix);
+        self.text_matrix = self.line_matrix;
+    }
+
+    // Where the next text starts on the page
+    fn position(&self) -> (f32, f32) {
+        let [_, _, _, _, x, y] = self.text_matrix.then(&self.ctm).0;
+        (x, y)
+    }
+}
+
+// Decodes the strings of each font with the encoding of that font, text in a font without a
+// usable encoding is read as Latin-1
+struct FontDecoder<'a> {
+    fonts: HashMap<Vec<u8>, Option<Encoding<'a>>>,
+}
+
+impl<'a> FontDecoder<'a> {
+    fn new(doc: &'a Document, page_id: ObjectId) -> Self {
+        let fonts = doc
+            .get_page_fonts(page_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, font)| (name, font.get_font_encoding(doc).ok()))
+            .collect();
+        FontDecoder { fonts }
+    }
+
+    fn decode(&self, font: &[u8], bytes: &[u8]) -> String {
+        self.fonts
+            .get(font)
+            .and_then(Option::as_ref)
+            .and_then(|encoding| Document::decode_text(encoding, bytes).ok())
+            .unwrap_or_else(|| bytes.iter().map(|byte| char::from(*byte)).collect())
+    }
+}
+
+fn operand_float(operands: &[Object], index: usize) -> Option<f32> {
+    operands.get(index)?.as_float().ok()
+}
+
+// Kerning in a TJ array moving the text back at least this much, in thousandths of the font size,
+// separates words instead of letters
+const TJ_SPACE_ADJUSTMENT: f32 = -200.0;
+
+// The strings of a TJ array joined, with a space where the kerning leaves room for one
+fn tj_text(decoder: &FontDecoder, font: &[u8], operands: &[Object]) -> Option<String> {
+    let mut text = String::new();
+    for element in operands.first()?.as_array().ok()? {
+        match element {
+            Object::String(bytes, _) => text.push_str(&decoder.decode(font, bytes)),
+            adjustment => {
+                if adjustment
+                    .as_float()
+                    .is_ok_and(|adjustment| adjustment <= TJ_SPACE_ADJUSTMENT)
+                    && !text.ends_with(' ')
+                {
+                    text.push(' ');
+                }
+            }
+        }
+    }
+    Some(text)
+}
+
+// The content streams of a page joined, a page can split its content over an array of streams.
+// None when the page has no content
+fn page_content(doc: &Document, page_id: ObjectId) -> GenResult<Option<Vec<u8>>> {
+    let stream_ids = doc.get_page_contents(page_id);
+    if stream_ids.is_empty() {
+        return Ok(None);
+    }
+    let mut content = vec![];
+    for stream_id in stream_ids {
+        content.extend(
+            doc.get_object(stream_id)?
+                .as_stream()?
+                .get_plain_content()?,
+        );
+        // Operators can't continue in the next stream, but the streams don't have to end with
+        // whitespace
+        content.push(b'\n');
+    }
+    Ok(Some(content))
+}
+
+// Every string shown on the page with the position it starts at, in the order they are drawn.
+// Strings shown right after each other without a move get the same position, the glyph widths
+// aren't used. None when the page has no content
+pub fn page_text_elements(
+    doc: &Document,
+    page_id: ObjectId,
+) -> GenResult<Option<Vec<TextElement>>> {
+    let Some(content) = page_content(doc, page_id)? else {
+        return Ok(None);
+    };
+    let operations = Content::decode(&content)?.operations;
+    let decoder = FontDecoder::new(doc, page_id);
+    let mut state = TextState::new();
+    let mut elements = vec![];
+    for operation in operations {
+        let operands = operation.operands.as_slice();
+        match operation.operator.as_str() {
+            "q" => state.saved_ctm.push(state.ctm),
+            "Q" => state.ctm = state.saved_ctm.pop().unwrap_or(Matrix::IDENTITY),
+            "cm" => {
+                if let Some(matrix) = Matrix::from_operands(operands) {
+                    state.ctm = matrix.then(&state.ctm);
+                }
+            }
+            "BT" => {
+                state.text_matrix = Matrix::IDENTITY;
+                state.line_matrix = Matrix::IDENTITY;
+            }
+            "Tf" => {
+                if let Some(font) = operands.first().and_then(|font| font.as_name().ok()) {
+                    state.font = font.to_vec();
+                }
+            }
+            "TL" => state.leading = operand_float(operands, 0).unwrap_or_default(),
+            "Td" | "TD" => {
+                let x = operand_float(operands, 0).unwrap_or_default();
+                let y = operand_float(operands, 1).unwrap_or_default();
+                if operation.operator == "TD" {
+                    state.leading = -y;
+                }
+                state.move_line(x, y);
+            }
+            "Tm" => {
+                if let Some(matrix) = Matrix::from_operands(operands) {
+                    state.text_matrix = matrix;
+                    state.line_matrix = matrix;
+                }
+            }
+            "T*" => state.move_line(0.0, -state.leading),
+            "Tj" | "'" | "\"" => {
+                if operation.operator != "Tj" {
+                    state.move_line(0.0, -state.leading);
+                }
+                if let Some(Ok(bytes)) = operands.last().map(Object::as_str) {
+                    elements.push((decoder.decode(&state.font, bytes), state.position()));
+                }
+            }
+            "TJ" => {
+                if let Some(text) = tj_text(&decoder, &state.font, operands) {
+                    elements.push((text, state.position()));
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(Some(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{Stream, dictionary};
+
+    use super::*;
+
+    // A one page PDF like the newer Dienstboek exports, with the content split over streams
+    fn fixture(streams: &[&[u8]]) -> (Document, ObjectId) {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let contents: Vec<Object> = streams
+            .iter()
+            .map(|content| {
+                doc.add_object(Stream::new(dictionary! {}, content.to_vec()))
+                    .into()
+            })
+            .collect();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            "Contents" => contents,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        (doc, page_id)
+    }
+
+    fn elements(streams: &[&[u8]]) -> Vec<TextElement> {
+        let (doc, page_id) = fixture(streams);
+        page_text_elements(&doc, page_id).unwrap().unwrap()
+    }
+
+    #[test]
+    fn tj_arrays_are_joined() {
+        let elements = elements(
+            &[b"BT /F1 10 Tf 83 700 Td [(Op/) -20 (Afstap) 15 (tijd)] TJ \
+              0 -15 Td [(Bus) -250 (stallen/afm)] TJ \
+              0 -15 Td [(Bus) -250 (op) -300 (lijn)] TJ ET"],
+        );
+        let texts: Vec<&str> = elements.iter().map(|(text, _)| text.as_str()).collect();
+        // Small kerning is between letters, a large one between words
+        assert_eq!(texts, ["Op/Afstaptijd", "Bus stallen/afm", "Bus op lijn"]);
+        assert_eq!(elements[1].1, (83.0, 685.0));
+    }
+
+    #[test]
+    fn tj_strings_use_the_font_encoding() {
+        let elements =
+            elements(&[b"BT /F1 10 Tf 83 700 Td [(Caf\xe9) -300 (Sch\xf6nefeld)] TJ ET"]);
+        assert_eq!(elements[0].0, "Caf\u{e9} Sch\u{f6}nefeld");
+    }
+
+    #[test]
+    fn content_streams_of_a_page_are_joined() {
+        // The text object continues in the next stream, which doesn't start with whitespace
+        let elements = elements(&[
+            b"q 1 0 0 1 10 20 cm BT /F1 10 Tf 83 700 Td (Pauze) Tj",
+            b"0 -15 Td [(Onder) (breking)] TJ ET Q",
+            b"BT /F1 10 Tf 1 0 0 1 400 100 Tm (Einde) Tj ET",
+        ]);
+        assert_eq!(
+            elements,
+            [
+                ("Pauze".to_string(), (93.0, 720.0)),
+                ("Onderbreking".to_string(), (93.0, 705.0)),
+                // Q restored the transformation from before the first stream
+                ("Einde".to_string(), (400.0, 100.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn page_without_content_has_no_elements() {
+        let (doc, page_id) = fixture(&[]);
+        assert!(page_text_elements(&doc, page_id).unwrap().is_none());
+    }
+}
@@ -0,0 +1,304 @@
+// Table model of a shift page: which column a text belongs to and which texts form a row.
+// Only takes the text with its position, so other layouts can be added without touching the
+// parsing of the jobs themselves
+use float_ord::FloatOrd;
+
+use crate::parsing::content_stream::TextElement;
+
+// Column of the job table on a shift page
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Column {
+    Lijn,
+    Omloop,
+    Rit,
+    Start,
+    Van,
+    Naar,
+    Eind,
+    Opmerkingen,
+}
+
+// Header texts of the columns, compared without case
+const COLUMN_HEADERS: [(Column, &[&str]); 8] = [
+    (Column::Lijn, &["lijn"]),
+    (Column::Omloop, &["omloop"]),
+    (Column::Rit, &["rit"]),
+    (Column::Start, &["start", "begin"]),
+    (Column::Van, &["van"]),
+    (Column::Naar, &["naar"]),
+    (Column::Eind, &["eind", "einde"]),
+    (Column::Opmerkingen, &["opmerkingen", "opmerking"]),
+];
+// A row is only the header when it has this many headers, so a job at a stop called "Van" isn't
+const MINIMUM_HEADER_COLUMNS: usize = 3;
+
+// Where the columns of a page are
+#[derive(Debug, PartialEq)]
+pub enum ColumnLayout {
+    // The x positions of the header texts, cells belong to the column of the nearest header
+    Detected { headers: Vec<(Column, f32)> },
+    // Pages without a header use the x ranges of the Dienstboek layout, relative to the leftmost text
+    Fixed { leftmost_x: f32 },
+}
+
+// The headers in a row, each column once
+fn row_headers(row: &[(String, f32)]) -> Vec<(Column, f32)> {
+    let mut headers: Vec<(Column, f32)> = vec![];
+    for (text, x) in row {
+        let text = text.trim().to_lowercase();
+        if let Some((column, _)) = COLUMN_HEADERS
+            .iter()
+            .find(|(_, names)| names.contains(&text.as_str()))
+            && headers.iter().all(|(found, _)| found != column)
+        {
+            headers.push((*column, *x));
+        }
+    }
+    headers
+}
+
+impl ColumnLayout {
+    // Uses the row with the most headers, returns the index of that row as well
+    fn detect(rows: &[(f32, Vec<(String, f32)>)]) -> (Self, Option<usize>) {
+        let header_row = rows
+            .iter()
+            .enumerate()
+            .map(|(index, (_, row))| (index, row_headers(row)))
+            .filter(|(_, headers)| headers.len() >= MINIMUM_HEADER_COLUMNS)
+            .max_by_key(|(_, headers)| headers.len());
+        if let Some((index, headers)) = header_row {
+            return (ColumnLayout::Detected { headers }, Some(index));
+        }
+        let leftmost_x = rows
+            .iter()
+            .flat_map(|(_, row)| row.iter().map(|(_, x)| FloatOrd(*x)))
+            .min()
+            .unwrap_or(FloatOrd(0.0))
+            .0;
+        (ColumnLayout::Fixed { leftmost_x }, None)
+    }
+
+    pub fn column(&self, x: f32) -> Option<Column> {
+        match self {
+            ColumnLayout::Detected { headers, .. } => headers
+                .iter()
+                .min_by_key(|(_, header_x)| FloatOrd((x - header_x).abs()))
+                .map(|(column, _)| *column),
+            ColumnLayout::Fixed { leftmost_x } => {
+                // The ranges were measured on a page where the leftmost text starts at 83
+                let x = x - leftmost_x + 83.0;
+                if x <= 150.0 {
+                    Some(Column::Lijn)
+                } else if (150.1..=290.0).contains(&x) {
+                    Some(Column::Omloop)
+                } else if (300.0..=350.0).contains(&x) {
+                    Some(Column::Rit)
+                } else if (350.0..=390.0).contains(&x) {
+                    Some(Column::Start)
+                } else if (400.0..=420.0).contains(&x) {
+                    Some(Column::Van)
+                } else if (450.0..=480.0).contains(&x) {
+                    Some(Column::Naar)
+                } else if x >= 490.0 {
+                    Some(Column::Eind)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+// A text in a row, without a column when it is outside the table or in the header
+#[derive(Debug, PartialEq)]
+pub struct Cell {
+    pub text: String,
+    pub x: f32,
+    pub column: Option<Column>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Row {
+    pub y: f32,
+    pub cells: Vec<Cell>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TableModel {
+    pub columns: ColumnLayout,
+    pub rows: Vec<Row>,
+}
+
+// The texts from the top of the page down, texts less than the tolerance lower than the first
+// text of a row are on that row. Rows are read from left to right
+fn group_rows(
+    mut elements: Vec<TextElement>,
+    row_tolerance: f32,
+) -> Vec<(f32, Vec<(String, f32)>)> {
+    elements.sort_by(|(_, (first_x, first_y)), (_, (second_x, second_y))| {
+        second_y
+            .total_cmp(first_y)
+            .then(first_x.total_cmp(second_x))
+    });
+    let mut rows: Vec<(f32, Vec<(String, f32)>)> = vec![];
+    for (text, (x, y)) in elements {
+        match rows.last_mut() {
+            Some((row_y, row)) if *row_y - y <= row_tolerance => row.push((text, x)),
+            _ => rows.push((y, vec![(text, x)])),
+        }
+    }
+    for (_, row) in &mut rows {
+        row.sort_by(|(_, first_x), (_, second_x)| first_x.total_cmp(second_x));
+    }
+    rows
+}
+
+impl TableModel {
+    pub fn from_elements(elements: Vec<TextElement>, row_tolerance: f32) -> Self {
+        let rows = group_rows(elements, row_tolerance);
+        let (columns, header_row) = ColumnLayout::detect(&rows);
+        let rows = rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, (y, row))| Row {
+                y,
+                cells: row
+                    .into_iter()
+                    .map(|(text, x)| Cell {
+                        text,
+                        x,
+                        // The header row only tells where the columns are
+                        column: (header_row != Some(index))
+                            .then(|| columns.column(x))
+                            .flatten(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        TableModel { columns, rows }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(text: &str, x: f32, y: f32) -> TextElement {
+        (text.to_string(), (x, y))
+    }
+
+    fn columns(row: &Row) -> Vec<(&str, Option<Column>)> {
+        row.cells
+            .iter()
+            .map(|cell| (cell.text.as_str(), cell.column))
+            .collect()
+    }
+
+    #[test]
+    fn columns_follow_the_header_row() {
+        // A layout with the columns in other places than the Dienstboek, drawn out of order
+        let table = TableModel::from_elements(
+            vec![
+                element("12:05", 310.0, 600.0),
+                element("Lijn", 40.0, 620.0),
+                element("Start", 300.0, 620.0),
+                element("Van", 380.0, 620.0),
+                element("Eind", 520.0, 620.0),
+                element("401", 42.0, 600.2),
+                element("Centraal", 385.0, 599.5),
+                element("12:40", 515.0, 600.0),
+            ],
+            1.0,
+        );
+        assert_eq!(
+            table.columns,
+            ColumnLayout::Detected {
+                headers: vec![
+                    (Column::Lijn, 40.0),
+                    (Column::Start, 300.0),
+                    (Column::Van, 380.0),
+                    (Column::Eind, 520.0),
+                ]
+            }
+        );
+        assert_eq!(table.rows.len(), 2);
+        // The header texts aren't cells of a column
+        assert!(table.rows[0].cells.iter().all(|cell| cell.column.is_none()));
+        assert_eq!(
+            columns(&table.rows[1]),
+            [
+                ("401", Some(Column::Lijn)),
+                ("12:05", Some(Column::Start)),
+                ("Centraal", Some(Column::Van)),
+                ("12:40", Some(Column::Eind)),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_stop_called_van_is_not_a_header() {
+        let table = TableModel::from_elements(
+            vec![element("Lijn", 83.0, 600.0), element("Van", 405.0, 600.0)],
+            1.0,
+        );
+        assert_eq!(table.columns, ColumnLayout::Fixed { leftmost_x: 83.0 });
+        assert_eq!(
+            columns(&table.rows[0]),
+            [("Lijn", Some(Column::Lijn)), ("Van", Some(Column::Van))]
+        );
+    }
+
+    #[test]
+    fn fixed_columns_are_relative_to_the_leftmost_text() {
+        // The Dienstboek layout moved 20 points to the left
+        let table = TableModel::from_elements(
+            vec![
+                element("MAT", 63.0, 500.0),
+                element("7021", 180.0, 500.0),
+                element("06:12", 350.0, 500.0),
+                element("06:30", 480.0, 500.0),
+            ],
+            1.0,
+        );
+        assert_eq!(table.columns, ColumnLayout::Fixed { leftmost_x: 63.0 });
+        assert_eq!(
+            columns(&table.rows[0]),
+            [
+                ("MAT", Some(Column::Lijn)),
+                ("7021", Some(Column::Omloop)),
+                ("06:12", Some(Column::Start)),
+                ("06:30", Some(Column::Eind)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_are_grouped_within_the_tolerance() {
+        let table = TableModel::from_elements(
+            vec![
+                element("b", 200.0, 498.0),
+                element("a", 100.0, 500.0),
+                element("c", 100.0, 496.5),
+                element("d", 100.0, 300.0),
+            ],
+            2.0,
+        );
+        let rows: Vec<(f32, Vec<&str>)> = table
+            .rows
+            .iter()
+            .map(|row| {
+                let texts = row.cells.iter().map(|cell| cell.text.as_str()).collect();
+                (row.y, texts)
+            })
+            .collect();
+        // Top of the page first, the row is at the height of its first text
+        assert_eq!(
+            rows,
+            [
+                (500.0, vec!["a", "b"]),
+                (496.5, vec!["c"]),
+                (300.0, vec!["d"])
+            ]
+        );
+    }
+}
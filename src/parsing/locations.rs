@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    GenResult,
+    config::Config,
+    parsing::shift_structs::{Coordinates, Shift},
+};
+
+// A row of the locations table, which has the columns code,name,latitude,longitude
+#[derive(Deserialize)]
+struct LocationRow {
+    code: String,
+    name: String,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+fn load_locations(path: &str) -> GenResult<Vec<LocationRow>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    Ok(reader.deserialize().collect::<Result<_, _>>()?)
+}
+
+// Turns the different spellings of a location in the source PDFs into one name
+pub struct LocationCanonicalizer {
+    // Lowercase word to its expansion
+    abbreviations: HashMap<String, String>,
+    // Lowercase spelling to the canonical name
+    aliases: HashMap<String, String>,
+    // Lowercase canonical name to its coordinates
+    coordinates: HashMap<String, Coordinates>,
+}
+
+impl LocationCanonicalizer {
+    pub fn from_config(config: &Config) -> Self {
+        let lowercase_keys = |map: &HashMap<String, String>| {
+            map.iter()
+                .map(|(from, to)| (normalize_whitespace(from).to_lowercase(), to.clone()))
+                .collect()
+        };
+        let mut aliases: HashMap<String, String> = lowercase_keys(&config.location_aliases);
+        let mut coordinates = HashMap::new();
+        // A broken table shouldn't stop the parsing, the shifts keep the codes of the PDF then
+        let locations = config.locations_path.as_deref().map(load_locations);
+        if let Some(Err(err)) = &locations {
+            error!("Failed to load the locations table: {err}");
+        }
+        for location in locations.and_then(Result::ok).into_iter().flatten() {
+            // The aliases of the configuration win over the table
+            aliases
+                .entry(normalize_whitespace(&location.code).to_lowercase())
+                .or_insert_with(|| location.name.clone());
+            if let (Some(latitude), Some(longitude)) = (location.latitude, location.longitude) {
+                coordinates.insert(
+                    location.name.to_lowercase(),
+                    Coordinates {
+                        latitude,
+                        longitude,
+                    },
+                );
+            }
+        }
+        Self {
+            abbreviations: lowercase_keys(&config.location_abbreviations),
+            aliases,
+            coordinates,
+        }
+    }
+
+    // Spelling and case don't matter for the lookups, unknown names keep their original case
+    pub fn canonical(&self, name: &str) -> String {
+        let normalized = normalize_whitespace(name);
+        if let Some(canonical) = self.aliases.get(&normalized.to_lowercase()) {
+            return canonical.clone();
+        }
+        let expanded = normalized
+            .split(' ')
+            .map(|word| {
+                self.abbreviations
+                    .get(&word.to_lowercase())
+                    .map_or(word, |expansion| expansion.as_str())
+            })
+            .collect::<Vec<&str>>()
+            .join(" ");
+        self.aliases
+            .get(&expanded.to_lowercase())
+            .cloned()
+            .unwrap_or(expanded)
+    }
+
+    // Apply to the depot and the from and to locations of every job. The jobs keep the names of
+    // the PDF next to the canonical ones
+    pub fn canonicalize_shift(&self, shift: &mut Shift) {
+        shift.location = self.canonical(&shift.location);
+        for job in &mut shift.job {
+            for (location, raw, coordinates) in [
+                (
+                    &mut job.start_location,
+                    &mut job.start_location_raw,
+                    &mut job.start_coordinates,
+                ),
+                (
+                    &mut job.end_location,
+                    &mut job.end_location_raw,
+                    &mut job.end_coordinates,
+                ),
+            ] {
+                let Some(location) = location else {
+                    continue;
+                };
+                let canonical = self.canonical(location);
+                *coordinates = self.coordinates.get(&canonical.to_lowercase()).copied();
+                *raw = Some(std::mem::replace(location, canonical));
+            }
+        }
+    }
+}
+
+fn normalize_whitespace(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
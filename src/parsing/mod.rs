@@ -1,2 +1,5 @@
+pub mod content_stream;
+pub mod layout;
+pub mod locations;
 pub mod shift_parsing;
 pub mod shift_structs;
@@ -1,300 +1,397 @@
-#![allow(warnings)]
-
 use crate::GenResult;
 use crate::collection::ShiftData;
+use crate::config::Config;
+use crate::parsing::content_stream::{TextElement, page_text_elements};
+use crate::parsing::layout::{Cell, Column, TableModel};
 use crate::parsing::shift_structs::*;
-use float_ord::FloatOrd;
 use lopdf::Document;
-use regex::Regex;
-use serde::Serialize;
+use rayon::prelude::*;
 use std::collections::HashMap;
-use std::ops::Neg;
 use std::path::PathBuf;
 use time::format_description::BorrowedFormatItem;
-use time::macros::format_description;
-use time::{Date, Time, error};
+use time::macros::{date, format_description, time};
+use time::{Date, Time};
+use tracing::Span;
 
 const DATE_FORMAT: &[BorrowedFormatItem<'_>] = format_description!["[day]-[month]-[year]"];
 
-trait StrTime {
-    fn string_to_time(&self) -> Result<Time, error::Parse>;
-}
+// Increase this when a change to the parser changes the extracted shift data
+pub const PARSER_VERSION: u32 = 14;
 
-impl StrTime for String {
-    fn string_to_time(&self) -> Result<Time, error::Parse> {
-        let format = format_description!("[hour]:[minute]");
-        Ok(Time::parse(self, format)?)
-    }
+// An Onderbreking at least this long splits a shift into a broken shift
+const BROKEN_SHIFT_MINIMUM_BREAK_MINUTES: i64 = 60;
+// Shifts starting before this are early shifts, before the second one day shifts
+const EARLY_SHIFT_START: Time = time!(07:00);
+const DAY_SHIFT_START: Time = time!(10:00);
+// Shifts ending after this, or past midnight, are late shifts
+const LATE_SHIFT_END: Time = time!(20:00);
+
+// Load a PDF for text extraction. Images are dropped while loading because they make up most of
+// the file size, but are never needed to find or parse shifts.
+// lopdf keeps its own copy of objects stored directly in the file and only uses the returned one
+// for objects inside object streams, so the object has to be cloned instead of taken
+pub fn load_text_document(pdf_path: &PathBuf) -> GenResult<Document> {
+    Ok(Document::load_filtered(pdf_path, |object_id, object| {
+        let is_image = object
+            .as_stream()
+            .ok()
+            .and_then(|stream| stream.dict.get(b"Subtype").ok())
+            .and_then(|subtype| subtype.as_name().ok())
+            == Some(b"Image".as_slice());
+        if is_image {
+            None
+        } else {
+            Some((object_id, object.clone()))
+        }
+    })?)
 }
 
-pub fn parse_pdf(
-    pdf_path: &PathBuf,
-    shift_data: HashMap<String, ShiftData>,
-) -> GenResult<Vec<Shift>> {
-    let doc = Document::load(pdf_path)?;
-    let pages = doc.get_pages();
+pub fn parse_pdf(doc: &Document, shift_data: &HashMap<String, ShiftData>) -> GenResult<Vec<Shift>> {
     let pagenr_hashmap = reverse_pagenr_hashmap(shift_data);
-    let mut i = 0;
+    let shift_pages: Vec<(u32, lopdf::ObjectId, String)> = doc
+        .get_pages()
+        .into_iter()
+        .filter_map(|(page_number, page_id)| {
+            let shift_number = pagenr_hashmap.get(&page_number)?;
+            Some((page_number, page_id, shift_number.to_string()))
+        })
+        .collect();
+    let row_tolerance = Config::get().row_tolerance;
+    // Pages are parsed in parallel.
+    // The rayon threads don't know the span of the file, so it is passed on to the page spans
+    let file_span = Span::current();
+    let parsed_pages: Vec<Result<Option<Shift>, String>> = shift_pages
+        .into_par_iter()
+        .map(|(page_number, page_id, shift_number)| {
+            let _page = debug_span!(
+                parent: &file_span,
+                "page",
+                page = page_number,
+                shift = %shift_number
+            )
+            .entered();
+            parse_shift_page(doc, page_id, page_number, shift_number, row_tolerance)
+                .map_err(|err| err.to_string())
+        })
+        .collect();
     let mut shifts: Vec<Shift> = vec![];
-    for (&page_number, &page_id) in pages.iter() {
-        let page_dict = doc.get_object(page_id)?.as_dict()?;
-        let contents = page_dict.get(b"Contents")?;
-        //println!("{:#?}", contents);
-        match contents {
-            lopdf::Object::Reference(r) => {
-                let object = doc.get_object(*r)?.as_stream()?;
-                let test = object.get_plain_content()?;
-                let stream_string = String::from_utf8_lossy(&test).to_string();
-                let stream_string = stream_string.replace("ET\n", "");
-                let stream_string = stream_string.replace("BT\n", "");
-                let stream_string = stream_string.replace("Td", "");
-                let stream_string = stream_string.replace("Tj", "");
-                let stream_string = stream_string.replace("Tf", "");
-                // let stream = lopdf::Object::Stream(*object);
-                //println!("Page {} stream: {}", page_number, stream_string);
-                let shift_number = match pagenr_hashmap.get(&page_number) {
-                    Some(shift_number) => shift_number.to_owned(),
-                    None => continue,
-                };
-                let parsed_shift = parse_page(stream_string, page_number, shift_number)?;
-                if let Some(errors) = parsed_shift.parse_error.clone() {
-                    error!("ERROR IN SHIFT {}\n{:#?}", parsed_shift.shift_nr, errors);
-                }
-                shifts.push(parsed_shift);
-            }
-            _ => {
-                println!("Unexpected type for Contents on page {}", page_number);
+    for parsed_page in parsed_pages {
+        if let Some(parsed_shift) = parsed_page? {
+            if let Some(errors) = &parsed_shift.parse_error {
+                error!(
+                    shift = %parsed_shift.shift_nr,
+                    errors = ?errors,
+                    "Shift parsed with errors"
+                );
             }
+            shifts.push(parsed_shift);
         }
-        i += 1;
     }
     Ok(shifts)
 }
 
-fn reverse_pagenr_hashmap(hashmap: HashMap<String, ShiftData>) -> HashMap<u32, String> {
-    let mut new_hashmap: HashMap<u32, String> = HashMap::new();
-    for item in hashmap.into_iter() {
-        item.1.pages.iter().for_each(|p| {
-            new_hashmap.insert(*p, item.0.clone());
-        })
+fn parse_shift_page(
+    doc: &Document,
+    page_id: lopdf::ObjectId,
+    page_number: u32,
+    shift_number: String,
+    row_tolerance: f32,
+) -> GenResult<Option<Shift>> {
+    match page_text_elements(doc, page_id)? {
+        Some(line_elements) => Ok(Some(parse_page(
+            line_elements,
+            page_number,
+            shift_number,
+            row_tolerance,
+        )?)),
+        None => {
+            warn!(page = page_number, "Page has no content");
+            Ok(None)
+        }
     }
-    new_hashmap
 }
 
-fn parse_page(page_stream: String, page_number: u32, shift_number: String) -> GenResult<Shift> {
-    let re = Regex::new(r"\((.*?)\)")?; // Match text inside parentheses
-    let mut line_elements: Vec<(String, (f32, f32))> = vec![];
-    let page_stream_clone = page_stream.clone();
-    for (line_number, line) in page_stream_clone.lines().enumerate() {
-        for cap in re.captures_iter(line) {
-            let mut coordinate_split = page_stream
-                .lines()
-                .nth(line_number - 1)
-                .ok_or(ShiftParseError::Option {
-                    function: "line coordinates".to_string(),
-                    parsing_job: None,
-                    line: None,
-                })?
-                .split_ascii_whitespace();
-            let coordinate: (f32, f32) = (
-                coordinate_split
-                    .next()
-                    .ok_or(ShiftParseError::Option {
-                        function: "line x coordinate".to_string(),
-                        parsing_job: None,
-                        line: None,
-                    })?
-                    .parse()?,
-                coordinate_split
-                    .next()
-                    .ok_or(ShiftParseError::Option {
-                        function: "line y coordinate".to_string(),
-                        parsing_job: None,
-                        line: None,
-                    })?
-                    .parse()?,
-            );
-
-            // println!(
-            //     "Line {}: {} op positie {:?}",
-            //     line_number + 1,
-            //     &cap[1],
-            //     coordinate
-            // );
-            line_elements.push((cap[1].to_string(), coordinate));
-        }
+fn reverse_pagenr_hashmap(hashmap: &HashMap<String, ShiftData>) -> HashMap<u32, &str> {
+    let mut new_hashmap: HashMap<u32, &str> = HashMap::new();
+    for item in hashmap.iter() {
+        item.1.pages.iter().for_each(|p| {
+            new_hashmap.insert(*p, item.0);
+        })
     }
-    let minimal_x = line_elements
-        .iter()
-        .map(|val| FloatOrd(val.1.0))
-        .min()
-        .unwrap_or(FloatOrd(0.0))
-        .0
-        .neg();
-    let shift = get_line_element(line_elements, minimal_x, page_number, shift_number)?;
-    Ok(shift)
+    new_hashmap
 }
 
-fn get_line_element(
-    items: Vec<(String, (f32, f32))>,
-    offset: f32,
+#[instrument(level = "debug", name = "extract_shift", skip_all, fields(shift = %shift_number))]
+fn parse_page(
+    line_elements: Vec<TextElement>,
     page_number: u32,
     shift_number: String,
+    row_tolerance: f32,
 ) -> GenResult<Shift> {
-    let mut line_errors: Vec<ShiftParseError> = vec![];
-
-    let mut last_y = items
-        .first()
-        .ok_or(ShiftParseError::Option {
+    let table = TableModel::from_elements(line_elements, row_tolerance);
+    debug!(page = page_number, columns = ?table.columns, "Columns of the page");
+    if table.rows.is_empty() {
+        return Err(Box::new(ShiftParseError::Option {
             function: "first line".to_string(),
             parsing_job: None,
             line: None,
-        })?
-        .1
-        .1;
-    let mut lijn: Option<String> = None;
-    let mut omloop: Option<_> = None;
-    let mut rit: Option<_> = None;
-    let mut start: Option<_> = None;
-    let mut van: Option<_> = None;
-    let mut naar: Option<_> = None;
-    let mut eind: Option<_> = None;
-    let mut start_date = Date::from_calendar_date(2025, time::Month::June, 29)?;
-    let mut valid_on = ShiftValid::Unknown;
-    let mut shift_number = shift_number;
-    let mut location = String::new();
-    let mut jobs = vec![];
-    for item in items {
-        match get_line_information(
-            &mut lijn,
-            &mut omloop,
-            &mut rit,
-            &mut start,
-            &mut van,
-            &mut naar,
-            &mut eind,
-            &mut jobs,
-            &mut start_date,
-            &mut valid_on,
-            &mut shift_number,
-            &mut location,
-            last_y,
-            item.1.1,
-            item.1.0,
-            offset,
-            page_number,
-            item.0,
-        ) {
-            Ok(_) => (),
-            Err(err) => line_errors.push(err),
-        };
-        last_y = item.1.1;
+        }));
     }
-    Ok(Shift {
-        shift_nr: shift_number,
-        valid_on,
-        location,
-        shift_type: None,
-        job: jobs,
-        starting_date: start_date,
-        parse_error: if !line_errors.is_empty() {
-            Some(line_errors)
-        } else {
-            None
-        },
-    })
+    let mut parser = ShiftPageParser::new(page_number, shift_number);
+    for row in table.rows {
+        for cell in row.cells {
+            parser.feed_line(row.y, cell);
+        }
+    }
+    Ok(parser.finish())
 }
 
-fn get_line_information(
-    lijn_number: &mut Option<String>,
-    omloop: &mut Option<String>,
-    rit: &mut Option<String>,
-    start: &mut Option<String>,
-    van: &mut Option<String>,
-    naar: &mut Option<String>,
-    eind: &mut Option<String>,
-    jobs: &mut Vec<ShiftJob>,
-    start_date: &mut Date,
-    valid_on: &mut ShiftValid,
-    shift_number: &mut String,
-    location: &mut String,
-    last_y: f32,
-    current_y: f32,
-    current_x: f32,
-    offset: f32,
+// Cells of the table row that is being read
+#[derive(Default)]
+struct PendingRow {
+    lijn: Option<String>,
+    omloop: Option<String>,
+    rit: Option<String>,
+    start: Option<String>,
+    van: Option<String>,
+    naar: Option<String>,
+    eind: Option<String>,
+    opmerkingen: Option<String>,
+    // Every text of the row that is in a column, from left to right
+    texts: Vec<String>,
+}
+
+// Remark lines start with this in the lijn column, compared without case
+const REMARK_PREFIXES: [&str; 2] = ["opmerkingen:", "opmerking:"];
+
+impl PendingRow {
+    // A row without times that is empty in the lijn column or starts with a remark label is a
+    // remark on the job above it
+    fn remark(&self) -> Option<String> {
+        if self.start.is_some() || self.eind.is_some() {
+            return None;
+        }
+        if let Some(lijn) = &self.lijn
+            && !lijn.to_lowercase().starts_with("opmerking")
+        {
+            return None;
+        }
+        let text = self.texts.join(" ");
+        let text = REMARK_PREFIXES
+            .iter()
+            .find_map(|prefix| {
+                text.get(..prefix.len())
+                    .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+                    .then(|| &text[prefix.len()..])
+            })
+            .unwrap_or(&text)
+            .trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+// Reads a shift page one text at a time, a job is made of every table row when the next row starts
+pub struct ShiftPageParser {
     page_number: u32,
-    line: String,
-) -> Result<(), ShiftParseError> {
-    let lijn_lower = 83.0 - 83.0 - offset;
-    let lijn_upper = 150.0 - 83.0 - offset;
-    let omloop_lower = 150.1 - 83.0 - offset;
-    let omloop_upper = 290.0 - 83.0 - offset;
-    let rit_lower = 300.0 - 83.0 - offset;
-    let rit_upper = 350.0 - 83.0 - offset;
-    let start_lower = 350.0 - 83.0 - offset;
-    let start_upper = 390.0 - 83.0 - offset;
-    let van_lower = 400.0 - 83.0 - offset;
-    let van_upper = 420.0 - 83.0 - offset;
-    let naar_lower = 450.0 - 83.0 - offset;
-    let naar_upper = 480.0 - 83.0 - offset;
-    let eind_lower = 490.0 - 83.0 - offset;
-    if last_y != current_y {
-        //println!("Job gevonden!\nLijn {lijn:?}, omloop {omloop:?}, rit {rit:?}, van {van:?}, naar {naar:?}, begint om {start:?} en stopt om {eind:?}");
-        let job = job_creator(
-            lijn_number.clone(),
-            omloop.clone(),
-            rit.clone(),
-            start.clone(),
-            eind.clone(),
-            van.clone(),
-            naar.clone(),
-        )?;
-        //println!("{:?}", &job);
-        if !job.empty() {
-            jobs.push(job);
+    row: PendingRow,
+    last_y: Option<f32>,
+    jobs: Vec<ShiftJob>,
+    start_date: Date,
+    valid_on: ShiftValid,
+    shift_number: String,
+    location: String,
+    errors: Vec<ShiftParseError>,
+}
+
+impl ShiftPageParser {
+    pub fn new(page_number: u32, shift_number: String) -> Self {
+        ShiftPageParser {
+            page_number,
+            row: PendingRow::default(),
+            last_y: None,
+            jobs: vec![],
+            start_date: date!(2025 - 06 - 29),
+            valid_on: ShiftValid::Unknown,
+            shift_number,
+            location: String::new(),
+            errors: vec![],
+        }
+    }
+
+    // A text of the page at height y, errors are collected for the shift instead of stopping it
+    pub fn feed_line(&mut self, y: f32, cell: Cell) {
+        if self.last_y.is_some_and(|last_y| last_y != y)
+            && let Err(err) = self.finish_row()
+        {
+            self.errors.push(err);
+        }
+        self.last_y = Some(y);
+        if let Err(err) = self.read_cell(y, cell) {
+            self.errors.push(err);
         }
-        *lijn_number = None;
-        *omloop = None;
-        *rit = None;
-        *start = None;
-        *van = None;
-        *naar = None;
-        *eind = None;
     }
-    //println!("Line: {}, x: {}",line, current_x);
-    if current_y < 50.0 || current_y > 735.0 {
-        if let metadata = line.clone() {
+
+    fn read_cell(&mut self, y: f32, cell: Cell) -> Result<(), ShiftParseError> {
+        if !(50.0..=735.0).contains(&y) {
             identify_metadata(
-                &mut *start_date,
-                &mut *valid_on,
-                &mut *shift_number,
-                &mut *location,
-                metadata,
-                current_y,
-                current_x,
+                &mut self.start_date,
+                &mut self.valid_on,
+                &mut self.shift_number,
+                &mut self.location,
+                cell.text,
+                y,
+                cell.x,
             )
             .ok_or(ShiftParseError::MetadataFailure {
-                page_number,
+                page_number: self.page_number,
                 line: None,
             })?;
+            return Ok(());
+        }
+        let row = &mut self.row;
+        let field = match cell.column {
+            Some(Column::Lijn) => &mut row.lijn,
+            Some(Column::Omloop) => &mut row.omloop,
+            Some(Column::Rit) => &mut row.rit,
+            Some(Column::Start) => &mut row.start,
+            Some(Column::Van) => &mut row.van,
+            Some(Column::Naar) => &mut row.naar,
+            Some(Column::Eind) => &mut row.eind,
+            Some(Column::Opmerkingen) => &mut row.opmerkingen,
+            None => return Ok(()),
+        };
+        row.texts.push(cell.text.clone());
+        *field = Some(cell.text);
+        Ok(())
+    }
+
+    // Make a job of the row that was read, rows without anything in the table are skipped
+    fn finish_row(&mut self) -> Result<(), ShiftParseError> {
+        let row = std::mem::take(&mut self.row);
+        if let Some(remark) = row.remark()
+            && let Some(job) = self.jobs.last_mut()
+        {
+            job.remarks.push(remark);
+            return Ok(());
+        }
+        let mut job = job_creator(
+            row.lijn, row.omloop, row.rit, row.start, row.eind, row.van, row.naar,
+        )?;
+        job.remarks.extend(row.opmerkingen);
+        if !job.empty() {
+            self.jobs.push(job);
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Shift {
+        if let Err(err) = self.finish_row() {
+            self.errors.push(err);
         }
-    } else if current_x >= lijn_lower && current_x <= lijn_upper {
-        *lijn_number = Some(line);
-    } else if current_x >= omloop_lower && current_x <= omloop_upper {
-        *omloop = Some(line);
-    } else if current_x >= rit_lower && current_x <= rit_upper {
-        *rit = Some(line);
-    } else if current_x >= start_lower && current_x <= start_upper {
-        *start = Some(line);
-    } else if current_x >= van_lower && current_x <= van_upper {
-        *van = Some(line);
-    } else if current_x >= naar_lower && current_x <= naar_upper {
-        *naar = Some(line);
-    } else if current_x >= eind_lower {
-        *eind = Some(line);
+        mark_next_day(&mut self.jobs);
+        let mut shift = Shift {
+            shift_nr: self.shift_number,
+            valid_on: self.valid_on,
+            location: self.location,
+            shift_type: None,
+            job: self.jobs,
+            starting_date: self.start_date,
+            parse_error: if !self.errors.is_empty() {
+                Some(self.errors)
+            } else {
+                None
+            },
+            times: ShiftTimes::default(),
+        };
+        shift.times = shift_times(&shift);
+        shift.shift_type = shift_type(&shift);
+        shift
     }
+}
 
-    Ok(())
+// Every time after the first one past midnight is on the next day. Times before 24:00 are past
+// midnight as well when they are earlier than the time before them. Sets the durations of the
+// jobs once their days are known
+fn mark_next_day(jobs: &mut [ShiftJob]) {
+    let mut previous: Option<Time> = None;
+    let mut next_day = false;
+    for job in jobs {
+        for (time, time_next_day) in [
+            (job.start, &mut job.start_next_day),
+            (job.end, &mut job.end_next_day),
+        ] {
+            let Some(time) = time else {
+                continue;
+            };
+            next_day |= *time_next_day || previous.is_some_and(|previous| time < previous);
+            *time_next_day = next_day;
+            previous = Some(time);
+        }
+        job.duration_minutes = job.minutes();
+    }
+}
+
+// Minutes from start to end, wrapping around when the end is past midnight
+fn minutes_between(start: Time, end: Time) -> i64 {
+    let minutes = (end - start).whole_minutes();
+    if minutes < 0 {
+        minutes + 24 * 60
+    } else {
+        minutes
+    }
+}
+
+fn shift_type(shift: &Shift) -> Option<ShiftType> {
+    let long_break = shift.job.iter().find(|job| {
+        job.job_type == JobType::Onderbreking
+            && job
+                .duration_minutes
+                .is_some_and(|minutes| minutes >= BROKEN_SHIFT_MINIMUM_BREAK_MINUTES)
+    });
+    if let Some(long_break) = long_break {
+        return Some(ShiftType::Gebroken {
+            start_break: long_break.start,
+            end_break: long_break.end,
+        });
+    }
+    let start = shift.times.start_time?;
+    let end = shift.times.end_time?;
+    Some(if start < EARLY_SHIFT_START {
+        ShiftType::Vroeg
+    } else if end > LATE_SHIFT_END || end < start {
+        ShiftType::Laat
+    } else if start < DAY_SHIFT_START {
+        ShiftType::Dag
+    } else {
+        ShiftType::Tussen
+    })
+}
+
+fn shift_times(shift: &Shift) -> ShiftTimes {
+    let start_time = shift.start_time();
+    let end_time = shift.end_time();
+    let duration_minutes = start_time
+        .zip(end_time)
+        .map(|(start, end)| minutes_between(start, end));
+    let minutes_of = |is_counted: fn(&JobType) -> bool| {
+        shift
+            .job
+            .iter()
+            .filter(|job| is_counted(&job.job_type))
+            .filter_map(|job| job.duration_minutes)
+            .sum()
+    };
+    let driving_minutes = minutes_of(|job_type| matches!(job_type, JobType::Rijden { .. }));
+    let break_minutes =
+        minutes_of(|job_type| matches!(job_type, JobType::Pauze | JobType::Onderbreking));
+    ShiftTimes {
+        start_time,
+        end_time,
+        duration_minutes,
+        driving_minutes,
+        break_minutes,
+        paid_minutes: duration_minutes.map(|duration| duration - break_minutes),
+    }
 }
 
 fn identify_metadata(
@@ -343,6 +440,8 @@ fn job_creator(
     let mut rit_number = None;
     let mut start_time: Option<Time> = None;
     let mut end_time = None;
+    let mut start_next_day = false;
+    let mut end_next_day = false;
     if let Some(lijn_string) = lijn {
         if lijn_string == "MAT" {
             job_type = JobType::Rijden {
@@ -368,10 +467,10 @@ fn job_creator(
         rit_number = rit_string.parse::<usize>().ok();
     }
     if let Some(start_string) = start {
-        start_time = to_iso8601(start_string, "Start time")?;
+        (start_time, start_next_day) = to_iso8601(start_string, "Start time")?;
     }
     if let Some(end_string) = eind {
-        end_time = to_iso8601(end_string, "End time")?;
+        (end_time, end_next_day) = to_iso8601(end_string, "End time")?;
     }
     if let Some(omloop_string) = omloop {
         match omloop_string.as_ref() {
@@ -388,15 +487,28 @@ fn job_creator(
         job_type,
         start: start_time,
         end: end_time,
+        start_next_day,
+        end_next_day,
+        duration_minutes: None,
         start_location: van,
         end_location: naar,
+        start_location_raw: None,
+        end_location_raw: None,
+        start_coordinates: None,
+        end_coordinates: None,
         omloop: omloop_number,
         rit: rit_number,
+        remarks: vec![],
     })
 }
 
-fn to_iso8601(time_string: String, job_name: &str) -> Result<Option<Time>, ShiftParseError> {
-    let mut time_split = time_string.split(":").into_iter();
+// The time of day with whether it is on the next day, the Dienstboek writes times after midnight
+// as 24:00 and later
+fn to_iso8601(
+    time_string: String,
+    job_name: &str,
+) -> Result<(Option<Time>, bool), ShiftParseError> {
+    let mut time_split = time_string.split(":");
     let hour_noniso = time_split
         .next()
         .ok_or(ShiftParseError::Option {
@@ -423,11 +535,11 @@ fn to_iso8601(time_string: String, job_name: &str) -> Result<Option<Time>, Shift
             error: err.to_string(),
             line: Some(time_string.clone()),
         })?;
-    let hour_iso = match hour_noniso {
-        24.. => hour_noniso - 24,
-        _ => hour_noniso,
+    let (hour_iso, next_day) = match hour_noniso {
+        24.. => (hour_noniso - 24, true),
+        _ => (hour_noniso, false),
     };
-    Ok(Time::from_hms(hour_iso, minute, 0).ok())
+    Ok((Time::from_hms(hour_iso, minute, 0).ok(), next_day))
 }
 
 fn message_type_finder(lijn_string: String) -> Option<JobMessageType> {
@@ -450,8 +562,239 @@ fn message_type_finder(lijn_string: String) -> Option<JobMessageType> {
                 omloop: dienst_omloop_split.next()?.to_string(),
             }
         }
-        "meenemen" => JobMessageType::Other(lijn_string),
+        "meenemen" => {
+            // The dienst numbers are listed after the instruction, separated by commas or spaces
+            // and sometimes with their prefix. Anything else keeps the line as it is
+            let dienstnummers: Option<Vec<u32>> = lijn_string
+                .split(|character: char| character == ',' || character.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .skip(1)
+                .map(|word| word.trim_start_matches(char::is_alphabetic).parse().ok())
+                .collect();
+            match dienstnummers {
+                Some(dienstnummers) if !dienstnummers.is_empty() => {
+                    JobMessageType::Meenemen { dienstnummers }
+                }
+                _ => JobMessageType::Other(lijn_string),
+            }
+        }
         _ => JobMessageType::Other(lijn_string),
     };
     Some(message)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(text: &str, x: f32, column: Option<Column>) -> Cell {
+        Cell {
+            text: text.to_string(),
+            x,
+            column,
+        }
+    }
+
+    // Feeds the texts of a table row, the x position doesn't matter once the column is known
+    fn feed_row(parser: &mut ShiftPageParser, y: f32, cells: &[(Column, &str)]) {
+        for (column, text) in cells {
+            parser.feed_line(y, cell(text, 0.0, Some(*column)));
+        }
+    }
+
+    fn metadata(parser: &mut ShiftPageParser) {
+        parser.feed_line(800.0, cell("Dienst G 1234", 100.0, None));
+        parser.feed_line(790.0, cell("Ingangsdatum 01-09-2025", 100.0, None));
+        parser.feed_line(780.0, cell("MA/DI/WO/DO/VR", 100.0, None));
+        parser.feed_line(770.0, cell("Depot Noord", 350.0, None));
+    }
+
+    #[test]
+    fn shift_page_is_read_into_jobs() {
+        let mut parser = ShiftPageParser::new(3, "G1234".to_string());
+        metadata(&mut parser);
+        feed_row(
+            &mut parser,
+            700.0,
+            &[
+                (Column::Lijn, "Op/Afstaptijd"),
+                (Column::Start, "06:00"),
+                (Column::Van, "Depot"),
+                (Column::Eind, "06:10"),
+            ],
+        );
+        feed_row(
+            &mut parser,
+            680.0,
+            &[
+                (Column::Lijn, "MAT"),
+                (Column::Omloop, "7021"),
+                (Column::Start, "06:10"),
+                (Column::Van, "Depot"),
+                (Column::Naar, "Centraal"),
+                (Column::Eind, "06:30"),
+            ],
+        );
+        feed_row(
+            &mut parser,
+            660.0,
+            &[
+                (Column::Lijn, "401"),
+                (Column::Omloop, "7021"),
+                (Column::Rit, "3"),
+                (Column::Start, "06:30"),
+                (Column::Van, "Centraal"),
+                (Column::Naar, "Airport"),
+                (Column::Eind, "07:30"),
+            ],
+        );
+        // A row without times is a remark on the job above it
+        feed_row(
+            &mut parser,
+            650.0,
+            &[
+                (Column::Lijn, "Opmerking:"),
+                (Column::Omloop, "let op omleiding"),
+            ],
+        );
+        feed_row(
+            &mut parser,
+            640.0,
+            &[
+                (Column::Omloop, "Onderbreking"),
+                (Column::Start, "07:30"),
+                (Column::Eind, "09:00"),
+            ],
+        );
+        feed_row(
+            &mut parser,
+            620.0,
+            &[
+                (Column::Lijn, "401"),
+                (Column::Start, "23:50"),
+                (Column::Eind, "24:20"),
+            ],
+        );
+        let shift = parser.finish();
+
+        assert_eq!(shift.shift_nr, "G1234");
+        assert_eq!(shift.starting_date, date!(2025 - 09 - 01));
+        assert_eq!(shift.valid_on, ShiftValid::Weekdays);
+        assert_eq!(shift.location, "Depot Noord");
+        assert!(shift.parse_error.is_none());
+        let job_types: Vec<&JobType> = shift.job.iter().map(|job| &job.job_type).collect();
+        assert_eq!(
+            job_types,
+            [
+                &JobType::OpAfstap,
+                &JobType::Rijden {
+                    drive_type: JobDrivingType::Mat
+                },
+                &JobType::Rijden {
+                    drive_type: JobDrivingType::Lijn(401)
+                },
+                &JobType::Onderbreking,
+                &JobType::Rijden {
+                    drive_type: JobDrivingType::Lijn(401)
+                },
+            ]
+        );
+        let line_job = &shift.job[2];
+        assert_eq!(line_job.omloop, Some(7021));
+        assert_eq!(line_job.rit, Some(3));
+        assert_eq!(line_job.start_location.as_deref(), Some("Centraal"));
+        assert_eq!(line_job.end_location.as_deref(), Some("Airport"));
+        assert_eq!(line_job.remarks, ["let op omleiding"]);
+        assert_eq!(line_job.duration_minutes, Some(60));
+        // 24:20 is twenty past midnight on the next day
+        let last_job = &shift.job[4];
+        assert!(!last_job.start_next_day);
+        assert!(last_job.end_next_day);
+        assert_eq!(last_job.end, Some(time!(00:20)));
+        assert_eq!(last_job.duration_minutes, Some(30));
+
+        assert_eq!(
+            shift.shift_type,
+            Some(ShiftType::Gebroken {
+                start_break: Some(time!(07:30)),
+                end_break: Some(time!(09:00)),
+            })
+        );
+        assert_eq!(shift.times.start_time, Some(time!(06:00)));
+        assert_eq!(shift.times.end_time, Some(time!(00:20)));
+        assert_eq!(shift.times.break_minutes, 90);
+    }
+
+    #[test]
+    fn errors_are_collected_without_stopping_the_page() {
+        let mut parser = ShiftPageParser::new(5, "1234".to_string());
+        feed_row(
+            &mut parser,
+            700.0,
+            &[
+                (Column::Lijn, "401"),
+                (Column::Start, "6h00"),
+                (Column::Eind, "06:30"),
+            ],
+        );
+        feed_row(
+            &mut parser,
+            680.0,
+            &[
+                (Column::Lijn, "402"),
+                (Column::Start, "06:40"),
+                (Column::Eind, "07:10"),
+            ],
+        );
+        let shift = parser.finish();
+        assert_eq!(shift.parse_error.map(|errors| errors.len()), Some(1));
+        assert_eq!(shift.job.len(), 1);
+        assert_eq!(
+            shift.job[0].job_type,
+            JobType::Rijden {
+                drive_type: JobDrivingType::Lijn(402)
+            }
+        );
+    }
+
+    #[test]
+    fn finish_reads_the_last_row() {
+        let mut parser = ShiftPageParser::new(1, "1234".to_string());
+        feed_row(
+            &mut parser,
+            700.0,
+            &[
+                (Column::Lijn, "Pauze"),
+                (Column::Start, "10:00"),
+                (Column::Eind, "10:30"),
+            ],
+        );
+        // Texts outside the table are ignored
+        parser.feed_line(700.0, cell("naast de tabel", 20.0, None));
+        let shift = parser.finish();
+        assert_eq!(shift.job.len(), 1);
+        assert_eq!(shift.job[0].job_type, JobType::Pauze);
+        assert_eq!(shift.job[0].duration_minutes, Some(30));
+        assert_eq!(shift.shift_nr, "1234");
+        assert_eq!(shift.valid_on, ShiftValid::Unknown);
+    }
+
+    #[test]
+    fn page_without_text_is_an_error() {
+        assert!(parse_page(vec![], 1, "1234".to_string(), 1.0).is_err());
+    }
+
+    #[test]
+    fn meenemen_reads_prefixed_dienst_numbers() {
+        assert_eq!(
+            message_type_finder("Meenemen G1234, 5678".to_string()),
+            Some(JobMessageType::Meenemen {
+                dienstnummers: vec![1234, 5678]
+            })
+        );
+        assert_eq!(
+            message_type_finder("Meenemen 1234 en tas".to_string()),
+            Some(JobMessageType::Other("Meenemen 1234 en tas".to_string()))
+        );
+    }
+}
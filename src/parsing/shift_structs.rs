@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use time::{Date, Time};
+use time::{Date, Time, Weekday};
 
 #[derive(Error, Debug, Serialize, Deserialize, Clone)]
 pub enum ShiftParseError {
@@ -23,7 +23,7 @@ pub enum ShiftParseError {
     },
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum ShiftValid {
     Weekdays,
     Wednesday,
@@ -34,7 +34,7 @@ pub enum ShiftValid {
     Unknown,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum ShiftType {
     Vroeg,
     Tussen,
@@ -75,18 +75,112 @@ pub enum JobType {
     Unknown,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ShiftJob {
     pub job_type: JobType,
     pub start: Option<Time>,
     pub end: Option<Time>,
+    // The start or end is after midnight, on the day after the service date of the shift
+    #[serde(default)]
+    pub start_next_day: bool,
+    #[serde(default)]
+    pub end_next_day: bool,
+    #[serde(default)]
+    pub duration_minutes: Option<i64>,
     pub start_location: Option<String>,
     pub end_location: Option<String>, // If none, it's the same as start
+    // The locations as printed in the PDF, before the canonical names replaced them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_location_raw: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_location_raw: Option<String>,
+    // Only known for locations in the locations table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_coordinates: Option<Coordinates>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_coordinates: Option<Coordinates>,
     pub omloop: Option<usize>,
     pub rit: Option<usize>,
+    // Opmerkingen printed beside or below the job
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remarks: Vec<String>,
+}
+
+impl ShiftParseError {
+    // Short name of the kind of error, for grouping them
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ShiftParseError::GenericShiftError { .. } => "generic",
+            ShiftParseError::MetadataFailure { .. } => "metadata",
+            ShiftParseError::Option { .. } => "missing_value",
+        }
+    }
+
+    // Page of the PDF the error is on, when the parser knew it
+    pub fn page_number(&self) -> Option<u32> {
+        match self {
+            ShiftParseError::GenericShiftError { page_number, .. }
+            | ShiftParseError::MetadataFailure { page_number, .. } => Some(*page_number),
+            ShiftParseError::Option { .. } => None,
+        }
+    }
+
+    // The line of the PDF the parser failed on
+    pub fn line(&self) -> Option<&str> {
+        match self {
+            ShiftParseError::GenericShiftError { line, .. }
+            | ShiftParseError::MetadataFailure { line, .. }
+            | ShiftParseError::Option { line, .. } => line.as_deref(),
+        }
+    }
+}
+
+impl ShiftValid {
+    // The variants of shifts that run on a weekday
+    pub fn for_weekday(weekday: Weekday) -> Vec<ShiftValid> {
+        match weekday {
+            Weekday::Saturday => vec![ShiftValid::Saturday],
+            Weekday::Sunday => vec![ShiftValid::Sunday],
+            Weekday::Wednesday => vec![ShiftValid::Weekdays, ShiftValid::Wednesday],
+            _ => vec![ShiftValid::Weekdays, ShiftValid::WeekdaysExceptWednesday],
+        }
+    }
+
+    // Whether the variant runs on a weekday, an unknown variant is assumed to run on every day
+    pub fn runs_on(&self, weekday: Weekday) -> bool {
+        *self == ShiftValid::Unknown || Self::for_weekday(weekday).contains(self)
+    }
+
+    // Used in the file name of a shift that has more than one variant
+    pub fn file_tag(&self) -> &'static str {
+        match self {
+            ShiftValid::Weekdays => "weekdays",
+            ShiftValid::Wednesday => "wednesday",
+            ShiftValid::WeekdaysExceptWednesday => "weekdays-except-wednesday",
+            ShiftValid::Saturday => "saturday",
+            ShiftValid::Sunday => "sunday",
+            ShiftValid::Unknown => "unknown",
+        }
+    }
 }
 
 impl ShiftJob {
+    // Minutes from the start to the end of the job, counting the days they are on
+    pub fn minutes(&self) -> Option<i64> {
+        let minutes = (self.end? - self.start?).whole_minutes();
+        Some(match (self.start_next_day, self.end_next_day) {
+            (false, true) => minutes + 24 * 60,
+            (true, false) => minutes - 24 * 60,
+            _ => minutes,
+        })
+    }
+
     pub fn empty(&self) -> bool {
         if self.job_type == JobType::Unknown
             && self.start.is_none()
@@ -100,6 +194,32 @@ impl ShiftJob {
     }
 }
 
+impl Shift {
+    // Start time of the first job that has one
+    pub fn start_time(&self) -> Option<Time> {
+        self.job.iter().find_map(|job| job.start)
+    }
+
+    // End time of the last job that has one
+    pub fn end_time(&self) -> Option<Time> {
+        self.job.iter().rev().find_map(|job| job.end.or(job.start))
+    }
+}
+
+// Start and end of a shift with its durations in minutes
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ShiftTimes {
+    pub start_time: Option<Time>,
+    pub end_time: Option<Time>,
+    pub duration_minutes: Option<i64>,
+    // Rijden jobs, on a line or with an empty bus
+    #[serde(default)]
+    pub driving_minutes: i64,
+    // Pauze and Onderbreking jobs
+    pub break_minutes: i64,
+    pub paid_minutes: Option<i64>,
+}
+
 #[derive(Debug, Serialize, Clone, Deserialize)]
 pub struct Shift {
     pub shift_nr: String,
@@ -109,4 +229,6 @@ pub struct Shift {
     pub job: Vec<ShiftJob>,
     pub starting_date: Date,
     pub parse_error: Option<Vec<ShiftParseError>>,
+    #[serde(default)]
+    pub times: ShiftTimes,
 }
@@ -0,0 +1,51 @@
+// PNG thumbnails of shift pages, so the app can show a preview without a PDF renderer.
+// Rendering uses the pdfium library of the system, only when built with the preview feature
+use crate::error::HermesError;
+
+// Wide enough to read the times on a phone, small enough to stay a thumbnail
+#[cfg(feature = "preview")]
+const PREVIEW_WIDTH: i32 = 800;
+
+#[cfg(feature = "preview")]
+pub fn render_page(pdf: &[u8], page: u16) -> Result<Vec<u8>, HermesError> {
+    use std::io::Cursor;
+
+    use image::ImageFormat;
+    use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+
+    let load_error = |err: &dyn std::error::Error| HermesError::PdfLoad(err.to_string());
+    let bindings = Pdfium::bind_to_system_library().map_err(|err| {
+        warn!("Loading pdfium failed: {err:?}");
+        HermesError::Unsupported(
+            "PNG previews need libpdfium, which could not be loaded".to_string(),
+        )
+    })?;
+    let pdfium = Pdfium::new(bindings);
+    let document = pdfium
+        .load_pdf_from_byte_slice(pdf, None)
+        .map_err(|err| load_error(&err))?;
+    let page_count = document.pages().len();
+    if page >= page_count {
+        return Err(HermesError::BadRequest(format!(
+            "Page {page} does not exist, this shift has {page_count} pages"
+        )));
+    }
+    let page = document.pages().get(page).map_err(|err| load_error(&err))?;
+    let bitmap = page
+        .render_with_config(&PdfRenderConfig::new().set_target_width(PREVIEW_WIDTH))
+        .map_err(|err| load_error(&err))?;
+    let mut png = Cursor::new(vec![]);
+    bitmap
+        .as_image()
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(|err| load_error(&err))?;
+    Ok(png.into_inner())
+}
+
+#[cfg(not(feature = "preview"))]
+pub fn render_page(_pdf: &[u8], _page: u16) -> Result<Vec<u8>, HermesError> {
+    Err(HermesError::Unsupported(
+        "PNG previews are not available, this server was built without the preview feature"
+            .to_string(),
+    ))
+}
@@ -0,0 +1,75 @@
+// Links from the messages of a shift to the other shifts they mention, so apps can open the
+// shift a driver passengers with or takes along
+use serde::Serialize;
+
+use crate::{
+    collection::{PdfTimetableCollection, key_number},
+    config::Config,
+    parsing::shift_structs::{JobMessageType, JobType, Shift},
+    tenant,
+};
+
+// A shift mentioned by a job of the shift
+#[derive(Serialize, Debug, Clone)]
+pub struct ShiftReference {
+    pub job: usize,
+    pub shift_number: u32,
+    // Prefix and number to request the shift with, None when the timetable doesn't have it
+    pub link: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+// The shift numbers in the Passagieren and Meenemen messages, looked up in the timetable of the
+// shift itself. Aliases from the configuration are followed
+pub fn link_references(shift: &Shift, collection: &PdfTimetableCollection) -> Vec<ShiftReference> {
+    let config = Config::get();
+    let shift_prefix: String = shift
+        .shift_nr
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .collect();
+    let mut references = vec![];
+    for (job_index, job) in shift.job.iter().enumerate() {
+        let shift_numbers = match &job.job_type {
+            JobType::Melding {
+                message: JobMessageType::Passagieren { dienstnummer, .. },
+            } => vec![*dienstnummer],
+            JobType::Melding {
+                message: JobMessageType::Meenemen { dienstnummers },
+            } => dienstnummers.clone(),
+            _ => continue,
+        };
+        for shift_number in shift_numbers {
+            // Shifts of the same depot come first, the prefix of the shift itself picks it
+            let number = shift_number.to_string();
+            let found = config
+                .shift_alias(&number, collection.valid_from)
+                .and_then(|alias| collection.find_key(&shift_prefix, &alias))
+                .or_else(|| collection.find_key(&shift_prefix, &number));
+            let reference = match found {
+                Some(key) => ShiftReference {
+                    job: job_index,
+                    shift_number,
+                    link: Some(format!(
+                        "{}{}",
+                        tenant::current().canonical_prefix(&collection.pages[key].shift_prefix),
+                        key_number(key)
+                    )),
+                    warning: None,
+                },
+                None => ShiftReference {
+                    job: job_index,
+                    shift_number,
+                    link: None,
+                    warning: Some(format!(
+                        "Shift {shift_number} is not in the timetable of {}",
+                        collection.valid_from
+                    )),
+                },
+            };
+            references.push(reference);
+        }
+    }
+    references
+}
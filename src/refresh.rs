@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use actix_web::{HttpResponse, Responder, get, http::header::ContentType};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{
+    GenResult, IndexSummary, admin::get_index_status, health, load_pdf_and_index, tenant, webhook,
+};
+
+// Progress of the latest refresh per tenant
+static REFRESH_PROGRESS: LazyLock<RwLock<HashMap<String, RefreshProgress>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+#[derive(Serialize, Clone, Default)]
+pub struct RefreshProgress {
+    pub running: bool,
+    // Unix timestamps
+    pub started: Option<i64>,
+    pub finished: Option<i64>,
+    // False if nothing changed and the refresh wasn't forced, None while running
+    pub reindexed: Option<bool>,
+    pub total_files: usize,
+    #[serde(flatten)]
+    pub summary: IndexSummary,
+    // Set when the refresh as a whole failed
+    pub error: Option<String>,
+    // Another refresh was asked for while this one ran, it starts when this one finishes
+    pub pending: bool,
+}
+
+fn update_progress(update: impl FnOnce(&mut RefreshProgress)) {
+    match REFRESH_PROGRESS.write() {
+        Ok(mut progress) => update(progress.entry(tenant::current().name).or_default()),
+        Err(err) => error!("Failed to update refresh progress: {err}"),
+    }
+}
+
+pub fn current_progress() -> RefreshProgress {
+    REFRESH_PROGRESS
+        .read()
+        .ok()
+        .and_then(|progress| progress.get(&tenant::current().name).cloned())
+        .unwrap_or_default()
+}
+
+// Called by the indexer, also when it runs at startup
+pub fn report_total_files(total_files: usize) {
+    update_progress(|progress| progress.total_files = total_files);
+}
+
+pub fn report_summary(summary: &IndexSummary) {
+    update_progress(|progress| progress.summary = summary.clone());
+}
+
+fn refresh(force: bool) -> GenResult<bool> {
+    if !force && !get_index_status()?.reindex_needed {
+        return Ok(false);
+    }
+    // Forcing rebuilds everything, otherwise only the changed files are parsed
+    let summary = load_pdf_and_index(force)?;
+    health::clear_startup_errors();
+    webhook::refresh_completed(&summary);
+    Ok(true)
+}
+
+// Start a refresh for the current tenant on its own thread.
+// Returns false if one is already running
+pub fn start_refresh(force: bool) -> bool {
+    let mut started = false;
+    update_progress(|progress| {
+        if progress.running {
+            return;
+        }
+        *progress = RefreshProgress {
+            running: true,
+            started: Some(OffsetDateTime::now_utc().unix_timestamp()),
+            ..Default::default()
+        };
+        started = true;
+    });
+    if !started {
+        return false;
+    }
+    let tenant = tenant::current();
+    std::thread::spawn(move || {
+        tenant::with_tenant(tenant, || {
+            let mut force = force;
+            loop {
+                let result = refresh(force);
+                if let Err(err) = &result {
+                    error!("Refresh failed: {err}");
+                }
+                let mut run_again = false;
+                update_progress(|progress| {
+                    // Checked under the same lock that clears running, so no request gets lost
+                    if progress.pending {
+                        *progress = RefreshProgress {
+                            running: true,
+                            started: Some(OffsetDateTime::now_utc().unix_timestamp()),
+                            ..Default::default()
+                        };
+                        run_again = true;
+                        return;
+                    }
+                    progress.running = false;
+                    progress.finished = Some(OffsetDateTime::now_utc().unix_timestamp());
+                    match result {
+                        Ok(reindexed) => progress.reindexed = Some(reindexed),
+                        Err(err) => progress.error = Some(err.to_string()),
+                    }
+                });
+                if !run_again {
+                    break;
+                }
+                force = false;
+            }
+        })
+    });
+    true
+}
+
+// Start a refresh, or have the running one run again once it finishes.
+// Returns false if the refresh was queued
+pub fn queue_refresh() -> bool {
+    if start_refresh(false) {
+        return true;
+    }
+    update_progress(|progress| {
+        if progress.running {
+            progress.pending = true;
+        }
+    });
+    // The running refresh could have finished in between
+    start_refresh(false)
+}
+
+// Wait until no refresh is running for any tenant, or the timeout passed
+pub async fn wait_for_running_refreshes(timeout: Duration) {
+    let running = || {
+        REFRESH_PROGRESS
+            .read()
+            .is_ok_and(|progress| progress.values().any(|progress| progress.running))
+    };
+    if !running() {
+        return;
+    }
+    info!("Waiting for the running refresh to finish before shutting down");
+    let start = Instant::now();
+    while running() {
+        if start.elapsed() > timeout {
+            warn!("Refresh didn't finish in time, the current collections are kept");
+            return;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+#[get("/refresh/status")]
+async fn refresh_status() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string_pretty(&current_progress()).unwrap())
+}
@@ -0,0 +1,142 @@
+use time::{Time, format_description::BorrowedFormatItem, macros::format_description};
+
+use crate::{
+    DATE_FORMAT,
+    parsing::shift_structs::{
+        JobDrivingType, JobMessageType, JobType, Shift, ShiftJob, ShiftValid,
+    },
+};
+
+pub const TIME_FORMAT: &[BorrowedFormatItem<'_>] = format_description!["[hour]:[minute]"];
+
+pub const JOB_HEADER: [&str; 5] = ["Start", "End", "Job", "Line", "Route"];
+
+// One job of a shift, as text for every column of JOB_HEADER
+pub fn job_row(job: &ShiftJob) -> [String; 5] {
+    let format_time = |time: Option<Time>| {
+        time.and_then(|time| time.format(TIME_FORMAT).ok())
+            .unwrap_or_default()
+    };
+    let (job_name, line) = describe_job(&job.job_type);
+    let route = match (&job.start_location, &job.end_location) {
+        (Some(start), Some(end)) if start != end => format!("{start} → {end}"),
+        (Some(start), _) => start.clone(),
+        (None, Some(end)) => format!("→ {end}"),
+        (None, None) => String::new(),
+    };
+    [
+        format_time(job.start),
+        format_time(job.end),
+        job_name,
+        line,
+        route,
+    ]
+}
+
+// The name of the job and the line number if it is driven on one
+fn describe_job(job_type: &JobType) -> (String, String) {
+    match job_type {
+        JobType::Rijden { drive_type } => match drive_type {
+            JobDrivingType::Lijn(line) => ("Rijden".to_string(), line.to_string()),
+            JobDrivingType::Mat => ("Rijden".to_string(), "Mat".to_string()),
+        },
+        JobType::Melding { message } => {
+            let message = match message {
+                JobMessageType::Meenemen { dienstnummers } => format!(
+                    "Meenemen {}",
+                    dienstnummers
+                        .iter()
+                        .map(|number| number.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+                JobMessageType::Passagieren {
+                    dienstnummer,
+                    omloop,
+                } => format!("Passagieren {dienstnummer} (omloop {omloop})"),
+                JobMessageType::BusOp { lijn } => return ("Bus op".to_string(), lijn.to_string()),
+                JobMessageType::NeemBus { bustype } => format!("Neem bus {bustype}"),
+                JobMessageType::Other(message) => message.clone(),
+            };
+            (message, String::new())
+        }
+        other => (format!("{other:?}"), String::new()),
+    }
+}
+
+fn describe_valid_on(valid_on: &ShiftValid) -> &'static str {
+    match valid_on {
+        ShiftValid::Weekdays => "Weekdays",
+        ShiftValid::Wednesday => "Wednesday",
+        ShiftValid::WeekdaysExceptWednesday => "Weekdays except Wednesdays",
+        ShiftValid::Saturday => "Saturday",
+        ShiftValid::Sunday => "Sunday",
+        ShiftValid::Unknown => "Unknown",
+    }
+}
+
+// Metadata shown above the jobs, as (label, value)
+pub fn shift_metadata(shift: &Shift) -> Vec<(&'static str, String)> {
+    vec![
+        ("Shift", shift.shift_nr.clone()),
+        ("Valid on", describe_valid_on(&shift.valid_on).to_string()),
+        ("Location", shift.location.clone()),
+        (
+            "Valid from",
+            shift.starting_date.format(DATE_FORMAT).unwrap_or_default(),
+        ),
+    ]
+}
+
+// Monospace rendering with aligned columns, for terminals, SMS gateways and old printers
+pub fn shift_to_text(shift: &Shift) -> String {
+    let mut text = String::new();
+    for (label, value) in shift_metadata(shift) {
+        text.push_str(&format!("{:<12}{value}\n", format!("{label}:")));
+    }
+    text.push('\n');
+    let rows: Vec<[String; 5]> = shift
+        .job
+        .iter()
+        .filter(|job| !job.empty())
+        .map(job_row)
+        .collect();
+    let mut widths = JOB_HEADER.map(|header| header.chars().count());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+    let header = JOB_HEADER.map(|header| header.to_string());
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| {
+                let padding = width - column.chars().count();
+                format!("{column}{}", " ".repeat(padding))
+            })
+            .collect::<Vec<String>>()
+            .join("  ");
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+// Metadata header and a table of the jobs, for pasting into the wiki and chat tools
+pub fn shift_to_markdown(shift: &Shift) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|");
+    let mut markdown = format!("## Shift {}\n\n", escape(&shift.shift_nr));
+    for (label, value) in shift_metadata(shift) {
+        markdown.push_str(&format!("- **{label}:** {}\n", escape(&value)));
+    }
+    markdown.push('\n');
+    markdown.push_str(&format!("| {} |\n", JOB_HEADER.join(" | ")));
+    markdown.push_str(&format!("|{}\n", "---|".repeat(JOB_HEADER.len())));
+    for job in shift.job.iter().filter(|job| !job.empty()) {
+        let row = job_row(job).map(|cell| escape(&cell));
+        markdown.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    markdown
+}
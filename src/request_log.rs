@@ -0,0 +1,75 @@
+// Access log with one JSON object per request on stdout, so it can be ingested without parsing
+// the text logs. The other logs go to stderr, so both streams stay separate
+use std::{future::Future, io::Write, pin::Pin, time::Instant};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse},
+};
+use serde::Serialize;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::config::Config;
+
+#[derive(Serialize)]
+struct AccessLogEntry {
+    timestamp: String,
+    method: String,
+    path: String,
+    query: String,
+    // None when the request failed before a response was made
+    status: Option<u16>,
+    latency_ms: f64,
+    // Taken from Forwarded or X-Forwarded-For when behind a proxy
+    client_ip: Option<String>,
+}
+
+fn write_entry(entry: &AccessLogEntry) {
+    let Ok(line) = serde_json::to_string(entry) else {
+        return;
+    };
+    if let Err(err) = writeln!(std::io::stdout().lock(), "{line}") {
+        warn!("Failed to write access log: {err}");
+    }
+}
+
+type LoggedResponse<B> = Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, Error>>>>;
+
+// Middleware logging the method, path, status, latency and client of every request
+pub fn log_request<S, B>(request: ServiceRequest, service: &S) -> LoggedResponse<B>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    if !Config::get().access_log {
+        return Box::pin(service.call(request));
+    }
+    let start = Instant::now();
+    let mut entry = AccessLogEntry {
+        timestamp: OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default(),
+        method: request.method().to_string(),
+        path: request.path().to_string(),
+        query: request.query_string().to_string(),
+        status: None,
+        latency_ms: 0.0,
+        client_ip: request
+            .connection_info()
+            .realip_remote_addr()
+            .map(str::to_string),
+    };
+    let response = service.call(request);
+    Box::pin(async move {
+        let response = response.await;
+        entry.status = response
+            .as_ref()
+            .ok()
+            .map(|response| response.status().as_u16());
+        entry.latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+        write_entry(&entry);
+        response
+    })
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{LazyLock, Mutex, RwLock},
+    time::Instant,
+};
+
+use lru::LruCache;
+use time::{Date, OffsetDateTime};
+
+use crate::{GenResult, NEGATIVE_CACHE_TTL, config::Config, tenant};
+
+// Serialized responses which only change when the collection is reloaded, like the index and statistics.
+// Clients choose the dates and filters in the keys, so the least recently used one is dropped when full
+static RESPONSE_CACHE: LazyLock<Mutex<LruCache<String, String>>> =
+    LazyLock::new(|| Mutex::new(LruCache::new(NonZeroUsize::MIN)));
+
+// Lookups that found nothing, with the moment they were made. These expire after a short while
+static NOT_FOUND_CACHE: LazyLock<RwLock<HashMap<String, Instant>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+// Assembled shift PDFs, the least recently requested one is dropped when the cache is full.
+// Reading a lookup also updates the order, so this needs a Mutex instead of a RwLock
+static PDF_CACHE: LazyLock<Mutex<LruCache<String, Vec<u8>>>> =
+    LazyLock::new(|| Mutex::new(LruCache::new(NonZeroUsize::MIN)));
+
+// Build a cache key, a missing date means today so the key doesn't go stale after midnight
+pub fn cache_key(endpoint: &str, date: Option<Date>, filters: &str) -> String {
+    let date = date.unwrap_or(OffsetDateTime::now_utc().date());
+    format!("{}|{endpoint}|{date}|{filters}", tenant::current().name)
+}
+
+pub fn cached(key: String, build: impl FnOnce() -> GenResult<String>) -> GenResult<String> {
+    if let Some(response) = RESPONSE_CACHE.lock()?.get(&key) {
+        return Ok(response.clone());
+    }
+    let response = build()?;
+    // A size of 0 disables the cache
+    if let Some(size) = NonZeroUsize::new(Config::get().response_cache_entries) {
+        let mut cache = RESPONSE_CACHE.lock()?;
+        // The size can change when the configuration is reloaded
+        if cache.cap() != size {
+            cache.resize(size);
+        }
+        cache.put(key, response.clone());
+    }
+    Ok(response)
+}
+
+pub fn is_known_missing(key: &str) -> bool {
+    NOT_FOUND_CACHE.read().is_ok_and(|cache| {
+        cache
+            .get(key)
+            .is_some_and(|looked_up| looked_up.elapsed() < NEGATIVE_CACHE_TTL)
+    })
+}
+
+pub fn remember_missing(key: String) {
+    if let Ok(mut cache) = NOT_FOUND_CACHE.write() {
+        // Drop expired entries, so clients asking for random numbers can't grow the cache forever
+        cache.retain(|_, looked_up| looked_up.elapsed() < NEGATIVE_CACHE_TTL);
+        cache.insert(key, Instant::now());
+    }
+}
+
+fn pdf_key(valid_from: Date, shift_number: &str) -> String {
+    format!("{}|{valid_from}|{shift_number}", tenant::current().name)
+}
+
+pub fn get_pdf(valid_from: Date, shift_number: &str) -> Option<Vec<u8>> {
+    PDF_CACHE
+        .lock()
+        .ok()?
+        .get(&pdf_key(valid_from, shift_number))
+        .cloned()
+}
+
+pub fn store_pdf(valid_from: Date, shift_number: &str, bytes: &[u8]) {
+    // A size of 0 disables the cache
+    let Some(size) = NonZeroUsize::new(Config::get().pdf_cache_entries) else {
+        return;
+    };
+    if let Ok(mut cache) = PDF_CACHE.lock() {
+        // The size can change when the configuration is reloaded
+        if cache.cap() != size {
+            cache.resize(size);
+        }
+        cache.put(pdf_key(valid_from, shift_number), bytes.to_vec());
+    }
+}
+
+pub fn clear() {
+    match RESPONSE_CACHE.lock() {
+        Ok(mut cache) => cache.clear(),
+        Err(err) => error!("Failed to clear response cache: {err}"),
+    }
+    match NOT_FOUND_CACHE.write() {
+        Ok(mut cache) => cache.clear(),
+        Err(err) => error!("Failed to clear not found cache: {err}"),
+    }
+    match PDF_CACHE.lock() {
+        Ok(mut cache) => cache.clear(),
+        Err(err) => error!("Failed to clear PDF cache: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenant;
+
+    #[test]
+    fn response_cache_is_bounded() {
+        tenant::with_tenant(tenant::test_tenant("response_cache"), || {
+            let size = Config::get().response_cache_entries;
+            for filter in 0..size + 10 {
+                let key = cache_key("index", None, &filter.to_string());
+                cached(key, || Ok(filter.to_string())).unwrap();
+            }
+            let cache = RESPONSE_CACHE.lock().unwrap();
+            assert!(cache.len() <= size);
+            // The oldest filters were dropped, the newest one is still there
+            assert!(!cache.contains(&cache_key("index", None, "0")));
+            assert!(cache.contains(&cache_key("index", None, &(size + 9).to_string())));
+        });
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::Arc,
+};
+
+use actix_web::{HttpResponse, Responder, ResponseError, post, web};
+use qpdf::QPdf;
+use serde::Deserialize;
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    DATE_FORMAT, GenResult, PDF_SEMAPHORE, ambiguous_shift,
+    collection::{PdfTimetableCollection, ShiftData},
+    copy_shift_pages,
+    error::{HermesError, OptionResult},
+    find_shift, holidays, index, parse_date, tenant,
+};
+
+// A month of shifts, more than that is probably a mistake
+const MAX_ROSTER_SHIFTS: usize = 31;
+
+// Either "G1234" or {"shift": "G1234", "date": "05-01-2026"}, without a date today is used
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RosterEntry {
+    Shift(String),
+    Dated { shift: String, date: Option<String> },
+}
+
+// Look up the timetable and pages of every shift, in the order they were requested
+fn resolve_roster(
+    entries: &[RosterEntry],
+) -> Result<Vec<(Arc<PdfTimetableCollection>, ShiftData)>, HermesError> {
+    let today = OffsetDateTime::now_utc().date();
+    let mut shifts = vec![];
+    for entry in entries {
+        let (request, requested_date) = match entry {
+            RosterEntry::Shift(shift) => (shift, None),
+            RosterEntry::Dated { shift, date: None } => (shift, None),
+            RosterEntry::Dated {
+                shift,
+                date: Some(date),
+            } => match parse_date(date) {
+                Some(date) => (shift, Some(date)),
+                None => {
+                    return Err(HermesError::DateParse(date.clone()));
+                }
+            },
+        };
+        let date = requested_date.unwrap_or(today);
+        let request = request.to_uppercase();
+        let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+        let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+        if let Some(err) = ambiguous_shift(&shift_prefix, &shift_number, date) {
+            return Err(err);
+        }
+        let found = find_shift(&shift_prefix, &shift_number, date)
+            .map_err(HermesError::from)?
+            .filter(|(_, shift_data, _)| {
+                shift_prefix.is_empty()
+                    || tenant::current()
+                        .prefixes_equivalent(&shift_prefix, &shift_data.shift_prefix)
+            });
+        let Some((collection, shift_data, found_key)) = found else {
+            return Err(HermesError::ShiftNotFound {
+                shift: format!("{request} on {}", date_string(date)),
+                suggestions: index::similar_shifts(&request, Some(date)),
+            });
+        };
+        // A dated shift uses the variant running on that weekday, holidays count as Sunday
+        let shift_data = requested_date
+            .and_then(|date| collection.variant_on(&found_key, holidays::service_weekday(date)))
+            .map_or(shift_data, |(_, variant)| variant);
+        shifts.push((collection, shift_data));
+    }
+    Ok(shifts)
+}
+
+fn date_string(date: Date) -> String {
+    date.format(DATE_FORMAT).unwrap_or_default()
+}
+
+fn merge_shift_pdfs(shifts: Vec<(Arc<PdfTimetableCollection>, ShiftData)>) -> GenResult<Vec<u8>> {
+    let merged_pdf = QPdf::empty();
+    // Most shifts come from the same few files, so every file is only opened once
+    let mut source_pdfs: HashMap<String, QPdf> = HashMap::new();
+    for (collection, shift_data) in shifts {
+        let path = collection
+            .files
+            .get(&shift_data.file_id)
+            .result_reason("No PDF found")?;
+        let source_pdf = match source_pdfs.entry(path.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(QPdf::read(path)?),
+        };
+        copy_shift_pages(source_pdf, &merged_pdf, &shift_data.pages)?;
+    }
+    Ok(merged_pdf.writer().write_to_memory()?)
+}
+
+#[post("/roster/pdf")]
+async fn roster_pdf(entries: web::Json<Vec<RosterEntry>>) -> impl Responder {
+    if entries.is_empty() || entries.len() > MAX_ROSTER_SHIFTS {
+        return HermesError::BadRequest(format!(
+            "A roster should contain between 1 and {MAX_ROSTER_SHIFTS} shifts"
+        ))
+        .error_response();
+    }
+    let shifts = match resolve_roster(&entries) {
+        Ok(shifts) => shifts,
+        Err(err) => return err.error_response(),
+    };
+    let merged = async {
+        let _permit = PDF_SEMAPHORE
+            .acquire()
+            .await
+            .map_err(|err| err.to_string())?;
+        web::block(move || merge_shift_pdfs(shifts).map_err(|err| err.to_string()))
+            .await
+            .map_err(|err| err.to_string())?
+    };
+    match merged.await {
+        Ok(bytes) => HttpResponse::Ok()
+            .content_type("application/pdf")
+            .body(bytes),
+        Err(err) => HermesError::PdfLoad(err).error_response(),
+    }
+}
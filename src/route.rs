@@ -0,0 +1,161 @@
+// The places a shift visits in order, for drawing the route of the day on a map
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime, Time};
+
+use crate::{
+    DATE_FORMAT, GenResult, ambiguous_shift,
+    error::HermesError,
+    find_shift, holidays, index, load_shift, parse_optional_date,
+    parsing::shift_structs::{Coordinates, Shift},
+    render::TIME_FORMAT,
+    tenant,
+};
+
+#[derive(Deserialize)]
+struct RoutePath {
+    shift_number: String,
+}
+
+#[derive(Deserialize)]
+struct RouteQuery {
+    date: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ShiftRoute {
+    shift: String,
+    timetable: String,
+    stops: Vec<RouteStop>,
+}
+
+// A location of the route, the arrival is when the driver gets there and the departure when they
+// leave again. Times after midnight are on the day after the service date
+#[derive(Serialize)]
+struct RouteStop {
+    location: String,
+    arrival: Option<String>,
+    arrival_next_day: bool,
+    departure: Option<String>,
+    departure_next_day: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coordinates: Option<Coordinates>,
+}
+
+fn format_time(time: Option<Time>) -> Option<String> {
+    time.and_then(|time| time.format(TIME_FORMAT).ok())
+}
+
+// Jobs that start where the previous one ended extend the stay at that location, so every stop
+// is a different location than the one before it
+fn route_stops(shift: &Shift) -> Vec<RouteStop> {
+    let mut stops: Vec<RouteStop> = vec![];
+    for job in &shift.job {
+        let Some(start_location) = &job.start_location else {
+            continue;
+        };
+        // Jobs without an end location end where they started
+        let end = match &job.end_location {
+            Some(end_location) => (end_location, job.end_coordinates),
+            None => (start_location, job.start_coordinates),
+        };
+        for (location, coordinates, time, next_day) in [
+            (
+                start_location,
+                job.start_coordinates,
+                job.start,
+                job.start_next_day,
+            ),
+            (end.0, end.1, job.end, job.end_next_day),
+        ] {
+            match stops.last_mut() {
+                Some(stop) if stop.location == *location => {
+                    if time.is_some() {
+                        stop.departure = format_time(time);
+                        stop.departure_next_day = next_day;
+                    }
+                }
+                _ => stops.push(RouteStop {
+                    location: location.clone(),
+                    arrival: format_time(time),
+                    arrival_next_day: next_day,
+                    departure: format_time(time),
+                    departure_next_day: next_day,
+                    coordinates,
+                }),
+            }
+        }
+    }
+    stops
+}
+
+// The route of the shift in the timetable valid on the date, with a date the variant running on
+// that weekday is used. None if the shift doesn't exist then
+fn shift_route(
+    request: &str,
+    date: Date,
+    variant_date: Option<Date>,
+) -> GenResult<Option<ShiftRoute>> {
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    let Some((collection, shift_data, found_number)) =
+        find_shift(&shift_prefix, &shift_number, date)?
+    else {
+        return Ok(None);
+    };
+    if !shift_prefix.is_empty()
+        && !tenant::current().prefixes_equivalent(&shift_prefix, &shift_data.shift_prefix)
+    {
+        return Ok(None);
+    }
+    let variant_name = variant_date
+        .and_then(|date| collection.variant_on(&found_number, holidays::service_weekday(date)))
+        .map_or(found_number, |(variant_name, _)| variant_name);
+    let shift = load_shift(variant_name, collection.valid_from)?;
+    Ok(Some(ShiftRoute {
+        shift: format!(
+            "{}{}",
+            tenant::current().canonical_prefix(&shift_data.shift_prefix),
+            shift_number
+        ),
+        timetable: collection.valid_from.format(DATE_FORMAT)?,
+        stops: route_stops(&shift),
+    }))
+}
+
+#[get("/shift/{shift_number}/route")]
+async fn route(path: web::Path<RoutePath>, query: web::Query<RouteQuery>) -> impl Responder {
+    let request = path.into_inner().shift_number.to_uppercase();
+    let custom_date = match parse_optional_date(&query.date) {
+        Ok(date) => date,
+        Err(err) => return err.error_response(),
+    };
+    let date = custom_date.unwrap_or(OffsetDateTime::now_utc().date());
+    let shift_prefix: String = request.chars().filter(|c| c.is_alphabetic()).collect();
+    let shift_number: String = request.chars().filter(|c| c.is_numeric()).collect();
+    if let Some(err) = ambiguous_shift(&shift_prefix, &shift_number, date) {
+        return err.error_response();
+    }
+    let tenant = tenant::current();
+    let shift_request = request.clone();
+    let route = web::block(move || {
+        tenant::with_tenant(tenant, || shift_route(&shift_request, date, custom_date))
+            .map_err(|err| err.to_string())
+    })
+    .await;
+    let route = match route {
+        Ok(Ok(Some(route))) => route,
+        Ok(Ok(None)) => {
+            return HermesError::ShiftNotFound {
+                suggestions: index::similar_shifts(&request, custom_date),
+                shift: request,
+            }
+            .error_response();
+        }
+        Ok(Err(err)) => return HermesError::Internal(err).error_response(),
+        Err(err) => return HermesError::Internal(err.to_string()).error_response(),
+    };
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string_pretty(&route).unwrap())
+}
@@ -0,0 +1,199 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
+use serde::{Deserialize, Serialize};
+use time::{Date, Time, Weekday};
+
+use crate::{
+    DATE_FORMAT, GenResult,
+    collection::{PdfTimetableCollection, shift_name},
+    error::HermesError,
+    get_valid_timetables, load_shift, parse_optional_date,
+    parsing::shift_structs::{Shift, ShiftTimes, ShiftType, ShiftValid},
+    render::TIME_FORMAT,
+    return_error, tenant,
+};
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    // Timetables that are valid on this date are searched, today by default
+    date: Option<String>,
+    // Part of the location of the shift or of one of its jobs
+    location: Option<String>,
+    // vroeg, tussen, dag, gebroken or laat
+    #[serde(rename = "type")]
+    shift_type: Option<String>,
+    // Weekday the shift should run on, like "saturday"
+    valid_on: Option<String>,
+    starts_after: Option<String>,
+    ends_before: Option<String>,
+}
+
+// The parsed query, so the shifts don't have to parse it again
+struct SearchFilter {
+    location: Option<String>,
+    shift_type: Option<String>,
+    valid_on: Option<Vec<ShiftValid>>,
+    starts_after: Option<Time>,
+    ends_before: Option<Time>,
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    shift_number: String,
+    valid_from: String,
+    location: String,
+    valid_on: ShiftValid,
+    shift_type: Option<ShiftType>,
+    #[serde(flatten)]
+    times: ShiftTimes,
+}
+
+fn shift_type_name(shift_type: &ShiftType) -> &'static str {
+    match shift_type {
+        ShiftType::Vroeg => "vroeg",
+        ShiftType::Tussen => "tussen",
+        ShiftType::Dag => "dag",
+        ShiftType::Gebroken { .. } => "gebroken",
+        ShiftType::Laat => "laat",
+    }
+}
+
+fn parse_weekday(weekday: &str) -> Option<Weekday> {
+    Some(match weekday.to_lowercase().as_str() {
+        "monday" => Weekday::Monday,
+        "tuesday" => Weekday::Tuesday,
+        "wednesday" => Weekday::Wednesday,
+        "thursday" => Weekday::Thursday,
+        "friday" => Weekday::Friday,
+        "saturday" => Weekday::Saturday,
+        "sunday" => Weekday::Sunday,
+        _ => return None,
+    })
+}
+
+impl SearchFilter {
+    fn from_query(query: &SearchQuery) -> Result<Self, String> {
+        let parse_time = |time: &Option<String>| match time {
+            Some(time) => Time::parse(time, TIME_FORMAT)
+                .map(Some)
+                .map_err(|_| format!("\"{time}\" is not a valid time, use hh:mm")),
+            None => Ok(None),
+        };
+        let valid_on = match &query.valid_on {
+            Some(weekday) => Some(ShiftValid::for_weekday(
+                parse_weekday(weekday).ok_or(format!("\"{weekday}\" is not a weekday"))?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            location: query
+                .location
+                .as_ref()
+                .map(|location| location.to_lowercase()),
+            shift_type: query
+                .shift_type
+                .as_ref()
+                .map(|shift_type| shift_type.to_lowercase()),
+            valid_on,
+            starts_after: parse_time(&query.starts_after)?,
+            ends_before: parse_time(&query.ends_before)?,
+        })
+    }
+
+    fn matches(&self, shift: &Shift) -> bool {
+        let location_matches = self.location.as_ref().is_none_or(|location| {
+            let contains = |name: &String| name.to_lowercase().contains(location);
+            contains(&shift.location)
+                || shift.job.iter().any(|job| {
+                    job.start_location.as_ref().is_some_and(contains)
+                        || job.end_location.as_ref().is_some_and(contains)
+                })
+        });
+        let type_matches = self.shift_type.as_ref().is_none_or(|requested| {
+            shift
+                .shift_type
+                .as_ref()
+                .is_some_and(|shift_type| shift_type_name(shift_type) == requested)
+        });
+        let valid_on_matches = self
+            .valid_on
+            .as_ref()
+            .is_none_or(|valid_on| valid_on.contains(&shift.valid_on));
+        let starts_after_matches = self.starts_after.is_none_or(|starts_after| {
+            shift
+                .times
+                .start_time
+                .is_some_and(|start| start >= starts_after)
+        });
+        let ends_before_matches = self
+            .ends_before
+            .is_none_or(|ends_before| shift.times.end_time.is_some_and(|end| end <= ends_before));
+        location_matches
+            && type_matches
+            && valid_on_matches
+            && starts_after_matches
+            && ends_before_matches
+    }
+}
+
+// Read every shift of the valid timetables and keep the ones that match
+fn search_shifts(date: Option<Date>, filter: &SearchFilter) -> GenResult<Vec<SearchResult>> {
+    // Later timetables replace the shifts of earlier ones, just like the index
+    let mut available_shifts: BTreeMap<String, Arc<PdfTimetableCollection>> = BTreeMap::new();
+    for timetable in get_valid_timetables(date)?.0 {
+        for shift_number in timetable.pages.keys() {
+            available_shifts.insert(shift_number.clone(), timetable.clone());
+        }
+    }
+    let mut results = vec![];
+    for (shift_number, timetable) in available_shifts {
+        let shift = match load_shift(shift_number.clone(), timetable.valid_from) {
+            Ok(shift) => shift,
+            Err(err) => {
+                warn!("Skipping shift {shift_number} while searching: {err}");
+                continue;
+            }
+        };
+        if !filter.matches(&shift) {
+            continue;
+        }
+        let Some(shift_data) = timetable.pages.get(&shift_number) else {
+            continue;
+        };
+        results.push(SearchResult {
+            shift_number: shift_name(&shift_number, shift_data),
+            valid_from: timetable.valid_from.format(DATE_FORMAT)?,
+            location: shift.location,
+            valid_on: shift.valid_on,
+            shift_type: shift.shift_type,
+            times: shift.times,
+        });
+    }
+    Ok(results)
+}
+
+#[get("/search")]
+async fn search(query: web::Query<SearchQuery>) -> impl Responder {
+    let date = match parse_optional_date(&query.date) {
+        Ok(date) => date,
+        Err(err) => return err.error_response(),
+    };
+    let filter = match SearchFilter::from_query(&query) {
+        Ok(filter) => filter,
+        Err(err) => return HermesError::BadRequest(err).error_response(),
+    };
+    // Every shift file is read, so don't do it on the worker thread
+    let tenant = tenant::current();
+    let results = web::block(move || {
+        tenant::with_tenant(tenant, || search_shifts(date, &filter)).map_err(|err| err.to_string())
+    })
+    .await;
+    match results {
+        Ok(Ok(results)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&results).unwrap()),
+        Ok(Err(err)) => return_error(err),
+        Err(err) => return_error(err.to_string()),
+    }
+}
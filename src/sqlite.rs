@@ -0,0 +1,209 @@
+// Optional SQLite copy of the collections, with the timetables, files and shifts
+// stored relationally so lookups don't have to go through the JSON files.
+// The JSON collection folder stays the source of truth, the database is rebuilt from it.
+// Without the sqlite feature, or without a sqlite_path, every query returns None and the
+// callers use the loaded collections instead.
+use time::Date;
+
+use crate::collection::{PdfTimetableCollection, ShiftData};
+#[cfg(feature = "sqlite")]
+use crate::{GenResult, ISO_DATE_FORMAT, config::Config, render::TIME_FORMAT, tenant};
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, params};
+#[cfg(feature = "sqlite")]
+use std::sync::{LazyLock, Mutex};
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS timetables (
+    tenant TEXT NOT NULL,
+    valid_from TEXT NOT NULL,
+    PRIMARY KEY (tenant, valid_from)
+);
+CREATE TABLE IF NOT EXISTS files (
+    tenant TEXT NOT NULL,
+    valid_from TEXT NOT NULL,
+    file_id INTEGER NOT NULL,
+    path TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS shifts (
+    tenant TEXT NOT NULL,
+    valid_from TEXT NOT NULL,
+    shift_number TEXT NOT NULL,
+    shift_prefix TEXT NOT NULL,
+    file_id INTEGER NOT NULL,
+    start_time TEXT,
+    end_time TEXT,
+    location TEXT,
+    valid_on TEXT,
+    shift_data TEXT NOT NULL,
+    PRIMARY KEY (tenant, valid_from, shift_number)
+);
+CREATE INDEX IF NOT EXISTS shifts_by_number ON shifts (tenant, shift_number, valid_from);
+";
+
+// The connection and the path it was opened with, so a changed path opens a new one
+#[cfg(feature = "sqlite")]
+static CONNECTION: LazyLock<Mutex<Option<(String, Connection)>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+// Run a query on the database, None if there is no database configured or the query failed
+#[cfg(feature = "sqlite")]
+fn with_connection<T>(query: impl FnOnce(&mut Connection) -> GenResult<T>) -> Option<T> {
+    let sqlite_path = Config::get().sqlite_path.clone()?;
+    let mut connection_guard = CONNECTION.lock().ok()?;
+    if connection_guard
+        .as_ref()
+        .is_none_or(|(path, _)| *path != sqlite_path)
+    {
+        let connection = Connection::open(&sqlite_path)
+            .and_then(|connection| connection.execute_batch(SCHEMA).map(|_| connection));
+        match connection {
+            Ok(connection) => *connection_guard = Some((sqlite_path, connection)),
+            Err(err) => {
+                warn!("Could not open {sqlite_path}: {err}");
+                return None;
+            }
+        }
+    }
+    let (_, connection) = connection_guard.as_mut()?;
+    match query(connection) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("SQLite query failed: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn date_key(date: Date) -> String {
+    date.format(ISO_DATE_FORMAT).unwrap_or_default()
+}
+
+#[cfg(feature = "sqlite")]
+fn parse_date_key(date: &str) -> rusqlite::Result<Date> {
+    Date::parse(date, ISO_DATE_FORMAT)
+        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))
+}
+
+#[cfg(feature = "sqlite")]
+fn format_time(time: Option<time::Time>) -> Option<String> {
+    time.and_then(|time| time.format(TIME_FORMAT).ok())
+}
+
+// Replace the rows of the current tenant with the loaded collections and their shifts
+#[cfg(feature = "sqlite")]
+pub fn sync_collections(collections: &[PdfTimetableCollection]) {
+    let tenant = tenant::current().name;
+    with_connection(|connection| {
+        let transaction = connection.transaction()?;
+        for table in ["timetables", "files", "shifts"] {
+            transaction.execute(&format!("DELETE FROM {table} WHERE tenant = ?1"), [&tenant])?;
+        }
+        for collection in collections {
+            let valid_from = date_key(collection.valid_from);
+            transaction.execute(
+                "INSERT INTO timetables (tenant, valid_from) VALUES (?1, ?2)",
+                params![tenant, valid_from],
+            )?;
+            for (file_id, path) in &collection.files {
+                transaction.execute(
+                    "INSERT INTO files (tenant, valid_from, file_id, path) VALUES (?1, ?2, ?3, ?4)",
+                    params![tenant, valid_from, *file_id as i64, path],
+                )?;
+            }
+            for (shift_number, shift_data) in &collection.pages {
+                // A shift without its JSON is still stored, it only misses the location and weekdays
+                let shift = collection.shifts.get(shift_number);
+                transaction.execute(
+                    "INSERT INTO shifts (tenant, valid_from, shift_number, shift_prefix, file_id, \
+                     start_time, end_time, location, valid_on, shift_data) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        tenant,
+                        valid_from,
+                        shift_number,
+                        shift_data.shift_prefix,
+                        shift_data.file_id as i64,
+                        format_time(shift_data.times.as_ref().and_then(|times| times.start_time)),
+                        format_time(shift_data.times.as_ref().and_then(|times| times.end_time)),
+                        shift.as_ref().map(|shift| shift.location.clone()),
+                        shift
+                            .as_ref()
+                            .and_then(|shift| serde_json::to_string(&shift.valid_on).ok()),
+                        serde_json::to_string(shift_data)?,
+                    ],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    });
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn sync_collections(_collections: &[PdfTimetableCollection]) {}
+
+// Valid from dates of the timetables valid on the date that contain the shift number with any
+// prefix, newest first. Shifts are stored under their key, or the bare number in old collections
+#[cfg(feature = "sqlite")]
+pub fn find_shift(shift_number: &str, date: Date) -> Option<Vec<Date>> {
+    let tenant = tenant::current().name;
+    with_connection(|connection| {
+        let mut statement = connection.prepare(
+            "SELECT DISTINCT valid_from FROM shifts WHERE tenant = ?1 \
+             AND (shift_number = ?2 OR shift_number = shift_prefix || ?2) \
+             AND valid_from <= ?3 ORDER BY valid_from DESC",
+        )?;
+        let rows = statement.query_map(params![tenant, shift_number, date_key(date)], |row| {
+            row.get::<_, String>(0)
+        })?;
+        let mut valid_froms = vec![];
+        for row in rows {
+            valid_froms.push(parse_date_key(&row?)?);
+        }
+        Ok(valid_froms)
+    })
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn find_shift(_shift_number: &str, _date: Date) -> Option<Vec<Date>> {
+    None
+}
+
+// Every shift that is valid on the date, from the newest timetable that contains it
+#[cfg(feature = "sqlite")]
+pub fn valid_shifts(date: Date) -> Option<Vec<(String, Date, ShiftData)>> {
+    let tenant = tenant::current().name;
+    with_connection(|connection| {
+        let mut statement = connection.prepare(
+            "SELECT shift_number, valid_from, shift_data FROM shifts AS newest \
+             WHERE tenant = ?1 AND valid_from = (SELECT MAX(valid_from) FROM shifts \
+             WHERE tenant = newest.tenant AND shift_number = newest.shift_number \
+             AND valid_from <= ?2)",
+        )?;
+        let rows = statement.query_map(params![tenant, date_key(date)], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut shifts = vec![];
+        for row in rows {
+            let (shift_number, valid_from, shift_data) = row?;
+            shifts.push((
+                shift_number,
+                parse_date_key(&valid_from)?,
+                serde_json::from_str(&shift_data)?,
+            ));
+        }
+        Ok(shifts)
+    })
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn valid_shifts(_date: Date) -> Option<Vec<(String, Date, ShiftData)>> {
+    None
+}
@@ -1,13 +1,18 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, fs::OpenOptions, io::Write};
 
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::{HttpResponse, Responder, ResponseError, get, http::header::ContentType, web};
 use serde::{Deserialize, Serialize};
-use time::Date;
-use walkdir::WalkDir;
+use time::{Date, OffsetDateTime};
 
 use crate::{
-    DATE_FORMAT, GenResult, collection::PdfTimetableCollection, get_valid_timetables,
-    index::get_valid_shifts, parsing::shift_structs::Shift, return_error,
+    DATE_FORMAT, GenResult, HermesError, STATISTICS_HISTORY_PATH,
+    collection::PdfTimetableCollection,
+    get_valid_timetables,
+    index::get_valid_shifts,
+    metrics::{EndpointStatistics, endpoint_statistics},
+    parse_optional_date,
+    parsing::shift_structs::ShiftParseError,
+    response_cache, return_error, tenant,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -21,7 +26,32 @@ pub struct Statistics {
     pub future_timetables: u64,
     pub recent_timetable: Option<String>,
     pub next_timetable: Option<String>,
-    pub errored_shifts: Vec<String>,
+    pub errored_shifts: u64,
+    // Number of parse errors of each kind, over all errored shifts
+    #[serde(default)]
+    pub error_types: BTreeMap<String, u64>,
+    pub timetable_breakdown: Vec<TimetableStatistics>,
+    // Request metrics since the process started
+    #[serde(default)]
+    pub endpoints: BTreeMap<String, EndpointStatistics>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StatisticsSnapshot {
+    pub timestamp: i64,
+    pub statistics: Statistics,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimetableStatistics {
+    pub valid_from: String,
+    pub shifts: u64,
+    pub files: u64,
+    pub active: bool,
+    #[serde(default)]
+    pub errored_shifts: u64,
+    #[serde(default)]
+    pub parse_errors: u64,
 }
 
 impl Statistics {
@@ -38,15 +68,46 @@ impl Statistics {
             .map(|collection| collection.pages.len())
             .sum::<usize>() as u64;
         let inactive_shifts = shifts - active_shifts;
+        // The active timetables are sorted oldest first
         let recent_timetable = active_timetables
             .0
-            .first()
+            .last()
             .and_then(|timetable| timetable.valid_from.format(DATE_FORMAT).ok());
         let next_timetable = active_timetables
             .1
             .and_then(|valid_date| valid_date.format(DATE_FORMAT).ok());
-        let errored_shifts = Statistics::get_errored_shifts()?;
+        let errored = get_errored_shifts()?;
+        // Counted from the loaded collections, the database doesn't have the variants
+        let errored_shifts = errored.len() as u64;
+        let mut error_types: BTreeMap<String, u64> = BTreeMap::new();
+        for error in errored.iter().flat_map(|shift| &shift.errors) {
+            *error_types.entry(error.kind().to_string()).or_default() += 1;
+        }
         let valid_shifts = get_valid_shifts(date)?.len() as u64;
+        let timetable_breakdown = timetables
+            .iter()
+            .map(|timetable| {
+                let valid_from = timetable.valid_from.format(DATE_FORMAT).unwrap_or_default();
+                let timetable_errors: Vec<&ErroredShift> = errored
+                    .iter()
+                    .filter(|shift| shift.valid_from == valid_from)
+                    .collect();
+                TimetableStatistics {
+                    shifts: timetable.pages.len() as u64,
+                    files: timetable.files.len() as u64,
+                    active: active_timetables
+                        .0
+                        .iter()
+                        .any(|active| active.valid_from == timetable.valid_from),
+                    errored_shifts: timetable_errors.len() as u64,
+                    parse_errors: timetable_errors
+                        .iter()
+                        .map(|shift| shift.errors.len() as u64)
+                        .sum(),
+                    valid_from,
+                }
+            })
+            .collect();
         Ok(Self {
             shifts,
             valid_shifts,
@@ -55,50 +116,393 @@ impl Statistics {
             timetables: timetables.len() as u64,
             active_timetables: active_timetables.0.len() as u64,
             future_timetables: (timetables.len() - active_timetables.0.len()) as u64,
-            recent_timetable: recent_timetable,
-            next_timetable: next_timetable,
-            errored_shifts: errored_shifts,
+            recent_timetable,
+            next_timetable,
+            errored_shifts,
+            error_types,
+            timetable_breakdown,
+            endpoints: endpoint_statistics(),
         })
     }
 
-    fn get_errored_shifts() -> GenResult<Vec<String>> {
-        let mut files: Vec<PathBuf> = vec![];
-        for entry in WalkDir::new("Dienstboek")
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
-            if path.is_file() {
-                files.push(path.to_path_buf());
+    // Prometheus text format. The timetable counters are gauges because they can go down after a
+    // reload, the request counters only go up until a restart
+    fn to_prometheus(&self) -> String {
+        let all = |value: u64| vec![(String::new(), value as f64)];
+        let endpoint = |value: fn(&EndpointStatistics) -> f64| {
+            self.endpoints
+                .iter()
+                .map(|(endpoint, statistics)| {
+                    (format!("{{endpoint=\"{endpoint}\"}}"), value(statistics))
+                })
+                .collect::<Vec<_>>()
+        };
+        let metrics = [
+            (
+                "shifts",
+                "gauge",
+                "Shifts in all timetables",
+                all(self.shifts),
+            ),
+            (
+                "active_shifts",
+                "gauge",
+                "Shifts in the active timetables",
+                all(self.active_shifts),
+            ),
+            (
+                "timetables",
+                "gauge",
+                "Loaded timetables",
+                all(self.timetables),
+            ),
+            (
+                "active_timetables",
+                "gauge",
+                "Timetables valid today",
+                all(self.active_timetables),
+            ),
+            (
+                "errored_shifts",
+                "gauge",
+                "Shifts with at least one parse error",
+                all(self.errored_shifts),
+            ),
+            (
+                "parse_errors",
+                "gauge",
+                "Parse errors of each kind",
+                self.error_types
+                    .iter()
+                    .map(|(kind, count)| (format!("{{kind=\"{kind}\"}}"), *count as f64))
+                    .collect(),
+            ),
+            (
+                "timetable_errored_shifts",
+                "gauge",
+                "Shifts with parse errors per timetable",
+                self.timetable_breakdown
+                    .iter()
+                    .map(|timetable| {
+                        (
+                            format!("{{valid_from=\"{}\"}}", timetable.valid_from),
+                            timetable.errored_shifts as f64,
+                        )
+                    })
+                    .collect(),
+            ),
+            (
+                "requests_total",
+                "counter",
+                "Requests since the process started",
+                endpoint(|statistics| statistics.requests as f64),
+            ),
+            (
+                "request_errors_total",
+                "counter",
+                "Requests answered with an error since the process started",
+                endpoint(|statistics| statistics.errors as f64),
+            ),
+            (
+                "request_p95_ms",
+                "gauge",
+                "95th percentile latency of the recent requests",
+                endpoint(|statistics| statistics.p95_ms),
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, values) in metrics {
+            text.push_str(&format!(
+                "# HELP hermes_{name} {help}\n# TYPE hermes_{name} {kind}\n"
+            ));
+            for (labels, value) in values {
+                text.push_str(&format!("hermes_{name}{labels} {value}\n"));
             }
         }
-        let mut shift = vec![];
-        for file in files {
-            match || -> GenResult<String> {
-                let shift_parse = std::fs::read_to_string(&file)?;
-                let shift: Shift = serde_json::from_str(&shift_parse)?;
-                if shift.parse_error.is_some() {
-                    Ok(file.to_string_lossy().to_string())
-                } else {
-                    Err("no error".into())
-                }
-            }() {
-                Ok(path) => shift.push(path),
-                Err(_) => (),
-            };
+        text
+    }
+
+    // Flatten the counters, per-timetable breakdown and endpoint metrics into "scope,metric,value" rows
+    fn to_csv(&self) -> GenResult<String> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["scope", "metric", "value"])?;
+        let counters = [
+            ("shifts", self.shifts.to_string()),
+            ("valid_shifts", self.valid_shifts.to_string()),
+            ("active_shifts", self.active_shifts.to_string()),
+            ("inactive_shifts", self.inactive_shifts.to_string()),
+            ("timetables", self.timetables.to_string()),
+            ("active_timetables", self.active_timetables.to_string()),
+            ("future_timetables", self.future_timetables.to_string()),
+            (
+                "recent_timetable",
+                self.recent_timetable.clone().unwrap_or_default(),
+            ),
+            (
+                "next_timetable",
+                self.next_timetable.clone().unwrap_or_default(),
+            ),
+            ("errored_shifts", self.errored_shifts.to_string()),
+        ];
+        for (metric, value) in counters {
+            writer.write_record(["all", metric, &value])?;
+        }
+        for (kind, count) in &self.error_types {
+            writer.write_record(["error_type", kind, &count.to_string()])?;
+        }
+        for timetable in &self.timetable_breakdown {
+            writer.write_record([
+                &timetable.valid_from,
+                "shifts",
+                &timetable.shifts.to_string(),
+            ])?;
+            writer.write_record([&timetable.valid_from, "files", &timetable.files.to_string()])?;
+            writer.write_record([
+                &timetable.valid_from,
+                "active",
+                &timetable.active.to_string(),
+            ])?;
+            writer.write_record([
+                &timetable.valid_from,
+                "errored_shifts",
+                &timetable.errored_shifts.to_string(),
+            ])?;
+            writer.write_record([
+                &timetable.valid_from,
+                "parse_errors",
+                &timetable.parse_errors.to_string(),
+            ])?;
+        }
+        for (endpoint, endpoint_statistics) in &self.endpoints {
+            let scope = format!("endpoint:{endpoint}");
+            let endpoint_metrics = [
+                ("requests", endpoint_statistics.requests.to_string()),
+                ("errors", endpoint_statistics.errors.to_string()),
+                ("p50_ms", endpoint_statistics.p50_ms.to_string()),
+                ("p95_ms", endpoint_statistics.p95_ms.to_string()),
+            ];
+            for (metric, value) in endpoint_metrics {
+                writer.write_record([scope.as_str(), metric, &value])?;
+            }
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+}
+
+#[derive(Serialize)]
+pub struct ErroredShift {
+    // The timetable and the key the shift is stored under
+    pub path: String,
+    // The timetable the shift belongs to
+    pub valid_from: String,
+    pub shift_nr: String,
+    pub errors: Vec<ShiftParseError>,
+}
+
+#[derive(Serialize)]
+pub struct ErroredShiftsPage {
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub shifts: Vec<ErroredShift>,
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+const DEFAULT_ERRORS_PER_PAGE: usize = 50;
+// Larger pages are refused, like the roster and coverage ranges
+const MAX_ERRORS_PER_PAGE: usize = 500;
+
+// The parsed shifts with errors of all loaded timetables
+fn get_errored_shifts() -> GenResult<Vec<ErroredShift>> {
+    let mut errored_shifts = vec![];
+    for collection in PdfTimetableCollection::get_timetables()? {
+        let valid_from = collection.valid_from.format(DATE_FORMAT)?;
+        for (key, shift) in &collection.shifts {
+            if let Some(errors) = &shift.parse_error
+                && !errors.is_empty()
+            {
+                errored_shifts.push(ErroredShift {
+                    path: format!("{valid_from}/{key}"),
+                    valid_from: valid_from.clone(),
+                    shift_nr: shift.shift_nr.clone(),
+                    errors: errors.clone(),
+                });
+            }
         }
-        Ok(shift)
     }
+    errored_shifts.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(errored_shifts)
 }
 
-pub fn handle_stats_request(date: Option<Date>) -> HttpResponse {
-    match Statistics::create_statistics(date) {
-        Ok(statistics) => {
-            let json = serde_json::to_string_pretty(&statistics).unwrap();
+#[get("/statistics/errors")]
+async fn errored_shifts_page(query: web::Query<PageQuery>) -> impl Responder {
+    let page = query.page.unwrap_or(0);
+    let per_page = query.per_page.unwrap_or(DEFAULT_ERRORS_PER_PAGE).max(1);
+    if per_page > MAX_ERRORS_PER_PAGE {
+        return HermesError::BadRequest(format!(
+            "A page can contain at most {MAX_ERRORS_PER_PAGE} shifts"
+        ))
+        .error_response();
+    }
+    let Some(skipped) = page.checked_mul(per_page) else {
+        return HermesError::BadRequest(format!("Page {page} is out of range")).error_response();
+    };
+    match get_errored_shifts() {
+        Ok(errored_shifts) => {
+            let errored_page = ErroredShiftsPage {
+                total: errored_shifts.len(),
+                page,
+                per_page,
+                shifts: errored_shifts
+                    .into_iter()
+                    .skip(skipped)
+                    .take(per_page)
+                    .collect(),
+            };
             HttpResponse::Ok()
                 .content_type(ContentType::json())
-                .body(json)
+                .body(serde_json::to_string_pretty(&errored_page).unwrap())
+        }
+        Err(err) => return_error(err.to_string()),
+    }
+}
+
+pub fn record_statistics_snapshot() -> GenResult<()> {
+    let snapshot = StatisticsSnapshot {
+        timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+        statistics: Statistics::create_statistics(None)?,
+    };
+    let mut history_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(tenant::state_path(STATISTICS_HISTORY_PATH))?;
+    writeln!(history_file, "{}", serde_json::to_string(&snapshot)?)?;
+    Ok(())
+}
+
+fn load_statistics_history() -> GenResult<Vec<StatisticsSnapshot>> {
+    let history = match std::fs::read_to_string(tenant::state_path(STATISTICS_HISTORY_PATH)) {
+        Ok(history) => history,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(Box::new(err)),
+    };
+    // Skip lines that can't be read, so one broken write doesn't hide the whole history
+    Ok(history
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct StatisticsQuery {
+    date: Option<String>,
+    // csv for a scope,metric,value table instead of JSON
+    format: Option<String>,
+}
+
+// The same statistics as /shift/stats, which is kept for existing clients
+#[get("/statistics")]
+async fn statistics_page(query: web::Query<StatisticsQuery>) -> impl Responder {
+    match parse_optional_date(&query.date) {
+        Ok(date) => handle_stats_request(date, query.format.as_deref()),
+        Err(err) => err.error_response(),
+    }
+}
+
+#[get("/statistics/history")]
+async fn statistics_history() -> impl Responder {
+    match load_statistics_history() {
+        Ok(history) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::to_string_pretty(&history).unwrap()),
+        Err(err) => return_error(err.to_string()),
+    }
+}
+
+// The cached statistics only cover the timetables, the request metrics are always fresh
+fn cached_statistics(date: Option<Date>) -> GenResult<Statistics> {
+    let cache_key = response_cache::cache_key("statistics", date, "");
+    let cached = response_cache::cached(cache_key, || {
+        Ok(serde_json::to_string(&Statistics::create_statistics(
+            date,
+        )?)?)
+    })?;
+    let mut statistics: Statistics = serde_json::from_str(&cached)?;
+    statistics.endpoints = endpoint_statistics();
+    Ok(statistics)
+}
+
+// The statistics as Prometheus gauges, so alerts can fire on them
+#[get("/metrics")]
+async fn prometheus_metrics() -> impl Responder {
+    match cached_statistics(None) {
+        Ok(statistics) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(statistics.to_prometheus()),
+        Err(err) => return_error(err.to_string()),
+    }
+}
+
+pub fn handle_stats_request(date: Option<Date>, format: Option<&str>) -> HttpResponse {
+    let csv = format.is_some_and(|format| format.eq_ignore_ascii_case("csv"));
+    let response = cached_statistics(date).and_then(|statistics| {
+        if csv {
+            statistics.to_csv()
+        } else {
+            Ok(serde_json::to_string_pretty(&statistics)?)
         }
+    });
+    match response {
+        Ok(body) if csv => HttpResponse::Ok().content_type("text/csv").body(body),
+        Ok(body) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(body),
         Err(err) => return_error(err.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use time::macros::date;
+
+    use super::*;
+    use crate::tenant;
+
+    fn empty_collection(valid_from: Date) -> PdfTimetableCollection {
+        PdfTimetableCollection {
+            valid_from,
+            files: HashMap::new(),
+            pages: HashMap::new(),
+            variants: HashMap::new(),
+            shifts: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn recent_timetable_is_the_newest_active_one() {
+        tenant::with_tenant(tenant::test_tenant("recent_timetable"), || {
+            PdfTimetableCollection::set_timetables(vec![
+                empty_collection(date!(2024 - 06 - 01)),
+                empty_collection(date!(2024 - 01 - 01)),
+                empty_collection(date!(2025 - 01 - 01)),
+                empty_collection(date!(2024 - 03 - 01)),
+            ]);
+            let statistics = Statistics::create_statistics(Some(date!(2024 - 07 - 01))).unwrap();
+            assert_eq!(statistics.recent_timetable.as_deref(), Some("01-06-2024"));
+            assert_eq!(statistics.next_timetable.as_deref(), Some("01-01-2025"));
+            assert_eq!(statistics.active_timetables, 3);
+            assert_eq!(statistics.future_timetables, 1);
+
+            let statistics = Statistics::create_statistics(Some(date!(2024 - 02 - 01))).unwrap();
+            assert_eq!(statistics.recent_timetable.as_deref(), Some("01-01-2024"));
+            assert_eq!(statistics.next_timetable.as_deref(), Some("01-03-2024"));
+        });
+    }
+}
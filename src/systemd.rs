@@ -0,0 +1,53 @@
+use std::{
+    env,
+    net::TcpListener,
+    os::{
+        fd::{FromRawFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+};
+
+use crate::GenResult;
+
+// systemd passes sockets starting at file descriptor 3
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+// Take the listening socket handed over by systemd socket activation, if there is one
+pub fn activated_listener() -> Option<TcpListener> {
+    let listen_pid: u32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    if listen_pid != std::process::id() {
+        return None;
+    }
+    let listen_fds: i32 = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if listen_fds < 1 {
+        return None;
+    }
+    if listen_fds > 1 {
+        warn!("Got {listen_fds} sockets from systemd, only the first one is used");
+    }
+    // Make sure child processes don't think the sockets are meant for them
+    unsafe {
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+    }
+    // SAFETY: systemd guarantees this descriptor is an open socket owned by this process
+    let listener = unsafe { TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    Some(listener)
+}
+
+// Tell systemd the service state, does nothing when not started by systemd
+pub fn notify(state: &str) -> GenResult<()> {
+    let socket_path = match env::var("NOTIFY_SOCKET") {
+        Ok(path) => path,
+        Err(_) => return Ok(()),
+    };
+    // Paths starting with @ are in the abstract namespace
+    let address = match socket_path.strip_prefix('@') {
+        Some(abstract_name) => SocketAddr::from_abstract_name(abstract_name)?,
+        None => SocketAddr::from_pathname(&socket_path)?,
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
@@ -0,0 +1,175 @@
+// Every operator (tenant) has its own source folder, collection and state files.
+// The tenant of the current request or job is kept in a task local, code that doesn't run
+// inside one (startup, background threads) uses the default tenant.
+use std::{future::Future, path::Path, pin::Pin};
+
+use actix_web::{
+    Error, ResponseError,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, Config},
+    error::HermesError,
+};
+
+pub const DEFAULT_TENANT_NAME: &str = "default";
+
+tokio::task_local! {
+    static CURRENT_TENANT: Tenant;
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub book_path: String,
+    pub collection_path: String,
+    // Overrides the global prefix equivalences for this operator
+    pub prefix_equivalences: Option<Vec<Vec<String>>>,
+    // More source folders, each with the duty books of one depot
+    #[serde(default)]
+    pub depots: Vec<Depot>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Depot {
+    pub name: String,
+    pub path: String,
+}
+
+impl Tenant {
+    // Runs with the prefix equivalences of this operator, or the global ones
+    fn with_prefix_equivalences<R>(&self, function: impl FnOnce(&[Vec<String>]) -> R) -> R {
+        match &self.prefix_equivalences {
+            Some(prefix_equivalences) => function(prefix_equivalences),
+            None => function(&Config::get().prefix_equivalences),
+        }
+    }
+
+    pub fn prefixes_equivalent(&self, requested: &str, actual: &str) -> bool {
+        self.with_prefix_equivalences(|equivalences| {
+            config::prefixes_equivalent(equivalences, requested, actual)
+        })
+    }
+
+    pub fn canonical_prefix(&self, prefix: &str) -> String {
+        self.with_prefix_equivalences(|equivalences| config::canonical_prefix(equivalences, prefix))
+    }
+
+    // The book folder followed by the depot folders
+    pub fn source_paths(&self) -> Vec<String> {
+        std::iter::once(self.book_path.clone())
+            .chain(self.depots.iter().map(|depot| depot.path.clone()))
+            .collect()
+    }
+
+    // The depot of a source PDF, None for PDFs in the book folder
+    pub fn depot_of(&self, pdf_path: &Path) -> Option<String> {
+        self.depots
+            .iter()
+            .find(|depot| pdf_path.starts_with(&depot.path))
+            .map(|depot| depot.name.clone())
+    }
+}
+
+fn default_tenant() -> Tenant {
+    let config = Config::get();
+    Tenant {
+        name: DEFAULT_TENANT_NAME.to_string(),
+        book_path: config.book_path.clone(),
+        collection_path: config.collection_path.clone(),
+        prefix_equivalences: None,
+        depots: config.depots.clone(),
+    }
+}
+
+// The default tenant followed by the configured operators
+pub fn all() -> Vec<Tenant> {
+    let mut tenants = vec![default_tenant()];
+    tenants.extend(Config::get().tenants.iter().cloned());
+    tenants
+}
+
+// An operator with its own loaded collections, so tests don't share them
+#[cfg(test)]
+pub fn test_tenant(name: &str) -> Tenant {
+    Tenant {
+        name: name.to_string(),
+        book_path: format!("Dienstboek_{name}"),
+        collection_path: format!("collection_{name}"),
+        prefix_equivalences: None,
+        depots: vec![],
+    }
+}
+
+pub fn find(name: &str) -> Option<Tenant> {
+    Config::get()
+        .tenants
+        .iter()
+        .find(|tenant| tenant.name == name)
+        .cloned()
+}
+
+pub fn current() -> Tenant {
+    CURRENT_TENANT
+        .try_with(|tenant| tenant.clone())
+        .unwrap_or_else(|_| default_tenant())
+}
+
+pub fn book_path() -> String {
+    current().book_path
+}
+
+pub fn collection_path() -> String {
+    current().collection_path
+}
+
+// State files of the default tenant keep their name, others get the tenant name as prefix
+pub fn state_path(file_name: &str) -> String {
+    let tenant = current();
+    let file_name = if tenant.name == DEFAULT_TENANT_NAME {
+        file_name.to_string()
+    } else {
+        format!("{}_{file_name}", tenant.name)
+    };
+    Path::new(&Config::get().state_path)
+        .join(file_name)
+        .to_string_lossy()
+        .to_string()
+}
+
+// Run blocking code for a tenant
+pub fn with_tenant<R>(tenant: Tenant, function: impl FnOnce() -> R) -> R {
+    CURRENT_TENANT.sync_scope(tenant, function)
+}
+
+// Run a future for a tenant
+pub async fn in_tenant<F: Future>(tenant: Tenant, future: F) -> F::Output {
+    CURRENT_TENANT.scope(tenant, future).await
+}
+
+type ScopedResponse = Pin<Box<dyn Future<Output = Result<ServiceResponse<BoxBody>, Error>>>>;
+
+// Middleware for the /op/{operator} scope, runs the request as that operator
+pub fn scope_request<S, B>(request: ServiceRequest, service: &S) -> ScopedResponse
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let operator = request.match_info().get("operator").unwrap_or_default();
+    let Some(tenant) = find(operator) else {
+        let operator = operator.to_string();
+        return Box::pin(async move {
+            Ok(request.into_response(HermesError::UnknownOperator(operator).error_response()))
+        });
+    };
+    let response = with_tenant(tenant.clone(), || service.call(request));
+    Box::pin(in_tenant(tenant, async move {
+        response
+            .await
+            .map(|response| response.map_into_boxed_body())
+    }))
+}
@@ -0,0 +1,164 @@
+use std::{fs, path::Path};
+
+use actix_web::{
+    HttpResponse, Responder, ResponseError, get,
+    http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    web,
+};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    DATE_FORMAT, GenResult, collection::PdfTimetableCollection, error::HermesError, load_shift,
+    parse_date, parse_optional_date, response_cache, tenant,
+};
+
+#[derive(Deserialize)]
+struct TimetablesQuery {
+    // Decide which timetables are active on this date instead of today
+    date: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum TimetableStatus {
+    Active,
+    Future,
+}
+
+#[derive(Serialize)]
+struct TimetableSummary {
+    valid_from: String,
+    status: TimetableStatus,
+    shifts: usize,
+    // File names of the source PDFs, sorted
+    source_files: Vec<String>,
+    // Shifts with at least one parse error, and the number of errors over all of them
+    errored_shifts: usize,
+    parse_errors: usize,
+}
+
+fn summarize(collection: &PdfTimetableCollection, date: Date) -> TimetableSummary {
+    let mut source_files: Vec<String> = collection
+        .files
+        .values()
+        .map(|file| {
+            Path::new(file)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(file.clone())
+        })
+        .collect();
+    source_files.sort();
+    let mut errored_shifts = 0;
+    let mut parse_errors = 0;
+    for shift_number in collection.pages.keys() {
+        // A shift without its JSON can't be checked, it is counted in the shifts anyway
+        let Ok(shift) = load_shift(shift_number.clone(), collection.valid_from) else {
+            continue;
+        };
+        if let Some(errors) = shift.parse_error
+            && !errors.is_empty()
+        {
+            errored_shifts += 1;
+            parse_errors += errors.len();
+        }
+    }
+    TimetableSummary {
+        valid_from: collection
+            .valid_from
+            .format(DATE_FORMAT)
+            .unwrap_or_default(),
+        status: if collection.valid_from <= date {
+            TimetableStatus::Active
+        } else {
+            TimetableStatus::Future
+        },
+        shifts: collection.pages.len(),
+        source_files,
+        errored_shifts,
+        parse_errors,
+    }
+}
+
+fn timetables_json(date: Option<Date>) -> GenResult<String> {
+    let current_date = date.unwrap_or(OffsetDateTime::now_utc().date());
+    let summaries: Vec<TimetableSummary> = PdfTimetableCollection::get_timetables()?
+        .iter()
+        .map(|collection| summarize(collection, current_date))
+        .collect();
+    Ok(serde_json::to_string_pretty(&summaries)?)
+}
+
+// Every loaded timetable, oldest first
+#[get("/timetables")]
+async fn timetables(query: web::Query<TimetablesQuery>) -> impl Responder {
+    match parse_optional_date(&query.date) {
+        Ok(date) => timetables_response(date).await,
+        Err(err) => err.error_response(),
+    }
+}
+
+pub async fn timetables_response(date: Option<Date>) -> HttpResponse {
+    // Reading every shift for the error counts is slow, so the result is kept until the next reload
+    let tenant = tenant::current();
+    let timetables_json = web::block(move || {
+        tenant::with_tenant(tenant, || {
+            response_cache::cached(response_cache::cache_key("timetables", date, ""), || {
+                timetables_json(date)
+            })
+        })
+        .map_err(|err| err.to_string())
+    })
+    .await;
+    match timetables_json {
+        Ok(Ok(json)) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(json),
+        Ok(Err(err)) => {
+            HermesError::Internal(format!("Listing the timetables failed: {err}")).error_response()
+        }
+        Err(err) => HermesError::Internal(err.to_string()).error_response(),
+    }
+}
+
+// The original Dienstboek a timetable was indexed from, to check the extraction against it
+#[get("/timetable/{valid_from}/source/{file_id}")]
+async fn timetable_source(path: web::Path<(String, usize)>) -> impl Responder {
+    let (valid_from_string, file_id) = path.into_inner();
+    let Some(valid_from) = parse_date(&valid_from_string) else {
+        return HermesError::DateParse(valid_from_string).error_response();
+    };
+    let collection = match PdfTimetableCollection::get_timetables() {
+        Ok(collections) => collections
+            .into_iter()
+            .find(|collection| collection.valid_from == valid_from),
+        Err(err) => return HermesError::Internal(err.to_string()).error_response(),
+    };
+    let Some(collection) = collection else {
+        return HermesError::TimetableNotFound(valid_from_string).error_response();
+    };
+    let Some(source_path) = collection.files.get(&file_id).cloned() else {
+        return HermesError::SourceNotFound {
+            timetable: valid_from_string,
+            file_id,
+        }
+        .error_response();
+    };
+    let file_name = Path::new(&source_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("{valid_from_string}-{file_id}.pdf"));
+    let source = web::block(move || fs::read(&source_path).map_err(|err| err.to_string())).await;
+    match source {
+        Ok(Ok(bytes)) => HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Inline,
+                parameters: vec![DispositionParam::Filename(file_name)],
+            })
+            .body(bytes),
+        Ok(Err(err)) => HermesError::PdfLoad(err).error_response(),
+        Err(err) => HermesError::Internal(err.to_string()).error_response(),
+    }
+}
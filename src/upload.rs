@@ -0,0 +1,212 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use actix_multipart::Multipart;
+use actix_web::{HttpResponse, Responder, ResponseError, http::header::ContentType, post, web};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    DATE_FORMAT, GenResult,
+    admin::FileError,
+    config::{ClusterRole, Config},
+    error::HermesError,
+    parse_date, parse_trip_sheet_in_memory, refresh, tenant,
+};
+
+// Larger files are refused, timetable books are far smaller
+const MAX_UPLOAD_BYTES: usize = 200 * 1024 * 1024;
+
+// Numbers the staging files, so uploads of files with the same name don't overwrite each other
+static UPLOAD_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Deserialize)]
+struct UploadQuery {
+    // Store the files in a subfolder named after this valid from date
+    timetable: Option<String>,
+    // Replace files that already exist, otherwise they are refused
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Serialize)]
+struct UploadReport {
+    stored: Vec<String>,
+    // The stored files that replaced an existing file
+    replaced: Vec<String>,
+    rejected: Vec<FileError>,
+    refresh_started: bool,
+}
+
+// Only keep the name of the file, so uploads can't write outside the source folder
+fn safe_file_name(file_name: &str) -> Option<String> {
+    let name = Path::new(file_name)
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    let is_pdf = Path::new(&name)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"));
+    is_pdf.then_some(name)
+}
+
+// The upload is parsed outside of the source folder, so a refresh never sees a file that doesn't parse.
+// The staging file is removed whether storing worked or not
+fn store_upload(file_name: &str, bytes: &[u8], folder: &Path) -> GenResult<String> {
+    // The process id keeps instances sharing the state folder apart
+    let staging_name = format!(
+        "upload_{}_{}_{file_name}",
+        process::id(),
+        UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let staging_path = PathBuf::from(tenant::state_path(&staging_name));
+    let stored = fs::write(&staging_path, bytes)
+        .map_err(|err| err.into())
+        .and_then(|_| move_upload(&staging_path, file_name, folder));
+    // After a rename there is nothing left to remove
+    if let Err(err) = fs::remove_file(&staging_path)
+        && err.kind() != io::ErrorKind::NotFound
+    {
+        warn!("Failed to remove staged upload {staging_path:?}: {err}");
+    }
+    stored
+}
+
+fn move_upload(staging_path: &PathBuf, file_name: &str, folder: &Path) -> GenResult<String> {
+    parse_trip_sheet_in_memory(staging_path, 0)?;
+    fs::create_dir_all(folder)?;
+    let destination = folder.join(file_name);
+    // The state folder can be on another file system, then the file has to be copied
+    if fs::rename(staging_path, &destination).is_err()
+        && let Err(err) = fs::copy(staging_path, &destination)
+    {
+        // Don't leave a partial copy for the refresh to find
+        let _ = fs::remove_file(&destination);
+        return Err(err.into());
+    }
+    Ok(destination.to_string_lossy().to_string())
+}
+
+#[post("/upload")]
+async fn upload(query: web::Query<UploadQuery>, mut payload: Multipart) -> impl Responder {
+    if Config::get().role == ClusterRole::Replica {
+        return HermesError::ReadOnlyReplica.error_response();
+    }
+    let mut folder = PathBuf::from(tenant::book_path());
+    if let Some(timetable) = &query.timetable {
+        match parse_date(timetable).and_then(|date| date.format(DATE_FORMAT).ok()) {
+            Some(timetable) => folder.push(timetable),
+            None => return HermesError::DateParse(timetable.clone()).error_response(),
+        }
+    }
+    let mut report = UploadReport {
+        stored: vec![],
+        replaced: vec![],
+        rejected: vec![],
+        refresh_started: false,
+    };
+    // Files refused because they already exist, when nothing else went wrong that is a conflict
+    let mut existing_files = 0;
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            // The files before the broken part are stored already, so they are still reported
+            Err(err) if !report.stored.is_empty() => {
+                report.rejected.push(FileError {
+                    file: String::new(),
+                    error: err.to_string(),
+                });
+                break;
+            }
+            Err(err) => return HermesError::BadRequest(err.to_string()).error_response(),
+        };
+        let Some(file_name) = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .map(|file_name| file_name.to_string())
+        else {
+            continue;
+        };
+        let Some(safe_name) = safe_file_name(&file_name) else {
+            report.rejected.push(FileError {
+                file: file_name,
+                error: "Only PDF files can be uploaded".to_string(),
+            });
+            continue;
+        };
+        let replaces_file = folder.join(&safe_name).exists();
+        if replaces_file && !query.overwrite {
+            report.rejected.push(FileError {
+                file: safe_name,
+                error: "Already exists, upload with overwrite=true to replace it".to_string(),
+            });
+            existing_files += 1;
+            continue;
+        }
+        let mut bytes = vec![];
+        let mut read_error = None;
+        while let Some(chunk) = field.next().await {
+            match chunk {
+                Ok(chunk) => bytes.extend_from_slice(&chunk),
+                Err(err) => {
+                    read_error = Some(err.to_string());
+                    break;
+                }
+            }
+            if bytes.len() > MAX_UPLOAD_BYTES {
+                read_error = Some(format!("Larger than {} MB", MAX_UPLOAD_BYTES / 1024 / 1024));
+                break;
+            }
+        }
+        // Only this file is refused, the files before it are still stored and indexed
+        if let Some(error) = read_error {
+            report.rejected.push(FileError {
+                file: safe_name,
+                error,
+            });
+            continue;
+        }
+        // Parsing takes a while, so don't do it on the worker thread
+        let tenant = tenant::current();
+        let destination_folder = folder.clone();
+        let stored_name = safe_name.clone();
+        let stored = web::block(move || {
+            tenant::with_tenant(tenant, || {
+                store_upload(&stored_name, &bytes, &destination_folder)
+                    .map_err(|err| err.to_string())
+            })
+        })
+        .await;
+        match stored {
+            Ok(Ok(path)) => {
+                if replaces_file {
+                    report.replaced.push(path.clone());
+                }
+                report.stored.push(path)
+            }
+            Ok(Err(err)) => report.rejected.push(FileError {
+                file: safe_name,
+                error: err,
+            }),
+            Err(err) => return HermesError::Internal(err.to_string()).error_response(),
+        }
+    }
+    if !report.stored.is_empty() {
+        // Only the new files are parsed. A running refresh can miss them, so it runs again
+        report.refresh_started = refresh::queue_refresh();
+    }
+    let mut response = if !report.stored.is_empty() {
+        HttpResponse::Ok()
+    } else if existing_files > 0 && existing_files == report.rejected.len() {
+        HttpResponse::Conflict()
+    } else {
+        HttpResponse::BadRequest()
+    };
+    response
+        .content_type(ContentType::json())
+        .body(serde_json::to_string_pretty(&report).unwrap())
+}
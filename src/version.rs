@@ -0,0 +1,51 @@
+use actix_web::{HttpResponse, Responder, get, http::header::ContentType};
+use serde::Serialize;
+
+use crate::parsing::shift_parsing::PARSER_VERSION;
+
+#[derive(Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub build_timestamp: u64,
+    pub pdf_backend: &'static str,
+    pub features: Vec<&'static str>,
+    pub parser_version: u32,
+}
+
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = vec![];
+    if cfg!(debug_assertions) {
+        features.push("debug");
+    }
+    if cfg!(feature = "redis") {
+        features.push("redis");
+    }
+    if cfg!(feature = "sqlite") {
+        features.push("sqlite");
+    }
+    if cfg!(feature = "preview") {
+        features.push("preview");
+    }
+    features
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("GIT_COMMIT"),
+            build_timestamp: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+            pdf_backend: "qpdf",
+            features: enabled_features(),
+            parser_version: PARSER_VERSION,
+        }
+    }
+}
+
+#[get("/version")]
+async fn get_version() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(serde_json::to_string_pretty(&VersionInfo::current()).unwrap())
+}
@@ -0,0 +1,135 @@
+// Pages for browsers, so drivers without a PDF viewer or the app can still read their shift
+use actix_web::{
+    HttpResponse, Responder, get,
+    http::header::{ContentType, LOCATION},
+    web,
+};
+use askama::Template;
+use serde::Deserialize;
+
+use crate::{
+    GenResult,
+    parsing::shift_structs::{JobType, Shift},
+    render::{JOB_HEADER, TIME_FORMAT, job_row, shift_metadata},
+    return_error,
+};
+
+#[derive(Template)]
+#[template(path = "index.html")]
+struct LandingPage;
+
+#[derive(Deserialize)]
+struct LandingQuery {
+    shift: Option<String>,
+    date: Option<String>,
+}
+
+struct JobRow {
+    columns: [String; 5],
+    is_break: bool,
+}
+
+#[derive(Template)]
+#[template(path = "shift.html")]
+struct ShiftPage<'a> {
+    shift_nr: &'a str,
+    // Links are relative, so they also work for the operators served under /op/{name}/
+    home: &'a str,
+    shift_file: &'a str,
+    date_query: String,
+    notice: Option<&'a str>,
+    metadata: Vec<(&'static str, String)>,
+    header: [&'static str; 5],
+    rows: Vec<JobRow>,
+}
+
+// Dates only contain digits and dashes, so nothing else is copied into a link
+fn date_query(date: &str) -> String {
+    let date: String = date
+        .chars()
+        .filter(|character| character.is_ascii_digit() || *character == '-')
+        .collect();
+    if date.is_empty() {
+        String::new()
+    } else {
+        format!("?date={date}")
+    }
+}
+
+fn format_minutes(minutes: i64) -> String {
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+// The shift as a table of its jobs, with the breaks highlighted.
+// The notice explains why the variant doesn't run on the requested date
+pub fn shift_to_html(
+    shift: &Shift,
+    shift_file: &str,
+    date: Option<&str>,
+    notice: Option<&str>,
+) -> GenResult<String> {
+    let mut metadata = shift_metadata(shift);
+    let times = &shift.times;
+    let format_time = |time: Option<time::Time>| {
+        time.and_then(|time| time.format(TIME_FORMAT).ok())
+            .unwrap_or_default()
+    };
+    if times.start_time.is_some() {
+        metadata.push(("Start", format_time(times.start_time)));
+    }
+    if times.end_time.is_some() {
+        metadata.push(("End", format_time(times.end_time)));
+    }
+    if let Some(duration_minutes) = times.duration_minutes {
+        metadata.push(("Duration", format_minutes(duration_minutes)));
+    }
+    metadata.push(("Driving", format_minutes(times.driving_minutes)));
+    metadata.push(("Breaks", format_minutes(times.break_minutes)));
+    if let Some(paid_minutes) = times.paid_minutes {
+        metadata.push(("Paid", format_minutes(paid_minutes)));
+    }
+    let rows = shift
+        .job
+        .iter()
+        .filter(|job| !job.empty())
+        .map(|job| JobRow {
+            columns: job_row(job),
+            is_break: matches!(job.job_type, JobType::Pauze | JobType::Onderbreking),
+        })
+        .collect();
+    let page = ShiftPage {
+        shift_nr: &shift.shift_nr,
+        home: "../",
+        shift_file,
+        date_query: date.map(date_query).unwrap_or_default(),
+        notice,
+        metadata,
+        header: JOB_HEADER,
+        rows,
+    };
+    Ok(page.render()?)
+}
+
+// The search box sends the shift back here, which forwards it to the page of the shift
+#[get("/")]
+async fn landing_page(query: web::Query<LandingQuery>) -> impl Responder {
+    if let Some(shift) = query.shift.as_deref().map(str::trim)
+        && !shift.is_empty()
+    {
+        let shift = shift
+            .chars()
+            .filter(|character| character.is_alphanumeric())
+            .collect::<String>()
+            .to_uppercase();
+        let date_query = query.date.as_deref().map(date_query).unwrap_or_default();
+        return HttpResponse::SeeOther()
+            .insert_header((LOCATION, format!("shift/{shift}.html{date_query}")))
+            .finish();
+    }
+    match LandingPage.render() {
+        Ok(html) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(html),
+        Err(err) => return_error(err.to_string()),
+    }
+}
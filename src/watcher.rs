@@ -0,0 +1,76 @@
+// Watch the source PDF folders and refresh automatically when PDFs are added, changed or removed
+use std::{
+    path::Path,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use notify_debouncer_mini::{
+    DebounceEventResult, Debouncer, new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+};
+
+use crate::{config::Config, refresh, tenant};
+
+// The watchers stop when they are dropped, so they are kept here for as long as the process runs
+static WATCHERS: LazyLock<Mutex<Vec<Debouncer<RecommendedWatcher>>>> =
+    LazyLock::new(|| Mutex::new(vec![]));
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+// Runs on the thread of the watcher, so waiting for a running refresh doesn't block anything else
+fn handle_events(tenant: &tenant::Tenant, debounce: Duration, result: DebounceEventResult) {
+    let events = match result {
+        Ok(events) => events,
+        Err(err) => {
+            error!("Watching the PDFs of {} failed: {err}", tenant.name);
+            return;
+        }
+    };
+    if !events.iter().any(|event| is_pdf(&event.path)) {
+        return;
+    }
+    info!("PDFs of {} changed, refreshing", tenant.name);
+    // A refresh that is already running might have missed the change, so wait for it and start another
+    while !tenant::with_tenant(tenant.clone(), || refresh::start_refresh(false)) {
+        std::thread::sleep(debounce);
+    }
+}
+
+fn watch_folder(tenant: &tenant::Tenant, book_path: String, debounce: Duration) {
+    let watched_tenant = tenant.clone();
+    let debouncer = new_debouncer(debounce, move |result| {
+        handle_events(&watched_tenant, debounce, result)
+    })
+    .and_then(|mut debouncer| {
+        debouncer
+            .watcher()
+            .watch(Path::new(&book_path), RecursiveMode::Recursive)?;
+        Ok(debouncer)
+    });
+    match (debouncer, WATCHERS.lock()) {
+        (Ok(debouncer), Ok(mut watchers)) => {
+            info!("Watching {book_path} for changed PDFs");
+            watchers.push(debouncer);
+        }
+        (Err(err), _) => error!("Could not watch {book_path}: {err}"),
+        (_, Err(err)) => error!("Could not keep the watcher of {book_path}: {err}"),
+    }
+}
+
+// Start watching the source folders of every operator, including the folders of their depots
+pub fn watch_book_paths() {
+    let config = Config::get();
+    if !config.watch_book_path {
+        return;
+    }
+    let debounce = Duration::from_secs(config.watch_debounce_seconds.max(1));
+    for tenant in tenant::all() {
+        for book_path in tenant.source_paths() {
+            watch_folder(&tenant, book_path, debounce);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+// Tell other services that a refresh finished, so they don't have to poll the statistics
+use std::time::Duration;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{
+    DATE_FORMAT, IndexSummary, admin::FileError, collection::PdfTimetableCollection,
+    config::Config, tenant,
+};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct RefreshCompleted<'a> {
+    event: &'static str,
+    tenant: String,
+    // Unix timestamp
+    finished: i64,
+    // The newest collection, None if no timetable was found
+    valid_from: Option<String>,
+    shifts: usize,
+    files_indexed: usize,
+    shifts_parsed: usize,
+    // Shifts that were parsed with errors and files that couldn't be indexed at all
+    errored_shifts: &'a [String],
+    file_errors: &'a [FileError],
+}
+
+// POST the result of a refresh to every configured webhook.
+// A webhook that fails is logged, it doesn't fail the refresh
+pub fn refresh_completed(summary: &IndexSummary) {
+    let webhook_urls = Config::get().webhook_urls.clone();
+    if webhook_urls.is_empty() {
+        return;
+    }
+    let newest = PdfTimetableCollection::get_timetables()
+        .unwrap_or_default()
+        .into_iter()
+        .max_by_key(|collection| collection.valid_from);
+    let payload = RefreshCompleted {
+        event: "refresh_completed",
+        tenant: tenant::current().name,
+        finished: OffsetDateTime::now_utc().unix_timestamp(),
+        valid_from: newest
+            .as_ref()
+            .and_then(|collection| collection.valid_from.format(DATE_FORMAT).ok()),
+        shifts: newest
+            .as_ref()
+            .map(|collection| collection.pages.len())
+            .unwrap_or_default(),
+        files_indexed: summary.files_indexed,
+        shifts_parsed: summary.shifts_parsed,
+        errored_shifts: &summary.errored_shifts,
+        file_errors: &summary.file_errors,
+    };
+    let agent = ureq::AgentBuilder::new().timeout(WEBHOOK_TIMEOUT).build();
+    for webhook_url in webhook_urls {
+        match agent.post(&webhook_url).send_json(&payload) {
+            Ok(_) => info!("Notified webhook {webhook_url} of the refresh"),
+            Err(err) => warn!("Webhook {webhook_url} failed: {err}"),
+        }
+    }
+}